@@ -0,0 +1,85 @@
+//! How bots decide what to input each tick.
+
+use std::str::FromStr;
+
+use bevy::prelude::*;
+use naia_bevy_client::Random;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Behaviour {
+    /// Stands still, only keeping the connection alive.
+    Idle,
+    /// Walks in a random direction, picking a new one every few seconds, and attacks at random,
+    /// though never in two ticks in a row as the server takes that for a cheat.
+    RandomWalk,
+    /// Walks in a circle, for repeatable runs.
+    Circle,
+}
+
+impl FromStr for Behaviour {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "idle" => Ok(Behaviour::Idle),
+            "random-walk" => Ok(Behaviour::RandomWalk),
+            "circle" => Ok(Behaviour::Circle),
+            _ => Err(format!(
+                "unknown behaviour `{s}`, expected `idle`, `random-walk` or `circle`"
+            )),
+        }
+    }
+}
+
+/// The per-bot state a [`Behaviour`] needs between ticks.
+#[derive(Resource)]
+pub struct BehaviourState {
+    pub behaviour: Behaviour,
+    heading: Vec2,
+    change_timer: Timer,
+    elapsed_secs: f32,
+    /// Whether the bot attacked in the previous tick.
+    attacked: bool,
+}
+
+impl BehaviourState {
+    pub fn new(behaviour: Behaviour) -> Self {
+        Self {
+            behaviour,
+            heading: Vec2::ZERO,
+            change_timer: Timer::from_seconds(0.0, TimerMode::Once),
+            elapsed_secs: 0.0,
+            attacked: false,
+        }
+    }
+
+    /// Advances the behaviour by one tick of `dt` seconds, returning the axes to move along and
+    /// whether to attack.
+    pub fn next_input(&mut self, dt: f32) -> (Vec2, bool) {
+        self.elapsed_secs += dt;
+
+        match self.behaviour {
+            Behaviour::Idle => (Vec2::ZERO, false),
+            Behaviour::RandomWalk => {
+                self.change_timer
+                    .tick(std::time::Duration::from_secs_f32(dt));
+
+                if self.change_timer.finished() {
+                    let angle = Random::gen_range_f32(0.0, std::f32::consts::TAU);
+                    self.heading = Vec2::new(angle.cos(), angle.sin());
+                    self.change_timer =
+                        Timer::from_seconds(Random::gen_range_f32(1.0, 4.0), TimerMode::Once);
+                }
+
+                let attack = !self.attacked && Random::gen_range_f32(0.0, 1.0) < 0.02;
+                self.attacked = attack;
+
+                (self.heading, attack)
+            }
+            Behaviour::Circle => {
+                let angle = self.elapsed_secs * 0.5;
+                (Vec2::new(angle.cos(), angle.sin()), false)
+            }
+        }
+    }
+}
@@ -0,0 +1,234 @@
+//! Headless clients which connect to a server and play on their own, for load and soak testing.
+//!
+//! Each bot runs its own Bevy [`App`] on its own thread, without any rendering, and logs its round
+//! trip time and bandwidth every few seconds.
+
+use std::{thread, time::Duration};
+
+use bevy::{app::ScheduleRunnerSettings, log::LogPlugin, prelude::*};
+use clap::Parser;
+use naia_bevy_client::{
+    events::{
+        ClientTickEvent, ConnectEvent, DespawnEntityEvent, DisconnectEvent, MessageEvents,
+        RejectEvent,
+    },
+    transport::webrtc,
+    Client, ClientConfig, Plugin as ClientPlugin, ReceiveEvents,
+};
+
+use shared::{
+    channels::{GameMessageChannel, PlayerActionChannel, PlayerInputChannel},
+    components::{MatchPhase, MatchState, PlayerInfo},
+    messages::{Auth, EntityAssignment, PlayerInput, ReadyUp},
+    protocol, TICK_INTERVAL,
+};
+
+use behaviour::{Behaviour, BehaviourState};
+
+mod behaviour;
+
+#[derive(Clone, Parser, Resource)]
+pub struct Args {
+    /// The server's signaling address, e.g. `http://127.0.0.1:2000`.
+    #[arg(short, long)]
+    addr: String,
+    #[arg(short, long)]
+    password: Option<String>,
+    /// The room to join. The server picks one when omitted.
+    #[arg(short, long)]
+    room: Option<String>,
+
+    /// How many bots to run.
+    #[arg(short, long, default_value_t = 1)]
+    count: u16,
+    /// Bots are named `<prefix>-<n>`.
+    #[arg(long, default_value = "bot")]
+    name_prefix: String,
+    /// `idle`, `random-walk` or `circle`.
+    #[arg(short, long, default_value = "random-walk")]
+    behaviour: Behaviour,
+    /// How often each bot logs its network statistics, in seconds.
+    #[arg(long, default_value_t = 5)]
+    report_secs: u64,
+}
+
+/// Who this bot is and what it controls.
+#[derive(Resource)]
+struct BotState {
+    name: String,
+    avatar: Option<Entity>,
+    /// Whether a [`ReadyUp`] has been sent since the room last entered the lobby.
+    ready_requested: bool,
+    report_timer: Timer,
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let handles = (0..args.count)
+        .map(|index| {
+            let args = args.clone();
+            // Stagger connections so the server is not hit by every handshake at once
+            thread::sleep(Duration::from_millis(50));
+            thread::spawn(move || run_bot(args, index))
+        })
+        .collect::<Vec<_>>();
+
+    for handle in handles {
+        let _ = handle.join();
+    }
+}
+
+fn run_bot(args: Args, index: u16) {
+    let name = format!("{}-{index:04}", args.name_prefix);
+
+    let mut app = App::default();
+    app.add_plugins(MinimalPlugins);
+
+    // Only one global logger may be installed per process
+    if index == 0 {
+        app.add_plugin(LogPlugin::default());
+    }
+
+    let mut config = ClientConfig::default();
+    config.connection.bandwidth_measure_duration = Some(Duration::from_secs(1));
+
+    app.add_plugin(ClientPlugin::new(config, protocol()))
+        .insert_resource(ScheduleRunnerSettings::run_loop(Duration::from_millis(5)))
+        .insert_resource(BehaviourState::new(args.behaviour))
+        .insert_resource(BotState {
+            name,
+            avatar: None,
+            ready_requested: false,
+            report_timer: Timer::from_seconds(args.report_secs as f32, TimerMode::Repeating),
+        })
+        .insert_resource(args)
+        .add_startup_system(connect)
+        .add_systems(
+            (
+                connect_events,
+                reject_events,
+                disconnect_events,
+                handle_entity_assignment,
+                despawn_events,
+            )
+                .chain()
+                .in_set(ReceiveEvents),
+        )
+        .add_system(tick_events.after(ReceiveEvents))
+        .add_system(ready_up)
+        .add_system(report)
+        .run();
+}
+
+fn connect(args: Res<Args>, bot: Res<BotState>, mut client: Client) {
+    client.auth(Auth {
+        name: bot.name.clone(),
+        channel_password: args.password.clone().unwrap_or_default(),
+        room: args.room.clone(),
+        spectate: false,
+    });
+
+    let socket = webrtc::Socket::new(&args.addr, client.socket_config());
+    client.connect(socket);
+}
+
+fn connect_events(mut event_reader: EventReader<ConnectEvent>, bot: Res<BotState>) {
+    for _ in event_reader.iter() {
+        info!("{} connected", bot.name);
+    }
+}
+
+fn reject_events(mut event_reader: EventReader<RejectEvent>, bot: Res<BotState>) {
+    for _ in event_reader.iter() {
+        warn!("{} was rejected by the server", bot.name);
+    }
+}
+
+fn disconnect_events(mut event_reader: EventReader<DisconnectEvent>, bot: Res<BotState>) {
+    for _ in event_reader.iter() {
+        warn!("{} disconnected", bot.name);
+    }
+}
+
+fn handle_entity_assignment(
+    mut event_reader: EventReader<MessageEvents>,
+    mut bot: ResMut<BotState>,
+    client: Client,
+) {
+    for events in event_reader.iter() {
+        for message in events.read::<GameMessageChannel, EntityAssignment>() {
+            let entity = message.entity.get(&client);
+
+            if message.assign {
+                bot.avatar = entity;
+            } else if bot.avatar == entity {
+                bot.avatar = None;
+            }
+        }
+    }
+}
+
+/// Forgets the bot's avatar once the server despawns it, e.g. when it is killed.
+fn despawn_events(mut event_reader: EventReader<DespawnEntityEvent>, mut bot: ResMut<BotState>) {
+    for DespawnEntityEvent(entity) in event_reader.iter() {
+        if bot.avatar == Some(*entity) {
+            bot.avatar = None;
+        }
+    }
+}
+
+/// Sends one input per tick for the bot's avatar, if it has one.
+fn tick_events(
+    mut event_reader: EventReader<ClientTickEvent>,
+    mut behaviour: ResMut<BehaviourState>,
+    bot: Res<BotState>,
+    mut client: Client,
+) {
+    for ClientTickEvent(tick) in event_reader.iter() {
+        let Some(avatar) = bot.avatar else { continue; };
+
+        let (axes, attack) = behaviour.next_input(TICK_INTERVAL.as_secs_f32());
+        let mut input = PlayerInput::from_axes(axes.x, axes.y).with_attack(attack);
+        input.entity.set(&client, &avatar);
+
+        client.send_tick_buffer_message::<PlayerInputChannel, PlayerInput>(tick, &input);
+    }
+}
+
+/// Readies up whenever the bot is waiting in the lobby and is not yet ready.
+fn ready_up(
+    match_query: Query<&MatchState>,
+    info_query: Query<&PlayerInfo>,
+    mut bot: ResMut<BotState>,
+    mut client: Client,
+) {
+    let Ok(state) = match_query.get_single() else { return; };
+    if *state.phase != MatchPhase::Lobby {
+        bot.ready_requested = false;
+        return;
+    }
+
+    let waiting = info_query
+        .iter()
+        .any(|info| *info.name == bot.name && !*info.ready);
+    if waiting && !bot.ready_requested {
+        client.send_message::<PlayerActionChannel, ReadyUp>(&ReadyUp { ready: true });
+        bot.ready_requested = true;
+    }
+}
+
+fn report(time: Res<Time>, mut bot: ResMut<BotState>, mut client: Client) {
+    if !bot.report_timer.tick(time.delta()).just_finished() || !client.is_connected() {
+        return;
+    }
+
+    info!(
+        "{}: rtt {:.1} ms, jitter {:.1} ms, in {:.2} kbps, out {:.2} kbps",
+        bot.name,
+        client.rtt(),
+        client.jitter(),
+        client.incoming_bandwidth(),
+        client.outgoing_bandwidth(),
+    );
+}
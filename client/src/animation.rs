@@ -0,0 +1,179 @@
+//! Character sprites and their animations, shared by the game and replays.
+//!
+//! `assets/sprites/character.png` is a sheet with a block of rows for each outfit, and within it a
+//! row of frames for each [`Animation`], every frame facing right. The figure is drawn in white so
+//! that it can be dyed in the palette of the character's [`Look`].
+
+use bevy::prelude::*;
+use shared::{
+    components::{Action, Look},
+    units::{to_pixels, CHARACTER_HALF_SIZE},
+};
+
+const CHARACTER_SHEET: &str = "sprites/character.png";
+/// The size of a frame in the sheet, in pixels of the image.
+const FRAME_SIZE: Vec2 = Vec2::new(32.0, 32.0);
+const COLUMNS: usize = 4;
+const ANIMATIONS: usize = 4;
+const ROWS: usize = ANIMATIONS * Look::OUTFITS as usize;
+/// The slowest a character can move and still be shown walking, in meters per second.
+const WALK_MIN_SPEED: f32 = 0.1;
+
+const OUTFITS: [&str; Look::OUTFITS as usize] =
+    ["hooded cloak", "wide-brimmed hat", "belted coat", "cap"];
+const PALETTES: [(&str, Color); Look::PALETTES as usize] = [
+    ("red", Color::rgb(0.86, 0.32, 0.30)),
+    ("blue", Color::rgb(0.30, 0.55, 0.86)),
+    ("green", Color::rgb(0.36, 0.74, 0.40)),
+    ("yellow", Color::rgb(0.88, 0.74, 0.30)),
+    ("purple", Color::rgb(0.64, 0.42, 0.82)),
+    ("teal", Color::rgb(0.30, 0.76, 0.76)),
+    ("orange", Color::rgb(0.90, 0.52, 0.24)),
+    ("grey", Color::rgb(0.70, 0.70, 0.70)),
+];
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Animation {
+    Idle,
+    Walk,
+    Attack,
+    Death,
+}
+
+impl Animation {
+    /// What a character moving at `velocity`, in meters per second, should be shown doing.
+    pub fn of(action: Action, velocity: Vec2) -> Self {
+        match action {
+            Action::Dead => Animation::Death,
+            Action::Attacking => Animation::Attack,
+            Action::Idle if velocity.length() >= WALK_MIN_SPEED => Animation::Walk,
+            Action::Idle => Animation::Idle,
+        }
+    }
+
+    fn row(self) -> usize {
+        match self {
+            Animation::Idle => 0,
+            Animation::Walk => 1,
+            Animation::Attack => 2,
+            Animation::Death => 3,
+        }
+    }
+
+    fn frames_per_sec(self) -> f32 {
+        match self {
+            Animation::Idle => 3.0,
+            Animation::Walk => 8.0,
+            Animation::Attack => 14.0,
+            Animation::Death => 8.0,
+        }
+    }
+
+    /// Whether the animation starts over when it ends, rather than holding its last frame.
+    fn looping(self) -> bool {
+        matches!(self, Animation::Idle | Animation::Walk)
+    }
+}
+
+/// The loaded character sheet.
+#[derive(Resource)]
+pub struct CharacterSheet {
+    atlas: Handle<TextureAtlas>,
+}
+
+/// Plays an [`Animation`] on a character's sprite, in the character's outfit.
+#[derive(Component)]
+pub struct Animator {
+    animation: Animation,
+    outfit: usize,
+    frame: usize,
+    timer: Timer,
+}
+
+impl Animator {
+    fn new(animation: Animation, outfit: usize) -> Self {
+        Self {
+            animation,
+            outfit,
+            frame: 0,
+            timer: Timer::from_seconds(1.0 / animation.frames_per_sec(), TimerMode::Repeating),
+        }
+    }
+
+    /// Switches to the animation from its first frame, unless it is already playing.
+    pub fn play(&mut self, animation: Animation) {
+        if self.animation != animation {
+            *self = Self::new(animation, self.outfit);
+        }
+    }
+}
+
+pub fn load_character_sheet(
+    asset_server: Res<AssetServer>,
+    mut atlases: ResMut<Assets<TextureAtlas>>,
+    mut commands: Commands,
+) {
+    let texture = asset_server.load(CHARACTER_SHEET);
+    let atlas = TextureAtlas::from_grid(texture, FRAME_SIZE, COLUMNS, ROWS, None, None);
+
+    commands.insert_resource(CharacterSheet {
+        atlas: atlases.add(atlas),
+    });
+}
+
+/// The sprite of a character, undyed until it is [`dress`]ed.
+pub fn character_sprite(sheet: &CharacterSheet) -> (SpriteSheetBundle, Animator) {
+    let sprite = SpriteSheetBundle {
+        sprite: TextureAtlasSprite {
+            custom_size: Some(to_pixels(CHARACTER_HALF_SIZE * 2.0)),
+            ..Default::default()
+        },
+        texture_atlas: sheet.atlas.clone(),
+        ..Default::default()
+    };
+
+    (sprite, Animator::new(Animation::Idle, 0))
+}
+
+/// Shows the character in its look.
+pub fn dress(sprite: &mut TextureAtlasSprite, animator: &mut Animator, look: Look) {
+    sprite.color = palette_color(look.palette);
+    animator.outfit = look.outfit as usize % OUTFITS.len();
+}
+
+pub fn palette_color(palette: u8) -> Color {
+    PALETTES[palette as usize % PALETTES.len()].1
+}
+
+/// A look in words, e.g. "red hooded cloak".
+pub fn describe(look: Look) -> String {
+    let (palette, _) = PALETTES[look.palette as usize % PALETTES.len()];
+    let outfit = OUTFITS[look.outfit as usize % OUTFITS.len()];
+    format!("{palette} {outfit}")
+}
+
+/// Turns the sprite to face the way it is moving, keeping its facing while it stands still.
+pub fn face(sprite: &mut TextureAtlasSprite, velocity: Vec2) {
+    if velocity.x.abs() >= WALK_MIN_SPEED {
+        sprite.flip_x = velocity.x < 0.0;
+    }
+}
+
+/// Advances every animation and shows its current frame.
+pub fn animate_sprites(
+    time: Res<Time>,
+    mut query: Query<(&mut Animator, &mut TextureAtlasSprite)>,
+) {
+    for (mut animator, mut sprite) in query.iter_mut() {
+        let animation = animator.animation;
+        let steps = animator.timer.tick(time.delta()).times_finished_this_tick() as usize;
+
+        animator.frame = if animation.looping() {
+            (animator.frame + steps) % COLUMNS
+        } else {
+            (animator.frame + steps).min(COLUMNS - 1)
+        };
+        let row = animator.outfit * ANIMATIONS + animation.row();
+        sprite.index = row * COLUMNS + animator.frame;
+    }
+}
@@ -0,0 +1,159 @@
+//! The camera controller shared by the game and replays. The camera follows a [`CameraFollow`]
+//! target smoothly, leading it in the direction it moves, or is panned freely, and can be zoomed
+//! with the mouse wheel. In game it is kept within the map's walls.
+
+use bevy::{input::mouse::MouseWheel, prelude::*};
+use bevy_egui::EguiContexts;
+use shared::{
+    components::{PhysicsStateSync, WallEntity},
+    units::to_pixels,
+};
+
+/// How fast the free camera moves, in pixels per second at normal zoom.
+const CAMERA_SPEED: f32 = 800.0;
+/// Half the size of the box around the middle of the screen which the followed character can move
+/// in without moving the camera, in pixels at normal zoom.
+const DEADZONE: Vec2 = Vec2::new(64.0, 48.0);
+/// How far ahead of the followed character the camera looks, in seconds of its movement.
+const LOOK_AHEAD_SECS: f32 = 0.4;
+/// How quickly the camera catches up with where it should be. Higher is snappier.
+const FOLLOW_RATE: f32 = 6.0;
+/// How quickly the followed character's estimated velocity adapts to changes.
+const VELOCITY_RATE: f32 = 10.0;
+const MIN_ZOOM: f32 = 0.25;
+const MAX_ZOOM: f32 = 8.0;
+
+/// What the camera follows.
+#[derive(Default, Resource)]
+pub struct CameraFollow {
+    /// The entity to keep in view, or `None` to leave the camera where it is.
+    pub target: Option<Entity>,
+    /// The target the rest was measured for, to start over when it changes.
+    followed: Option<Entity>,
+    last_position: Option<Vec2>,
+    /// The target's smoothed velocity, in pixels per second.
+    velocity: Vec2,
+}
+
+/// The fraction of the way to close in `dt` seconds when closing in at `rate`, which does not
+/// depend on the frame rate.
+fn approach(rate: f32, dt: f32) -> f32 {
+    1.0 - (-rate * dt).exp()
+}
+
+/// Moves the camera towards the followed entity, ahead of it in the direction it is moving, once it
+/// leaves the deadzone.
+pub fn follow_camera(
+    time: Res<Time>,
+    mut follow: ResMut<CameraFollow>,
+    target_query: Query<&Transform, Without<Camera2d>>,
+    mut camera_query: Query<(&mut Transform, &OrthographicProjection), With<Camera2d>>,
+) {
+    let follow = &mut *follow;
+    if follow.followed != follow.target {
+        follow.followed = follow.target;
+        follow.last_position = None;
+        follow.velocity = Vec2::ZERO;
+    }
+
+    let Some(position) = follow
+        .target
+        .and_then(|target| target_query.get(target).ok())
+        .map(|transform| transform.translation.truncate())
+    else {
+        return;
+    };
+    let Ok((mut camera, projection)) = camera_query.get_single_mut() else { return; };
+
+    let dt = time.delta_seconds();
+    if dt <= 0.0 {
+        return;
+    }
+
+    if let Some(last) = follow.last_position {
+        let velocity = (position - last) / dt;
+        follow.velocity = follow.velocity.lerp(velocity, approach(VELOCITY_RATE, dt));
+    }
+    follow.last_position = Some(position);
+
+    let center = camera.translation.truncate();
+    let offset = position + follow.velocity * LOOK_AHEAD_SECS - center;
+    let deadzone = DEADZONE * projection.scale;
+    let goal = center + offset - offset.clamp(-deadzone, deadzone);
+
+    let next = center.lerp(goal, approach(FOLLOW_RATE, dt));
+    camera.translation.x = next.x;
+    camera.translation.y = next.y;
+}
+
+/// Pans the camera with WASD.
+pub fn free_camera(
+    time: Res<Time>,
+    input: Res<Input<KeyCode>>,
+    mut camera_query: Query<(&mut Transform, &OrthographicProjection), With<Camera2d>>,
+) {
+    let Ok((mut transform, projection)) = camera_query.get_single_mut() else { return; };
+
+    let mut direction = Vec2::ZERO;
+    for (key, step) in [
+        (KeyCode::W, Vec2::Y),
+        (KeyCode::A, Vec2::NEG_X),
+        (KeyCode::S, Vec2::NEG_Y),
+        (KeyCode::D, Vec2::X),
+    ] {
+        if input.pressed(key) {
+            direction += step;
+        }
+    }
+    let step = direction * CAMERA_SPEED * projection.scale * time.delta_seconds();
+    transform.translation += step.extend(0.0);
+}
+
+/// Zooms with the mouse wheel, unless the pointer is over a window.
+pub fn zoom_camera(
+    mut wheel: EventReader<MouseWheel>,
+    mut contexts: EguiContexts,
+    mut camera_query: Query<&mut OrthographicProjection, With<Camera2d>>,
+) {
+    let over_ui = contexts.ctx_mut().wants_pointer_input();
+    let Ok(mut projection) = camera_query.get_single_mut() else { return; };
+
+    for event in wheel.iter() {
+        if !over_ui {
+            projection.scale = (projection.scale * (1.0 - event.y * 0.1)).clamp(MIN_ZOOM, MAX_ZOOM);
+        }
+    }
+}
+
+/// Keeps the view within the box around every wall, centering it on an axis where the map is
+/// smaller than the view.
+pub fn clamp_camera(
+    wall_query: Query<(&WallEntity, &PhysicsStateSync)>,
+    mut camera_query: Query<(&mut Transform, &OrthographicProjection), With<Camera2d>>,
+) {
+    let Some((min, max)) = wall_query
+        .iter()
+        .map(|(wall, state)| {
+            let center = Vec2::new(*state.pos_x_m, *state.pos_y_m);
+            let half_size = Vec2::new(*wall.half_width_m, *wall.half_height_m);
+            (to_pixels(center - half_size), to_pixels(center + half_size))
+        })
+        .reduce(|(min, max), (wall_min, wall_max)| (min.min(wall_min), max.max(wall_max)))
+    else {
+        return;
+    };
+    let Ok((mut camera, projection)) = camera_query.get_single_mut() else { return; };
+
+    let half_view = projection.area.size() / 2.0;
+    let center = camera.translation.truncate();
+    let clamp = |center: f32, min: f32, max: f32, half_view: f32| {
+        if max - min <= half_view * 2.0 {
+            (min + max) / 2.0
+        } else {
+            center.clamp(min + half_view, max - half_view)
+        }
+    };
+
+    camera.translation.x = clamp(center.x, min.x, max.x, half_view.x);
+    camera.translation.y = clamp(center.y, min.y, max.y, half_view.y);
+}
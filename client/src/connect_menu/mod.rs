@@ -1,21 +1,68 @@
+use std::str::FromStr;
+
 use bevy::prelude::*;
 use bevy_egui::{egui, EguiContexts};
+#[cfg(not(target_arch = "wasm32"))]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "link-conditioner")]
+use shared::link_condition::LinkCondition;
 
 use crate::MainState;
 
+/// How to reach the server. Browsers can only use WebRTC.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(
+    not(target_arch = "wasm32"),
+    derive(Deserialize, Serialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Transport {
+    WebRtc,
+    #[cfg(not(target_arch = "wasm32"))]
+    Udp,
+}
+
+impl FromStr for Transport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "webrtc" => Ok(Transport::WebRtc),
+            #[cfg(not(target_arch = "wasm32"))]
+            "udp" => Ok(Transport::Udp),
+            _ => Err(format!(
+                "unknown transport `{s}`, expected `webrtc` or `udp`"
+            )),
+        }
+    }
+}
+
 #[derive(Resource)]
 pub struct ConnectMenuState {
+    pub transport: Transport,
+    /// The signaling URL for WebRTC, e.g. `http://127.0.0.1:2000`, or the `host:port` for UDP.
     pub addr: String,
     pub user: String,
     pub pass: String,
+    /// The room to join. Left empty to let the server choose.
+    pub room: String,
+    /// Joins the room to watch rather than play.
+    pub spectate: bool,
+    #[cfg(feature = "link-conditioner")]
+    pub link_condition: LinkCondition,
 }
 
 impl Default for ConnectMenuState {
     fn default() -> Self {
         Self {
+            transport: Transport::WebRtc,
             addr: "http://127.0.0.1:2000".to_owned(),
             user: String::new(),
             pass: String::new(),
+            room: String::new(),
+            spectate: false,
+            #[cfg(feature = "link-conditioner")]
+            link_condition: LinkCondition::Off,
         }
     }
 }
@@ -26,6 +73,12 @@ pub fn connect_menu(
     mut contexts: EguiContexts,
 ) {
     egui::Window::new("Connect to Server").show(contexts.ctx_mut(), |ui| {
+        #[cfg(not(target_arch = "wasm32"))]
+        ui.horizontal(|ui| {
+            ui.label("Transport: ");
+            ui.radio_value(&mut menu_state.transport, Transport::WebRtc, "WebRTC");
+            ui.radio_value(&mut menu_state.transport, Transport::Udp, "UDP");
+        });
         ui.horizontal(|ui| {
             ui.label("Server address: ");
             ui.text_edit_singleline(&mut menu_state.addr)
@@ -38,9 +91,55 @@ pub fn connect_menu(
             ui.label("Server password: ");
             ui.text_edit_singleline(&mut menu_state.pass)
         });
+        ui.horizontal(|ui| {
+            ui.label("Room (blank for any): ");
+            ui.text_edit_singleline(&mut menu_state.room)
+        });
+        ui.checkbox(&mut menu_state.spectate, "Join as a spectator");
+
+        #[cfg(feature = "link-conditioner")]
+        link_condition_ui(ui, &mut menu_state.link_condition);
 
         if ui.button("Connect").clicked() {
             app_state.set(MainState::InGame);
         }
     });
 }
+
+/// Picks a link conditioner preset, with fields for the custom one.
+#[cfg(feature = "link-conditioner")]
+fn link_condition_ui(ui: &mut egui::Ui, condition: &mut LinkCondition) {
+    ui.horizontal(|ui| {
+        ui.label("Simulated link: ");
+        egui::ComboBox::from_id_source("link_condition")
+            .selected_text(condition.name())
+            .show_ui(ui, |ui| {
+                for preset in LinkCondition::ALL {
+                    let selected = condition.name() == preset.name();
+                    if ui.selectable_label(selected, preset.name()).clicked() && !selected {
+                        *condition = preset;
+                    }
+                }
+            });
+    });
+
+    if let LinkCondition::Custom {
+        latency_ms,
+        jitter_ms,
+        loss,
+    } = condition
+    {
+        ui.horizontal(|ui| {
+            ui.label("Latency (ms): ");
+            ui.add(egui::DragValue::new(latency_ms).clamp_range(0..=2000));
+            ui.label("Jitter (ms): ");
+            ui.add(egui::DragValue::new(jitter_ms).clamp_range(0..=1000));
+            ui.label("Loss: ");
+            ui.add(
+                egui::DragValue::new(loss)
+                    .speed(0.01)
+                    .clamp_range(0.0..=1.0),
+            );
+        });
+    }
+}
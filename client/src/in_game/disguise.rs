@@ -0,0 +1,51 @@
+//! Changing disguise at the map's wardrobes.
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use naia_bevy_client::Client;
+use shared::{
+    channels::PlayerActionChannel,
+    components::{MatchPhase, MatchState, PhysicsStateSync, WardrobeEntity},
+    maps::WARDROBE_REACH_M,
+    messages::ChangeDisguise,
+    units::to_meters,
+};
+
+use super::OwnedEntities;
+
+/// While this player stands at a wardrobe, offers to change their disguise with E. The server
+/// ignores requests made too soon after the last change.
+pub fn disguise_controls(
+    input: Res<Input<KeyCode>>,
+    match_query: Query<&MatchState>,
+    owned_entities: Res<OwnedEntities>,
+    wardrobe_query: Query<&PhysicsStateSync, With<WardrobeEntity>>,
+    transform_query: Query<&Transform>,
+    mut contexts: EguiContexts,
+    mut client: Client,
+) {
+    let Ok(state) = match_query.get_single() else { return; };
+    if *state.phase != MatchPhase::InProgress {
+        return;
+    }
+    let Some(owned) = &owned_entities.player_avatar else { return; };
+    let Ok(transform) = transform_query.get(owned.predicted) else { return; };
+
+    let position = to_meters(transform.translation.truncate());
+    let at_wardrobe = wardrobe_query.iter().any(|wardrobe| {
+        position.distance(Vec2::new(*wardrobe.pos_x_m, *wardrobe.pos_y_m)) <= WARDROBE_REACH_M
+    });
+    if !at_wardrobe {
+        return;
+    }
+
+    if input.just_pressed(KeyCode::E) {
+        client.send_message::<PlayerActionChannel, ChangeDisguise>(&ChangeDisguise);
+    }
+
+    egui::Area::new("wardrobe_prompt")
+        .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0.0, -80.0))
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label("Press E to change disguise");
+        });
+}
@@ -10,13 +10,21 @@ use naia_bevy_client::{
 };
 use rapier2d::prelude::{nalgebra, vector};
 use shared::{
-    channels::{GameMessageChannel, PlayerInputChannel},
+    channels::{GameMessageChannel, PlayerInputChannel, SpectatorChannel},
     components::PhysicsStateSync,
-    messages::{EntityAssignment, PlayerInput},
+    messages::{
+        Announcement, EntityAssignment, GameplaySettings, MatchSummary, PlayerInput,
+        SpectatedPlayer, TargetHint,
+    },
     physics::{components::PhysicsBodyHandle, PhysicsWorld},
 };
 
-use super::{Confirmed, EntityProxy, InputHistory, OwnedEntities, Predicted, QueuedCommand};
+use super::{
+    hud::ANNOUNCEMENT_SECS,
+    spectator::{SpectatedEntities, Spectating},
+    Confirmed, EntityProxy, InputHistory, LatestAnnouncement, MatchResults, MovementSettings,
+    OwnedEntities, Predicted, QueuedCommand,
+};
 
 pub mod spawning;
 
@@ -101,3 +109,71 @@ pub fn handle_entity_assignment(
         }
     }
 }
+
+/// Fired when the server tells this player how their target looks. A hint without a look means
+/// there is currently no one to hunt.
+pub fn handle_target_hint(
+    mut event_reader: EventReader<MessageEvents>,
+    mut owned_entities: ResMut<OwnedEntities>,
+) {
+    for events in event_reader.iter() {
+        for hint in events.read::<GameMessageChannel, TargetHint>() {
+            owned_entities.target_look = hint.look;
+        }
+    }
+}
+
+/// Fired when the match in this player's room ends.
+pub fn handle_match_summary(
+    mut event_reader: EventReader<MessageEvents>,
+    mut results: ResMut<MatchResults>,
+) {
+    for events in event_reader.iter() {
+        for summary in events.read::<GameMessageChannel, MatchSummary>() {
+            results.summary = Some(summary);
+        }
+    }
+}
+
+/// Fired every time the server sends its [`GameplaySettings`]
+pub fn handle_gameplay_settings(
+    mut event_reader: EventReader<MessageEvents>,
+    mut movement: ResMut<MovementSettings>,
+) {
+    for events in event_reader.iter() {
+        for settings in events.read::<GameMessageChannel, GameplaySettings>() {
+            movement.player_speed = settings.player_speed;
+        }
+    }
+}
+
+/// Fired every time the server's operator sends an [`Announcement`]
+pub fn handle_announcement(
+    mut event_reader: EventReader<MessageEvents>,
+    mut latest: ResMut<LatestAnnouncement>,
+) {
+    for events in event_reader.iter() {
+        for announcement in events.read::<GameMessageChannel, Announcement>() {
+            info!("Announcement: {}", announcement.text);
+            latest.text = Some(announcement.text);
+            latest.timer = Timer::from_seconds(ANNOUNCEMENT_SECS, TimerMode::Once);
+        }
+    }
+}
+
+/// Fired every time the server tells spectators about a player with a [`SpectatedPlayer`]
+pub fn handle_spectated_player(
+    mut event_reader: EventReader<MessageEvents>,
+    mut spectating: ResMut<Spectating>,
+    client: Client,
+) {
+    for events in event_reader.iter() {
+        for message in events.read::<SpectatorChannel, SpectatedPlayer>() {
+            let entities = SpectatedEntities {
+                avatar: message.avatar.get(&client),
+                target: message.target.get(&client),
+            };
+            spectating.players.insert(message.name, entities);
+        }
+    }
+}
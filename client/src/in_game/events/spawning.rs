@@ -2,18 +2,28 @@
 //! a marker tag defined in `shared`.
 
 use bevy::prelude::*;
-use naia_bevy_client::{events::InsertComponentEvents, CommandsExt};
-use rapier2d::prelude::{ColliderBuilder, RigidBodyBuilder};
+use bevy::utils::HashSet;
+use naia_bevy_client::{
+    events::{DespawnEntityEvent, InsertComponentEvents},
+    CommandsExt,
+};
+use rapier2d::prelude::{nalgebra, vector, ColliderBuilder, RigidBodyBuilder};
 use shared::{
-    components::CharacterEntity,
+    components::{CharacterEntity, PhysicsStateSync, WallEntity, WardrobeEntity},
+    maps::WARDROBE_REACH_M,
     physics::{components::PhysicsBodyHandle, Layer, PhysicsWorld},
+    units::{to_pixels, CHARACTER_HALF_SIZE},
 };
 
-use crate::in_game::{sync::Lerp, Confirmed, Predicted};
+use crate::{
+    animation::{character_sprite, CharacterSheet},
+    in_game::{sync::Lerp, Confirmed, OwnedEntities, Predicted},
+};
 
 pub fn insert_character_to_world(physics: &mut PhysicsWorld, layer: Layer) -> PhysicsBodyHandle {
     let rb = RigidBodyBuilder::dynamic().linear_damping(1.0).build();
-    let cl = ColliderBuilder::cuboid(0.5, 0.5).collision_groups(layer.into());
+    let cl = ColliderBuilder::cuboid(CHARACTER_HALF_SIZE.x, CHARACTER_HALF_SIZE.y)
+        .collision_groups(layer.into());
 
     let (rigid_body, collider) = physics.insert(rb, cl);
 
@@ -23,10 +33,38 @@ pub fn insert_character_to_world(physics: &mut PhysicsWorld, layer: Layer) -> Ph
     }
 }
 
+/// The sprite of a wall centered on `(x, y)`, with everything in meters.
+pub fn wall_sprite(x: f32, y: f32, half_width: f32, half_height: f32) -> SpriteBundle {
+    SpriteBundle {
+        sprite: Sprite {
+            color: Color::GRAY,
+            custom_size: Some(to_pixels(Vec2::new(half_width, half_height) * 2.0)),
+            ..Default::default()
+        },
+        transform: Transform::from_translation(to_pixels(Vec2::new(x, y)).extend(-1.0)),
+        ..Default::default()
+    }
+}
+
+/// The marking of the floor around a wardrobe centered on `(x, y)`, in meters, the size of the area
+/// from which disguises can be changed.
+pub fn wardrobe_sprite(x: f32, y: f32) -> SpriteBundle {
+    SpriteBundle {
+        sprite: Sprite {
+            color: Color::rgba(0.55, 0.35, 0.2, 0.5),
+            custom_size: Some(to_pixels(Vec2::splat(WARDROBE_REACH_M * 2.0))),
+            ..Default::default()
+        },
+        transform: Transform::from_translation(to_pixels(Vec2::new(x, y)).extend(-0.5)),
+        ..Default::default()
+    }
+}
+
 /// Listens for the insertion of [`CharacterEntity`] components from the server. If one is inserted,
 /// that means a new character must be spawned.
 pub fn listen_character_creation(
     mut reader: EventReader<InsertComponentEvents>,
+    sheet: Res<CharacterSheet>,
     mut physics: ResMut<PhysicsWorld>,
     mut commands: Commands,
 ) {
@@ -38,15 +76,7 @@ pub fn listen_character_creation(
                 .duplicate()
                 .insert(handle)
                 .insert(Lerp::new(0.0, 0.0))
-                .insert(SpriteBundle {
-                    sprite: Sprite {
-                        color: Color::FUCHSIA,
-                        custom_size: Some(Vec2::new(100.0, 100.0)),
-                        ..Default::default()
-                    },
-                    transform: Transform::from_xyz(0.0, 0.0, 0.0),
-                    ..Default::default()
-                })
+                .insert(character_sprite(&sheet))
                 .insert(Predicted)
                 .id();
 
@@ -54,3 +84,85 @@ pub fn listen_character_creation(
         }
     }
 }
+
+/// Listens for the insertion of [`WallEntity`] components from the server. Walls never move, so
+/// unlike characters they are not duplicated into a predicted entity; the static body is shared by
+/// both physics layers.
+pub fn listen_wall_creation(
+    mut reader: EventReader<InsertComponentEvents>,
+    wall_query: Query<(&WallEntity, &PhysicsStateSync)>,
+    mut physics: ResMut<PhysicsWorld>,
+    mut commands: Commands,
+) {
+    for event in reader.iter() {
+        for entity in event.read::<WallEntity>() {
+            let Ok((wall, state)) = wall_query.get(entity) else { continue; };
+            let (x, y) = (*state.pos_x_m, *state.pos_y_m);
+
+            let rb = RigidBodyBuilder::fixed().translation(vector![x, y]).build();
+            let cl = ColliderBuilder::cuboid(*wall.half_width_m, *wall.half_height_m)
+                .collision_groups(Layer::Static.into());
+            let (rigid_body, collider) = physics.insert(rb, cl);
+
+            commands
+                .entity(entity)
+                .insert(PhysicsBodyHandle {
+                    rigid_body,
+                    collider,
+                })
+                .insert(wall_sprite(x, y, *wall.half_width_m, *wall.half_height_m));
+        }
+    }
+}
+
+/// Listens for the insertion of [`WardrobeEntity`] components from the server. Wardrobes have no
+/// body, so they are only drawn.
+pub fn listen_wardrobe_creation(
+    mut reader: EventReader<InsertComponentEvents>,
+    wardrobe_query: Query<&PhysicsStateSync, With<WardrobeEntity>>,
+    mut commands: Commands,
+) {
+    for event in reader.iter() {
+        for entity in event.read::<WardrobeEntity>() {
+            let Ok(state) = wardrobe_query.get(entity) else { continue; };
+            commands
+                .entity(entity)
+                .insert(wardrobe_sprite(*state.pos_x_m, *state.pos_y_m));
+        }
+    }
+}
+
+/// Listens for entities being despawned by the server, forgetting about them if they were owned by
+/// this player.
+pub fn listen_entity_despawn(
+    mut reader: EventReader<DespawnEntityEvent>,
+    mut owned_entities: ResMut<OwnedEntities>,
+) {
+    for DespawnEntityEvent(entity) in reader.iter() {
+        if owned_entities
+            .player_avatar
+            .as_ref()
+            .map_or(false, |owned| owned.confirmed == *entity)
+        {
+            owned_entities.player_avatar = None;
+        }
+    }
+}
+
+/// Despawns predicted entities whose confirmed counterpart no longer exists, removing their bodies
+/// from the [`PhysicsWorld`].
+pub fn despawn_orphaned_predictions(
+    confirmed_query: Query<&Confirmed>,
+    predicted_query: Query<(Entity, &PhysicsBodyHandle), With<Predicted>>,
+    mut physics: ResMut<PhysicsWorld>,
+    mut commands: Commands,
+) {
+    let live = confirmed_query.iter().map(|c| c.0).collect::<HashSet<_>>();
+
+    for (entity, handle) in predicted_query.iter() {
+        if !live.contains(&entity) {
+            physics.remove(handle.rigid_body);
+            commands.entity(entity).despawn();
+        }
+    }
+}
@@ -0,0 +1,76 @@
+//! The in-match heads-up display.
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use naia_bevy_client::Client;
+use shared::{
+    channels::PlayerActionChannel,
+    components::{MatchPhase, MatchState},
+    messages::Spectate,
+};
+
+use crate::animation::{describe, palette_color};
+
+use super::{spectator::Spectating, LatestAnnouncement, OwnedEntities};
+
+/// How long an announcement stays on screen, in seconds.
+pub const ANNOUNCEMENT_SECS: f32 = 8.0;
+
+/// Shows the time left in the round and whether this player is alive and hunting, with what they
+/// have seen of their target's look, and lets them spectate once eliminated.
+pub fn match_hud(
+    match_query: Query<&MatchState>,
+    owned_entities: Res<OwnedEntities>,
+    mut spectating: ResMut<Spectating>,
+    mut contexts: EguiContexts,
+    mut client: Client,
+) {
+    let Ok(state) = match_query.get_single() else { return; };
+    if *state.phase != MatchPhase::InProgress {
+        return;
+    }
+
+    let remaining = *state.seconds_remaining;
+
+    egui::Area::new("match_hud")
+        .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 8.0))
+        .show(contexts.ctx_mut(), |ui| {
+            ui.heading(format!("{}:{:02}", remaining / 60, remaining % 60));
+
+            if spectating.joined {
+                ui.label("Spectating");
+            } else if owned_entities.player_avatar.is_none() {
+                ui.label("You have been eliminated");
+                if !spectating.watching && ui.button("Spectate").clicked() {
+                    client.send_message::<PlayerActionChannel, Spectate>(&Spectate);
+                    spectating.watching = true;
+                }
+            } else if let Some(look) = owned_entities.target_look {
+                let [r, g, b, _] = palette_color(look.palette).as_rgba_u8();
+                ui.horizontal(|ui| {
+                    ui.label("Your target was last seen in a");
+                    ui.colored_label(egui::Color32::from_rgb(r, g, b), describe(look));
+                });
+            } else {
+                ui.label("No target");
+            }
+        });
+}
+
+/// Shows the latest announcement from the server's operator until it times out.
+pub fn announcement_banner(
+    time: Res<Time>,
+    mut latest: ResMut<LatestAnnouncement>,
+    mut contexts: EguiContexts,
+) {
+    if latest.timer.tick(time.delta()).finished() {
+        latest.text = None;
+    }
+    let Some(text) = &latest.text else { return; };
+
+    egui::Area::new("announcement")
+        .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0.0, -32.0))
+        .show(contexts.ctx_mut(), |ui| {
+            ui.heading(text);
+        });
+}
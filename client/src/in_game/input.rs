@@ -14,11 +14,17 @@ pub fn key_input(
     let a = input.pressed(KeyCode::A);
     let s = input.pressed(KeyCode::S);
     let d = input.pressed(KeyCode::D);
+    // Several frames may pass before the next tick, so keep an attack which is already queued
+    let attack = input.just_pressed(KeyCode::Space)
+        || queued_command
+            .command
+            .as_ref()
+            .map_or(false, |command| command.attack);
 
     if let Some(owned_entity) = &owned_entities.player_avatar {
-        if w || a || s || d {
+        if w || a || s || d || attack {
             *queued_command = QueuedCommand {
-                command: Some(PlayerInput::from_wasd(w, a, s, d)),
+                command: Some(PlayerInput::from_wasd(w, a, s, d).with_attack(attack)),
             };
             queued_command
                 .command
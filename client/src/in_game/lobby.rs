@@ -0,0 +1,84 @@
+//! The lobby screen shown while the room is waiting for a match to start.
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use naia_bevy_client::Client;
+use shared::{
+    channels::PlayerActionChannel,
+    components::{MatchPhase, MatchState, PlayerInfo},
+    messages::ReadyUp,
+};
+
+use crate::connect_menu::ConnectMenuState;
+
+use super::MatchResults;
+
+/// Lists the users in the room and lets this player toggle whether they are ready. Hidden while a
+/// match is in progress.
+pub fn lobby_screen(
+    match_query: Query<&MatchState>,
+    info_query: Query<&PlayerInfo>,
+    conn: Res<ConnectMenuState>,
+    mut results: ResMut<MatchResults>,
+    mut contexts: EguiContexts,
+    mut client: Client,
+) {
+    let Ok(state) = match_query.get_single() else { return; };
+
+    if *state.phase == MatchPhase::Lobby && results.summary.is_some() {
+        results.summary = None;
+    }
+
+    let heading = match *state.phase {
+        MatchPhase::Lobby => "Waiting for players".to_owned(),
+        MatchPhase::Countdown => format!("Starting in {}", *state.seconds_remaining),
+        MatchPhase::InProgress => return,
+        MatchPhase::PostGame => format!("Back to lobby in {}", *state.seconds_remaining),
+    };
+
+    egui::Window::new("Lobby").show(contexts.ctx_mut(), |ui| {
+        ui.heading(heading);
+        ui.separator();
+
+        if let Some(summary) = &results.summary {
+            match &summary.winner {
+                Some(winner) => ui.label(format!("{winner} wins!")),
+                None => ui.label("Draw"),
+            };
+
+            egui::Grid::new("summary").striped(true).show(ui, |ui| {
+                for header in ["Name", "Kills", "Wrong kills", "Deaths", "Score"] {
+                    ui.strong(header);
+                }
+                ui.end_row();
+
+                for entry in summary.entries.iter() {
+                    ui.label(&entry.name);
+                    ui.label(entry.kills.to_string());
+                    ui.label(entry.wrong_kills.to_string());
+                    ui.label(entry.deaths.to_string());
+                    ui.label(entry.score.to_string());
+                    ui.end_row();
+                }
+            });
+            ui.separator();
+        }
+
+        let mut infos = info_query.iter().collect::<Vec<_>>();
+        infos.sort_by(|a, b| (*a.name).cmp(&*b.name));
+
+        for info in infos {
+            ui.horizontal(|ui| {
+                if *info.name == conn.user && *state.phase != MatchPhase::PostGame {
+                    let mut ready = *info.ready;
+                    if ui.checkbox(&mut ready, info.name.as_str()).changed() {
+                        client.send_message::<PlayerActionChannel, ReadyUp>(&ReadyUp { ready });
+                    }
+                } else {
+                    let mark = if *info.ready { "ready" } else { "not ready" };
+                    ui.label(format!("{} ({mark})", *info.name));
+                }
+            });
+        }
+    });
+}
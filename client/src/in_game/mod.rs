@@ -1,14 +1,28 @@
 //! This is the root modul for the "in-game" state.
 
 use bevy::prelude::*;
+#[cfg(not(target_arch = "wasm32"))]
+use naia_bevy_client::transport::udp;
 use naia_bevy_client::{transport::webrtc, Client, CommandHistory};
-use shared::messages::{Auth, PlayerInput};
+#[cfg(not(feature = "link-conditioner"))]
+use shared::link_condition::LinkCondition;
+use shared::{
+    components::Look,
+    messages::{Auth, MatchSummary, PlayerInput},
+};
 
-use crate::connect_menu::ConnectMenuState;
+use crate::connect_menu::{ConnectMenuState, Transport};
 
+use spectator::Spectating;
+
+pub mod disguise;
 pub mod events;
+pub mod hud;
 pub mod input;
+pub mod lobby;
 pub mod physics;
+pub mod scoreboard;
+pub mod spectator;
 pub mod sync;
 
 /// Utility type defining a pair of [`Entity`] instances which both represent the same remote
@@ -28,6 +42,8 @@ pub struct EntityProxy {
 #[derive(Resource)]
 pub struct OwnedEntities {
     pub player_avatar: Option<EntityProxy>,
+    /// How this player's target looked when they last saw them, as told by the server.
+    pub target_look: Option<Look>,
 }
 
 /// This resource is the next command to be sent to the server. It is set from player input.
@@ -43,6 +59,26 @@ pub struct InputHistory {
     pub history: CommandHistory<PlayerInput>,
 }
 
+/// The results of the last match played in this room, shown until the room returns to the lobby.
+#[derive(Resource)]
+pub struct MatchResults {
+    pub summary: Option<MatchSummary>,
+}
+
+/// The last message from the server's operator, shown for a while after it arrives.
+#[derive(Resource)]
+pub struct LatestAnnouncement {
+    pub text: Option<String>,
+    pub timer: Timer,
+}
+
+/// The server's settings for predicting this player's own movement.
+#[derive(Resource)]
+pub struct MovementSettings {
+    /// The top speed, in meters per second.
+    pub player_speed: f32,
+}
+
 /// A marker trait for confirmed entities to point to their predicted counterpart
 #[derive(Component)]
 pub struct Confirmed(Entity);
@@ -52,12 +88,36 @@ pub struct Confirmed(Entity);
 pub struct Predicted;
 
 /// A simple initialization system for the in-game state.
-pub fn init_game(conn: Res<ConnectMenuState>, mut client: Client, mut commands: Commands) {
+pub fn init_game(
+    conn: Res<ConnectMenuState>,
+    mut spectating: ResMut<Spectating>,
+    mut client: Client,
+) {
     client.auth(Auth {
         name: conn.user.clone(),
         channel_password: conn.pass.clone(),
+        room: (!conn.room.is_empty()).then(|| conn.room.clone()),
+        spectate: conn.spectate,
     });
+    spectating.joined = conn.spectate;
+
+    #[cfg(feature = "link-conditioner")]
+    let condition = conn.link_condition;
+    #[cfg(not(feature = "link-conditioner"))]
+    let condition = LinkCondition::Off;
+
+    let mut config = client.socket_config().clone();
+    config.link_condition = condition.config();
 
-    let socket = webrtc::Socket::new(&conn.addr, client.socket_config());
-    client.connect(socket);
+    match conn.transport {
+        Transport::WebRtc => {
+            let socket = webrtc::Socket::new(&conn.addr, &config);
+            client.connect(socket);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        Transport::Udp => {
+            let socket = udp::Socket::new(&conn.addr, &config);
+            client.connect(socket);
+        }
+    }
 }
@@ -6,11 +6,11 @@ use naia_bevy_client::{
 };
 use rapier2d::prelude::{nalgebra, vector};
 use shared::{
-    components::PhysicsStateSync,
+    components::{Action, CharacterAction, PhysicsStateSync},
     physics::{components::PhysicsBodyHandle, PhysicsWorld},
 };
 
-use super::{Confirmed, InputHistory, OwnedEntities};
+use super::{Confirmed, InputHistory, MovementSettings, OwnedEntities};
 
 /// Meant to be run with other [`EventReader`]s for naia.
 ///
@@ -22,6 +22,7 @@ pub fn restep_physics(
     physics_handle_query: Query<&PhysicsBodyHandle>,
     owned_entities: Res<OwnedEntities>,
     mut player_commands: ResMut<InputHistory>,
+    movement: Res<MovementSettings>,
     mut physics: ResMut<PhysicsWorld>,
     client: Client,
 ) {
@@ -66,7 +67,8 @@ pub fn restep_physics(
                 }
 
                 let Some(rb) = physics.get_rigid_body_mut(handles.rigid_body) else { return; };
-                rb.set_linvel(vector![cmd.x_axis, cmd.y_axis], true);
+                let speed = movement.player_speed;
+                rb.set_linvel(vector![cmd.x_axis * speed, cmd.y_axis * speed], true);
             }
 
             while sequence_greater_than(client.client_tick().unwrap(), last_tick) {
@@ -77,6 +79,25 @@ pub fn restep_physics(
     }
 }
 
+/// Stops the predicted bodies of killed characters from colliding with anything, as the server
+/// already has, while they lie there.
+pub fn disable_corpses(
+    action_query: Query<(&CharacterAction, &Confirmed)>,
+    physics_handle_query: Query<&PhysicsBodyHandle>,
+    mut physics: ResMut<PhysicsWorld>,
+) {
+    for (action, confirmed) in action_query.iter() {
+        if *action.action != Action::Dead {
+            continue;
+        }
+
+        let Ok(handles) = physics_handle_query.get(confirmed.0) else { continue; };
+        if let Some(collider) = physics.get_collider_mut(handles.collider) {
+            collider.set_enabled(false);
+        }
+    }
+}
+
 pub fn step_physics(mut physics: ResMut<PhysicsWorld>) {
     physics.step();
 }
@@ -0,0 +1,56 @@
+//! The scoreboard overlay, shown while Tab is held.
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use shared::components::{PlayerInfo, PlayerStats};
+
+pub fn scoreboard(
+    input: Res<Input<KeyCode>>,
+    stats_query: Query<(&PlayerInfo, &PlayerStats)>,
+    mut contexts: EguiContexts,
+) {
+    if !input.pressed(KeyCode::Tab) {
+        return;
+    }
+
+    let mut rows = stats_query.iter().collect::<Vec<_>>();
+    rows.sort_by(|(a_info, a), (b_info, b)| {
+        (*b.points)
+            .cmp(&*a.points)
+            .then_with(|| (*a_info.name).cmp(&*b_info.name))
+    });
+
+    egui::Window::new("Scoreboard")
+        .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+        .collapsible(false)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::Grid::new("scoreboard").striped(true).show(ui, |ui| {
+                for header in [
+                    "Name",
+                    "Score",
+                    "Kills",
+                    "Wrong kills",
+                    "Deaths",
+                    "Survived",
+                ] {
+                    ui.strong(header);
+                }
+                ui.end_row();
+
+                for (info, stats) in rows {
+                    ui.label(info.name.as_str());
+                    ui.label((*stats.points).to_string());
+                    ui.label((*stats.kills).to_string());
+                    ui.label((*stats.wrong_kills).to_string());
+                    ui.label((*stats.deaths).to_string());
+                    ui.label(format!(
+                        "{}:{:02}",
+                        *stats.survival_secs / 60,
+                        *stats.survival_secs % 60
+                    ));
+                    ui.end_row();
+                }
+            });
+        });
+}
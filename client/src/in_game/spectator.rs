@@ -0,0 +1,144 @@
+//! Watching a match without playing in it, having joined as a spectator or been eliminated.
+//!
+//! Spectators are sent every character in the room, and told by the server which player controls
+//! each one and whom they are hunting. The camera follows a chosen player or is moved freely, and
+//! every player's name is shown over their character with an arrow to their target.
+
+use bevy::{prelude::*, utils::HashMap};
+use bevy_egui::{egui, EguiContexts};
+use shared::{
+    components::{MatchPhase, MatchState, PlayerInfo},
+    units::{to_pixels, CHARACTER_HALF_SIZE},
+};
+
+use super::{Confirmed, Predicted};
+
+/// The confirmed characters of a player and of their target.
+#[derive(Clone, Copy, Default)]
+pub struct SpectatedEntities {
+    pub avatar: Option<Entity>,
+    pub target: Option<Entity>,
+}
+
+#[derive(Default, Resource)]
+pub struct Spectating {
+    /// Whether this player joined the room only to watch.
+    pub joined: bool,
+    /// Whether this player asked to watch the rest of the match after being eliminated.
+    pub watching: bool,
+    /// What the server last said of each player, by name.
+    pub players: HashMap<String, SpectatedEntities>,
+    /// The name of the player the camera follows, or `None` to move it freely.
+    pub following: Option<String>,
+}
+
+impl Spectating {
+    pub fn is_active(&self) -> bool {
+        self.joined || self.watching
+    }
+}
+
+/// Whether the spectator camera is free to move, for [`free_camera`](crate::camera::free_camera).
+pub fn roaming(spectating: Res<Spectating>) -> bool {
+    spectating.is_active() && spectating.following.is_none()
+}
+
+/// Lets eliminated players watch only until their match ends.
+pub fn end_watching(match_query: Query<&MatchState>, mut spectating: ResMut<Spectating>) {
+    let Ok(state) = match_query.get_single() else { return; };
+    if !spectating.watching || *state.phase == MatchPhase::InProgress {
+        return;
+    }
+
+    spectating.watching = false;
+    spectating.following = None;
+}
+
+/// The player picker, and every player's name and target drawn over the match.
+pub fn spectator_view(
+    match_query: Query<&MatchState>,
+    info_query: Query<&PlayerInfo>,
+    confirmed_query: Query<&Confirmed>,
+    predicted_query: Query<&GlobalTransform, With<Predicted>>,
+    camera_query: Query<(&Camera, &GlobalTransform)>,
+    mut spectating: ResMut<Spectating>,
+    mut contexts: EguiContexts,
+) {
+    let Ok(state) = match_query.get_single() else { return; };
+    if !spectating.is_active() || *state.phase != MatchPhase::InProgress {
+        return;
+    }
+
+    let mut names = info_query
+        .iter()
+        .map(|info| (*info.name).clone())
+        .collect::<Vec<_>>();
+    names.sort();
+
+    let ctx = contexts.ctx_mut();
+    egui::Window::new("Spectating")
+        .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-8.0, 8.0))
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.radio_value(&mut spectating.following, None, "Free camera (WASD)");
+            for name in names.iter() {
+                let alive = spectating
+                    .players
+                    .get(name)
+                    .map_or(false, |player| player.avatar.is_some());
+                let label = if alive {
+                    name.clone()
+                } else {
+                    format!("{name} (eliminated)")
+                };
+                ui.add_enabled_ui(alive, |ui| {
+                    ui.radio_value(&mut spectating.following, Some(name.clone()), label);
+                });
+            }
+        });
+
+    let Ok((camera, camera_transform)) = camera_query.get_single() else { return; };
+    let screen_height = ctx.screen_rect().height();
+    // Where a point relative to a confirmed character is drawn, in egui's coordinates which grow
+    // downwards
+    let on_screen = |entity: Entity, offset: Vec2| {
+        let predicted = confirmed_query.get(entity).ok()?.0;
+        let position = predicted_query.get(predicted).ok()?.translation() + offset.extend(0.0);
+        let viewport = camera.world_to_viewport(camera_transform, position)?;
+        Some(egui::pos2(viewport.x, screen_height - viewport.y))
+    };
+
+    let painter = ctx.layer_painter(egui::LayerId::new(
+        egui::Order::Background,
+        egui::Id::new("spectator_overlay"),
+    ));
+    for name in names.iter() {
+        let Some(player) = spectating.players.get(name) else { continue; };
+        let Some(avatar) = player.avatar else { continue; };
+        let Some(position) = on_screen(avatar, Vec2::ZERO) else { continue; };
+
+        let followed = spectating.following.as_ref() == Some(name);
+        let color = if followed {
+            egui::Color32::YELLOW
+        } else {
+            egui::Color32::WHITE
+        };
+
+        if let Some(target) = player
+            .target
+            .and_then(|target| on_screen(target, Vec2::ZERO))
+        {
+            painter.arrow(position, target - position, egui::Stroke::new(2.0, color));
+        }
+        // Just above the character's head
+        let above = to_pixels(Vec2::new(0.0, CHARACTER_HALF_SIZE.y + 0.1));
+        let Some(label) = on_screen(avatar, above) else { continue; };
+        painter.text(
+            label,
+            egui::Align2::CENTER_BOTTOM,
+            name,
+            egui::FontId::proportional(16.0),
+            color,
+        );
+    }
+}
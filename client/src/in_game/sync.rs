@@ -4,11 +4,21 @@ use bevy::prelude::*;
 use naia_bevy_client::Client;
 use rapier2d::prelude::{nalgebra, vector};
 use shared::{
-    components::PhysicsStateSync,
+    components::{Action, Appearance, CharacterAction, PhysicsStateSync},
     physics::{components::PhysicsBodyHandle, PhysicsWorld},
+    units::to_pixels,
 };
 
-use super::{OwnedEntities, Predicted};
+use crate::{
+    animation::{dress, face, Animation, Animator},
+    camera::CameraFollow,
+};
+
+use super::{spectator::Spectating, Confirmed, OwnedEntities, Predicted};
+
+/// How far a predicted body must move before it is interpolated towards its new position, in
+/// meters.
+const LERP_THRESHOLD_M: f32 = 0.05;
 
 /// Copied almost verbatim from the `naia` Bevy demo.
 #[derive(Component)]
@@ -81,23 +91,59 @@ pub fn sync_physics(
     for (mut lerp, handle) in physics_query.iter_mut() {
         let Some(rb) = physics.get_rigid_body_mut(handle.rigid_body) else { continue; };
 
-        let pos = rb.translation() * 100.0;
+        let pos = to_pixels(*rb.translation());
 
-        if vector![lerp.next_x, lerp.next_y].metric_distance(&vector![pos.x, pos.y]) > 5.0 {
+        let moved = vector![lerp.next_x, lerp.next_y].metric_distance(&vector![pos.x, pos.y]);
+        if moved > to_pixels(LERP_THRESHOLD_M) {
             lerp.next_pos(pos.x, pos.y);
         }
     }
 }
 
-pub fn sync_camera_pos(
-    player_pos: Query<&Transform, With<Predicted>>,
-    mut camera_pos: Query<&mut Transform, (With<Camera2d>, Without<Predicted>)>,
-    owned: Res<OwnedEntities>,
+/// Dresses each character in its look and plays the animation of what the server says it is doing,
+/// walking and facing the way its predicted body moves.
+pub fn animate_characters(
+    action_query: Query<(&Confirmed, &CharacterAction, Option<&Appearance>)>,
+    mut sprite_query: Query<
+        (&PhysicsBodyHandle, &mut Animator, &mut TextureAtlasSprite),
+        With<Predicted>,
+    >,
+    physics: Res<PhysicsWorld>,
 ) {
-    if let Some(owned) = &owned.player_avatar {
-        let Ok(player_transform) = player_pos.get(owned.predicted) else { return; };
-        let mut camera_transform = camera_pos.single_mut();
-
-        camera_transform.translation = player_transform.translation;
+    for (confirmed, action, appearance) in action_query.iter() {
+        let Ok((handle, mut animator, mut sprite)) = sprite_query.get_mut(confirmed.0) else {
+            continue;
+        };
+        let Some(rb) = physics.get_rigid_body(handle.rigid_body) else { continue; };
+
+        if let Some(appearance) = appearance {
+            dress(&mut sprite, &mut animator, *appearance.look);
+        }
+        let velocity = Vec2::new(rb.linvel().x, rb.linvel().y);
+        animator.play(Animation::of(*action.action, velocity));
+        if *action.action != Action::Dead {
+            face(&mut sprite, velocity);
+        }
     }
 }
+
+/// Points the camera at the spectated player's character when spectating, and otherwise at this
+/// player's own. Nothing is followed while spectating with the free camera, or once eliminated.
+pub fn choose_camera_target(
+    owned: Res<OwnedEntities>,
+    spectating: Res<Spectating>,
+    confirmed_query: Query<&Confirmed>,
+    mut follow: ResMut<CameraFollow>,
+) {
+    let target = if spectating.is_active() {
+        spectating
+            .following
+            .as_ref()
+            .and_then(|name| spectating.players.get(name)?.avatar)
+            .and_then(|avatar| Some(confirmed_query.get(avatar).ok()?.0))
+    } else {
+        owned.player_avatar.as_ref().map(|owned| owned.predicted)
+    };
+
+    follow.target = target;
+}
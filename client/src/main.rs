@@ -1,32 +1,108 @@
-use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
 
+use animation::{animate_sprites, load_character_sheet};
 use bevy::prelude::*;
 use bevy_egui::EguiPlugin;
+#[cfg(feature = "inspector")]
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
+use camera::{clamp_camera, follow_camera, free_camera, zoom_camera, CameraFollow};
+#[cfg(not(target_arch = "wasm32"))]
+use clap::Parser;
+#[cfg(not(target_arch = "wasm32"))]
+use connect_menu::Transport;
 use connect_menu::{connect_menu, ConnectMenuState};
 use in_game::{
+    disguise::disguise_controls,
     events::{
-        connect_events, disconnect_events, handle_entity_assignment, reject_events,
-        spawning::listen_character_creation, tick_events,
+        connect_events, disconnect_events, handle_announcement, handle_entity_assignment,
+        handle_gameplay_settings, handle_match_summary, handle_spectated_player,
+        handle_target_hint, reject_events,
+        spawning::{
+            despawn_orphaned_predictions, listen_character_creation, listen_entity_despawn,
+            listen_wall_creation, listen_wardrobe_creation,
+        },
+        tick_events,
     },
+    hud::{announcement_banner, match_hud},
     init_game,
     input::key_input,
-    physics::{restep_physics, step_physics},
-    sync::{sync_camera_pos, sync_physics, sync_predicted_sprites},
-    InputHistory, OwnedEntities, QueuedCommand,
+    lobby::lobby_screen,
+    physics::{disable_corpses, restep_physics, step_physics},
+    scoreboard::scoreboard,
+    spectator::{end_watching, roaming, spectator_view, Spectating},
+    sync::{animate_characters, choose_camera_target, sync_physics, sync_predicted_sprites},
+    InputHistory, LatestAnnouncement, MatchResults, MovementSettings, OwnedEntities, QueuedCommand,
 };
 use naia_bevy_client::{ClientConfig, CommandHistory, Plugin as ClientPlugin, ReceiveEvents};
 
-use shared::{physics::PhysicsWorld, protocol};
+#[cfg(feature = "link-conditioner")]
+use shared::link_condition::LinkCondition;
+use shared::{physics::PhysicsWorld, protocol, TICK_INTERVAL};
 
+#[cfg(not(target_arch = "wasm32"))]
+use replay::{advance_playback, load_replay, playback_controls, sync_replay_bodies};
+#[cfg(not(target_arch = "wasm32"))]
+use settings::{load_settings, save_settings, SettingsPath};
+
+mod animation;
+mod camera;
 mod connect_menu;
 mod in_game;
+#[cfg(not(target_arch = "wasm32"))]
+mod replay;
+#[cfg(not(target_arch = "wasm32"))]
+mod settings;
+#[cfg(target_arch = "wasm32")]
+mod web;
+
+/// Every flag is optional, and overrides the settings saved from the last connection.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Parser, Resource)]
+pub struct Args {
+    /// The server's signaling URL for WebRTC, e.g. `http://127.0.0.1:2000`, or its `host:port` for
+    /// UDP.
+    #[arg(short, long)]
+    addr: Option<String>,
+    /// `webrtc` or `udp`.
+    #[arg(short, long)]
+    transport: Option<Transport>,
+    #[arg(short, long)]
+    name: Option<String>,
+    #[arg(short, long)]
+    password: Option<String>,
+    /// The room to join.
+    #[arg(short, long)]
+    room: Option<String>,
+    /// Joins the room to watch rather than play.
+    #[arg(long)]
+    spectate: bool,
+    /// Connects straight away instead of waiting in the connect menu.
+    #[arg(long)]
+    autoconnect: bool,
+
+    /// Simulates a poor connection on incoming packets: `off`, `good`, `average`, `poor` or
+    /// `<latency_ms>:<jitter_ms>:<loss>`.
+    #[cfg(feature = "link-conditioner")]
+    #[arg(long)]
+    link_condition: Option<LinkCondition>,
+
+    /// The settings file to use instead of the one in the platform's config directory.
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Plays back a replay saved by the server instead of connecting.
+    #[arg(long)]
+    replay: Option<PathBuf>,
+}
 
 #[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, States)]
 pub enum MainState {
     #[default]
     ConnectMenu,
     InGame,
+    /// Watching a match recorded by the server, from `--replay`.
+    Replay,
 }
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, SystemSet)]
@@ -35,16 +111,29 @@ struct MainLoop;
 struct Tick;
 
 fn main() {
-    App::default()
-        .add_plugins(DefaultPlugins)
+    #[cfg(target_arch = "wasm32")]
+    let (default_plugins, menu_state): (_, ConnectMenuState) = (
+        DefaultPlugins
+            .set(web::window_plugin())
+            .set(ImagePlugin::default_nearest()),
+        web::connect_menu_from_url(),
+    );
+    #[cfg(not(target_arch = "wasm32"))]
+    let (default_plugins, menu_state) = (
+        DefaultPlugins.set(ImagePlugin::default_nearest()),
+        ConnectMenuState::default(),
+    );
+
+    let mut app = App::default();
+    app.add_plugins(default_plugins)
         .add_plugin(ClientPlugin::new(ClientConfig::default(), protocol()))
         .add_plugin(EguiPlugin)
-        .add_plugin(WorldInspectorPlugin::new())
         .add_state::<MainState>()
         .insert_resource(PhysicsWorld::default())
         // Connect Menu
-        .insert_resource(ConnectMenuState::default())
+        .insert_resource(menu_state)
         .add_system(connect_menu.in_set(OnUpdate(MainState::ConnectMenu)))
+        .add_startup_system(load_character_sheet)
         // In Game
         .add_startup_system(init)
         .add_system(init_game.in_schedule(OnEnter(MainState::InGame)))
@@ -53,41 +142,108 @@ fn main() {
                 connect_events,
                 disconnect_events,
                 handle_entity_assignment,
+                handle_target_hint,
+                handle_match_summary,
+                handle_gameplay_settings,
+                handle_announcement,
+                handle_spectated_player,
                 reject_events,
-                listen_character_creation,
+                (
+                    listen_character_creation,
+                    listen_wall_creation,
+                    listen_wardrobe_creation,
+                    listen_entity_despawn,
+                )
+                    .chain(),
                 restep_physics,
+                disable_corpses,
             )
                 .chain()
                 .in_set(ReceiveEvents),
         )
         .configure_set(Tick.after(ReceiveEvents))
         .add_system(tick_events.in_set(Tick))
+        .add_system(despawn_orphaned_predictions.after(Tick))
+        .add_system(lobby_screen.in_set(OnUpdate(MainState::InGame)))
+        .add_system(match_hud.in_set(OnUpdate(MainState::InGame)))
+        .add_system(disguise_controls.in_set(OnUpdate(MainState::InGame)))
+        .add_system(announcement_banner.in_set(OnUpdate(MainState::InGame)))
+        .add_system(scoreboard.in_set(OnUpdate(MainState::InGame)))
+        .add_systems(
+            (
+                end_watching,
+                choose_camera_target,
+                follow_camera,
+                free_camera.run_if(roaming),
+                zoom_camera,
+                clamp_camera,
+                spectator_view,
+            )
+                .chain()
+                .after(MainLoop)
+                .in_set(OnUpdate(MainState::InGame)),
+        )
         .configure_set(MainLoop.after(Tick))
         .add_systems(
             (
                 key_input,
-                // sync_camera_pos,
                 sync_predicted_sprites,
                 sync_physics,
+                animate_characters,
                 // sync_physics_state,
             )
                 .chain()
                 .in_set(MainLoop),
         )
+        .add_system(animate_sprites.after(MainLoop))
         .add_system(
             step_physics
                 .in_schedule(CoreSchedule::FixedUpdate)
                 .in_set(MainLoop),
         )
-        .insert_resource(FixedTime::new(Duration::from_millis(50)))
-        .run()
+        .insert_resource(FixedTime::new(TICK_INTERVAL));
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let args = Args::parse();
+        app.insert_resource(SettingsPath::new(&args))
+            .insert_resource(args)
+            .add_startup_system(load_settings)
+            .add_system(save_settings.in_schedule(OnEnter(MainState::InGame)))
+            .add_startup_system(load_replay.after(load_settings))
+            .add_systems(
+                (
+                    playback_controls,
+                    advance_playback,
+                    sync_replay_bodies,
+                    free_camera,
+                    zoom_camera,
+                )
+                    .chain()
+                    .in_set(OnUpdate(MainState::Replay)),
+            );
+    }
+
+    #[cfg(feature = "inspector")]
+    app.add_plugin(WorldInspectorPlugin::new());
+
+    app.run();
 }
 
 pub fn init(mut commands: Commands) {
     commands.insert_resource(OwnedEntities {
         player_avatar: None,
+        target_look: None,
     });
     commands.insert_resource(QueuedCommand { command: None });
+    commands.insert_resource(MatchResults { summary: None });
+    commands.insert_resource(MovementSettings { player_speed: 1.0 });
+    commands.insert_resource(LatestAnnouncement {
+        text: None,
+        timer: Timer::default(),
+    });
+    commands.insert_resource(Spectating::default());
+    commands.insert_resource(CameraFollow::default());
     commands.insert_resource(InputHistory {
         history: CommandHistory::default(),
     });
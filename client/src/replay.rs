@@ -0,0 +1,401 @@
+//! Watching a match recorded by the server, without connecting to one. Started with `--replay`.
+//!
+//! The replay is read into a [`Timeline`] of whole snapshots up front, so that seeking anywhere is
+//! as cheap as playing. Space pauses, the left and right arrows skip five seconds, and the camera
+//! is moved freely with WASD and zoomed with the mouse wheel.
+
+use bevy::{prelude::*, utils::HashMap};
+use bevy_egui::{egui, EguiContexts};
+use shared::{
+    components::{Action, Look, ATTACK_SECS},
+    replay::{BodyState, Event, Replay, Shape},
+    units::to_pixels,
+};
+
+use crate::{
+    animation::{character_sprite, dress, face, Animation, Animator, CharacterSheet},
+    in_game::events::spawning::wall_sprite,
+    Args, MainState,
+};
+
+/// The server's tick rate, which replays are recorded at.
+const TICKS_PER_SEC: f32 = shared::TICKS_PER_SEC as f32;
+/// How far the arrow keys skip, in seconds.
+const SKIP_SECS: f32 = 5.0;
+const SPEEDS: [f32; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+/// How long a character is shown attacking after an attack input, in ticks, as the server does.
+const ATTACK_TICKS: f32 = ATTACK_SECS * TICKS_PER_SEC;
+
+/// A body as of one recorded frame.
+#[derive(Clone, Copy)]
+struct Body {
+    shape: Shape,
+    state: BodyState,
+    /// When its user last attacked, in ticks since the first frame.
+    attacked: Option<u32>,
+    /// Whether it was killed, and is only left lying there.
+    dead: bool,
+    /// Unset for walls, and for characters in replays from before looks were recorded.
+    look: Option<Look>,
+}
+
+/// A body at some point of playback.
+struct Placed {
+    id: u32,
+    shape: Shape,
+    /// In meters.
+    position: Vec2,
+    /// In meters per second.
+    velocity: Vec2,
+    action: Action,
+    look: Option<Look>,
+}
+
+/// The bodies of one recorded frame.
+struct Snapshot {
+    /// Ticks since the first frame.
+    offset: u32,
+    bodies: HashMap<u32, Body>,
+}
+
+/// A replay unpacked for playback.
+struct Timeline {
+    snapshots: Vec<Snapshot>,
+    players: Vec<String>,
+    /// When each kill happened, in ticks since the first frame, and who killed whom.
+    kills: Vec<(u32, String)>,
+}
+
+impl Timeline {
+    fn new(replay: &Replay) -> Self {
+        let mut snapshots = Vec::<Snapshot>::new();
+        let mut names = HashMap::new();
+        let mut avatars = HashMap::new();
+        let mut kills = Vec::new();
+        let mut bodies = HashMap::new();
+        let mut offset = 0;
+
+        for (index, frame) in replay.frames.iter().enumerate() {
+            if index > 0 {
+                offset += frame.tick.wrapping_sub(replay.frames[index - 1].tick) as u32;
+            }
+
+            for event in frame.events.iter() {
+                match event {
+                    Event::Player { user, name } => {
+                        names.insert(*user, name.clone());
+                    }
+                    Event::Spawn { body, shape, state } => {
+                        bodies.insert(
+                            *body,
+                            Body {
+                                shape: *shape,
+                                state: *state,
+                                attacked: None,
+                                dead: false,
+                                look: None,
+                            },
+                        );
+                    }
+                    Event::Despawn { body } => {
+                        bodies.remove(body);
+                    }
+                    Event::State { body, state } => {
+                        if let Some(last) = bodies.get_mut(body) {
+                            last.state = *state;
+                        }
+                    }
+                    Event::Input {
+                        user, body, attack, ..
+                    } => {
+                        avatars.insert(*body, *user);
+                        match bodies.get_mut(body) {
+                            Some(body) if *attack => body.attacked = Some(offset),
+                            _ => {}
+                        }
+                    }
+                    Event::Kill { attacker, victim } => {
+                        if let Some(body) = bodies.get_mut(victim) {
+                            body.dead = true;
+                        }
+                        let name = |user: &u32| names.get(user).map_or("?", String::as_str);
+                        let victim = match avatars.get(victim) {
+                            Some(user) => name(user),
+                            None => "a bystander",
+                        };
+                        kills.push((offset, format!("{} killed {victim}", name(attacker))));
+                    }
+                    Event::Appearance { body, look } => {
+                        if let Some(body) = bodies.get_mut(body) {
+                            body.look = Some(*look);
+                        }
+                    }
+                    Event::Target { .. } | Event::PlayerSpeed(_) => {}
+                }
+            }
+
+            snapshots.push(Snapshot {
+                offset,
+                bodies: bodies.clone(),
+            });
+        }
+
+        let mut players = names.into_values().collect::<Vec<_>>();
+        players.sort();
+
+        Self {
+            snapshots,
+            players,
+            kills,
+        }
+    }
+
+    /// The length of the replay, in ticks.
+    fn ticks(&self) -> u32 {
+        self.snapshots.last().map_or(0, |snapshot| snapshot.offset)
+    }
+
+    /// Every body at `position` ticks since the start, with its position interpolated between the
+    /// frames around it.
+    fn bodies_at(&self, position: f32) -> Vec<Placed> {
+        let index = self
+            .snapshots
+            .partition_point(|snapshot| snapshot.offset as f32 <= position)
+            .saturating_sub(1);
+        let Some(before) = self.snapshots.get(index) else { return Vec::new(); };
+        let after = self.snapshots.get(index + 1);
+
+        // Frames recorded at the same offset leave nothing to interpolate
+        let t = match after {
+            Some(after) if after.offset > before.offset => {
+                (position - before.offset as f32) / (after.offset - before.offset) as f32
+            }
+            _ => 0.0,
+        };
+
+        before
+            .bodies
+            .iter()
+            .map(|(id, body)| {
+                let from = Vec2::new(body.state.x, body.state.y);
+                let to = after
+                    .and_then(|after| after.bodies.get(id))
+                    .map_or(from, |after| Vec2::new(after.state.x, after.state.y));
+                let attacking = body
+                    .attacked
+                    .map_or(false, |attacked| position < attacked as f32 + ATTACK_TICKS);
+                let action = if body.dead {
+                    Action::Dead
+                } else if attacking {
+                    Action::Attacking
+                } else {
+                    Action::Idle
+                };
+
+                Placed {
+                    id: *id,
+                    shape: body.shape,
+                    position: from.lerp(to, t.clamp(0.0, 1.0)),
+                    velocity: Vec2::new(body.state.linvel_x, body.state.linvel_y),
+                    action,
+                    look: body.look,
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Resource)]
+pub struct Playback {
+    timeline: Timeline,
+    room: String,
+    /// Ticks since the start of the replay.
+    position: f32,
+    speed: f32,
+    paused: bool,
+    /// The sprite of each body on screen.
+    sprites: HashMap<u32, Entity>,
+}
+
+impl Playback {
+    /// Pauses or resumes, starting over when resumed at the end.
+    fn toggle_pause(&mut self) {
+        if self.paused && self.position >= self.timeline.ticks() as f32 {
+            self.position = 0.0;
+        }
+        self.paused = !self.paused;
+    }
+}
+
+/// Marks the sprites of replayed bodies.
+#[derive(Component)]
+pub struct ReplayBody;
+
+/// Reads the replay given with `--replay`, if any, and skips the connect menu to play it.
+pub fn load_replay(
+    args: Res<Args>,
+    mut next_state: ResMut<NextState<MainState>>,
+    mut commands: Commands,
+) {
+    let Some(path) = &args.replay else { return; };
+
+    let replay = match Replay::load(path) {
+        Ok(replay) => replay,
+        Err(err) => {
+            error!("Could not read replay {}: {err}", path.display());
+            return;
+        }
+    };
+    if replay.truncated {
+        warn!("Replay {} is truncated", path.display());
+    }
+    info!(
+        "Playing replay of room {} on map {}",
+        replay.header.room, replay.header.map
+    );
+
+    commands.insert_resource(Playback {
+        timeline: Timeline::new(&replay),
+        room: replay.header.room,
+        position: 0.0,
+        speed: 1.0,
+        paused: false,
+        sprites: HashMap::new(),
+    });
+    next_state.set(MainState::Replay);
+}
+
+/// The playback window and its keyboard shortcuts.
+pub fn playback_controls(
+    input: Res<Input<KeyCode>>,
+    mut playback: ResMut<Playback>,
+    mut contexts: EguiContexts,
+) {
+    let len = playback.timeline.ticks() as f32;
+    let skip = SKIP_SECS * TICKS_PER_SEC;
+    if input.just_pressed(KeyCode::Space) {
+        playback.toggle_pause();
+    }
+    if input.just_pressed(KeyCode::Left) {
+        playback.position = (playback.position - skip).max(0.0);
+    }
+    if input.just_pressed(KeyCode::Right) {
+        playback.position = (playback.position + skip).min(len);
+    }
+
+    let playback = &mut *playback;
+    egui::Window::new(format!("Replay of {}", playback.room))
+        .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0.0, -8.0))
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                let label = if playback.paused { "Play" } else { "Pause" };
+                if ui.button(label).clicked() {
+                    playback.toggle_pause();
+                }
+
+                let secs = (playback.position / TICKS_PER_SEC) as u32;
+                ui.add(egui::Slider::new(&mut playback.position, 0.0..=len).show_value(false));
+                ui.label(format!("{}:{:02}", secs / 60, secs % 60));
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Speed: ");
+                for speed in SPEEDS {
+                    ui.radio_value(&mut playback.speed, speed, format!("{speed}x"));
+                }
+            });
+
+            ui.label(format!("Players: {}", playback.timeline.players.join(", ")));
+            let now = playback.position as u32;
+            for (_, kill) in playback
+                .timeline
+                .kills
+                .iter()
+                .filter(|(offset, _)| *offset <= now)
+                .rev()
+                .take(5)
+            {
+                ui.label(kill);
+            }
+        });
+}
+
+pub fn advance_playback(time: Res<Time>, mut playback: ResMut<Playback>) {
+    if playback.paused {
+        return;
+    }
+
+    let len = playback.timeline.ticks() as f32;
+    playback.position += time.delta_seconds() * TICKS_PER_SEC * playback.speed;
+    if playback.position >= len {
+        playback.position = len;
+        playback.paused = true;
+    }
+}
+
+/// Spawns, moves, animates and despawns sprites to match the bodies at the current position.
+pub fn sync_replay_bodies(
+    mut playback: ResMut<Playback>,
+    sheet: Res<CharacterSheet>,
+    mut body_query: Query<
+        (
+            &mut Transform,
+            Option<(&mut Animator, &mut TextureAtlasSprite)>,
+        ),
+        With<ReplayBody>,
+    >,
+    mut commands: Commands,
+) {
+    let bodies = playback.timeline.bodies_at(playback.position);
+
+    playback.sprites.retain(|id, entity| {
+        let keep = bodies.iter().any(|body| body.id == *id);
+        if !keep {
+            commands.entity(*entity).despawn();
+        }
+        keep
+    });
+
+    for body in bodies {
+        let pos = to_pixels(body.position);
+        match playback.sprites.get(&body.id) {
+            Some(entity) => {
+                let Ok((mut transform, animated)) = body_query.get_mut(*entity) else { continue; };
+                transform.translation.x = pos.x;
+                transform.translation.y = pos.y;
+
+                if let Some((mut animator, mut sprite)) = animated {
+                    if let Some(look) = body.look {
+                        dress(&mut sprite, &mut animator, look);
+                    }
+                    animator.play(Animation::of(body.action, body.velocity));
+                    if body.action != Action::Dead {
+                        face(&mut sprite, body.velocity);
+                    }
+                }
+            }
+            None => {
+                let entity = match body.shape {
+                    Shape::Character => {
+                        let (mut sprite, animator) = character_sprite(&sheet);
+                        sprite.transform.translation = pos.extend(0.0);
+                        commands.spawn((sprite, animator))
+                    }
+                    Shape::Wall {
+                        half_width,
+                        half_height,
+                    } => commands.spawn(wall_sprite(
+                        body.position.x,
+                        body.position.y,
+                        half_width,
+                        half_height,
+                    )),
+                }
+                .insert(ReplayBody)
+                .id();
+                playback.sprites.insert(body.id, entity);
+            }
+        }
+    }
+}
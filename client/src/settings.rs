@@ -0,0 +1,155 @@
+//! Connection settings remembered between launches, and the command line flags which override them.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "link-conditioner")]
+use shared::link_condition::LinkCondition;
+
+use crate::{
+    connect_menu::{ConnectMenuState, Transport},
+    Args, MainState,
+};
+
+/// Where the settings are read from and saved to.
+#[derive(Resource)]
+pub struct SettingsPath(pub Option<PathBuf>);
+
+impl SettingsPath {
+    /// The `--config` path if given, else `client.toml` in the platform's config directory, e.g.
+    /// `~/.config/assassin/client.toml` on Linux.
+    pub fn new(args: &Args) -> Self {
+        let default = || dirs::config_dir().map(|dir| dir.join("assassin").join("client.toml"));
+        Self(args.config.clone().or_else(default))
+    }
+}
+
+/// The last used connection settings. The server password is left out on purpose, as the file is
+/// stored in plain text.
+#[derive(Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Settings {
+    pub transport: Option<Transport>,
+    pub addr: Option<String>,
+    pub name: Option<String>,
+    pub room: Option<String>,
+    /// A [`LinkCondition`](shared::link_condition::LinkCondition) in its command line form.
+    pub link_condition: Option<String>,
+}
+
+impl Settings {
+    /// Reads the settings at `path`, falling back to the defaults when there are none yet or they
+    /// cannot be read.
+    pub fn load(path: &Path) -> Self {
+        match fs::read_to_string(path) {
+            Ok(text) => toml::from_str(&text).unwrap_or_else(|err| {
+                warn!("Ignoring invalid settings in {}: {err}", path.display());
+                Self::default()
+            }),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(err) => {
+                warn!("Could not read settings from {}: {err}", path.display());
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let text = toml::to_string_pretty(self)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        fs::write(path, text)
+    }
+
+    fn from_menu(menu_state: &ConnectMenuState) -> Self {
+        Self {
+            transport: Some(menu_state.transport),
+            addr: Some(menu_state.addr.clone()),
+            name: Some(menu_state.user.clone()),
+            room: Some(menu_state.room.clone()),
+            #[cfg(feature = "link-conditioner")]
+            link_condition: Some(menu_state.link_condition.to_string()),
+            #[cfg(not(feature = "link-conditioner"))]
+            link_condition: None,
+        }
+    }
+
+    fn apply(self, menu_state: &mut ConnectMenuState) {
+        if let Some(transport) = self.transport {
+            menu_state.transport = transport;
+        }
+        if let Some(addr) = self.addr {
+            menu_state.addr = addr;
+        }
+        if let Some(name) = self.name {
+            menu_state.user = name;
+        }
+        if let Some(room) = self.room {
+            menu_state.room = room;
+        }
+
+        #[cfg(feature = "link-conditioner")]
+        if let Some(condition) = self.link_condition {
+            match condition.parse::<LinkCondition>() {
+                Ok(condition) => menu_state.link_condition = condition,
+                Err(err) => warn!("Ignoring saved link condition: {err}"),
+            }
+        }
+    }
+}
+
+/// Fills in the connect menu from the saved settings and then the command line, and skips the menu
+/// when asked to connect straight away.
+pub fn load_settings(
+    args: Res<Args>,
+    path: Res<SettingsPath>,
+    mut menu_state: ResMut<ConnectMenuState>,
+    mut next_state: ResMut<NextState<MainState>>,
+) {
+    if let Some(path) = &path.0 {
+        Settings::load(path).apply(&mut menu_state);
+    }
+
+    if let Some(transport) = args.transport {
+        menu_state.transport = transport;
+    }
+    if let Some(addr) = &args.addr {
+        menu_state.addr = addr.clone();
+    }
+    if let Some(name) = &args.name {
+        menu_state.user = name.clone();
+    }
+    if let Some(password) = &args.password {
+        menu_state.pass = password.clone();
+    }
+    if let Some(room) = &args.room {
+        menu_state.room = room.clone();
+    }
+    if args.spectate {
+        menu_state.spectate = true;
+    }
+    #[cfg(feature = "link-conditioner")]
+    if let Some(condition) = args.link_condition {
+        menu_state.link_condition = condition;
+    }
+
+    if args.autoconnect {
+        next_state.set(MainState::InGame);
+    }
+}
+
+/// Remembers the settings used to connect for the next launch.
+pub fn save_settings(path: Res<SettingsPath>, menu_state: Res<ConnectMenuState>) {
+    let Some(path) = &path.0 else { return; };
+
+    if let Err(err) = Settings::from_menu(&menu_state).save(path) {
+        warn!("Could not save settings to {}: {err}", path.display());
+    }
+}
@@ -0,0 +1,55 @@
+//! Setup which only applies when running in a browser.
+
+use bevy::{prelude::*, window::WindowPlugin};
+use web_sys::UrlSearchParams;
+
+use crate::connect_menu::ConnectMenuState;
+
+/// The port the server's WebRTC signaling listens on by default.
+const DEFAULT_SIGNALING_PORT: u16 = 2000;
+
+/// Renders into the page's `#bevy` canvas, resized along with the page.
+pub fn window_plugin() -> WindowPlugin {
+    WindowPlugin {
+        primary_window: Some(Window {
+            title: "Assassin".to_owned(),
+            canvas: Some("#bevy".to_owned()),
+            fit_canvas_to_parent: true,
+            ..default()
+        }),
+        ..default()
+    }
+}
+
+/// Prefills the connect menu from the page URL. The server is assumed to be on the same host as the
+/// page unless `?addr=` says otherwise, and `?name=` and `?room=` fill in the other fields.
+/// `?spectate` joins as a spectator.
+pub fn connect_menu_from_url() -> ConnectMenuState {
+    let mut state = ConnectMenuState::default();
+    let Some(location) = web_sys::window().map(|window| window.location()) else { return state; };
+
+    if let Ok(host) = location.hostname() {
+        if !host.is_empty() {
+            state.addr = format!("http://{host}:{DEFAULT_SIGNALING_PORT}");
+        }
+    }
+
+    let params = location
+        .search()
+        .ok()
+        .and_then(|search| UrlSearchParams::new_with_str(&search).ok());
+    if let Some(params) = params {
+        if let Some(addr) = params.get("addr") {
+            state.addr = addr;
+        }
+        if let Some(name) = params.get("name") {
+            state.user = name;
+        }
+        if let Some(room) = params.get("room") {
+            state.room = room;
+        }
+        state.spectate = params.has("spectate");
+    }
+
+    state
+}
@@ -0,0 +1,491 @@
+//! The operator's console for a running server.
+//!
+//! Commands are lines of text, read from stdin and, on Unix, from clients of a local socket given
+//! with `--admin-socket`, e.g. `socat - UNIX-CONNECT:/run/assassin.sock`. Reader threads queue
+//! each line in the [`AdminConsole`] along with a way to reply, and the systems here carry them out
+//! between updates like any other event.
+
+use std::{
+    io::{self, BufRead},
+    net::IpAddr,
+    str::FromStr,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use naia_bevy_server::{events::TickEvent, Server};
+
+use shared::{channels::GameMessageChannel, components::MatchPhase, messages::Announcement};
+
+use crate::{
+    bans::{parse_duration, Ban, BanList},
+    config::Config,
+    resources::UserNameMapping,
+    rooms::RoomManager,
+    Args,
+};
+
+const HELP: &str = "\
+list                       connected users, with their room, address and ping
+kick <name>                disconnect a user
+ban <target> [time] [why]  refuse a name or address, disconnecting matching users. A connected
+                           user's address is banned along with their name. Permanent unless
+                           given a time like `30m`, `12h` or `7d`
+unban <target>             lift the bans on a name or address
+bans                       the current bans
+say <text>                 show a message to every player
+restart-round [room]       abandon the current match of every room, or of one room
+set <section.name> <value> change a gameplay, scoping or anti-cheat setting, e.g.
+                           `set gameplay.npcs 4`
+status                     tick rate, entity count and the state of each room
+help                       this list";
+
+/// A line of input waiting to be carried out, and where to send the reply.
+pub struct AdminRequest {
+    pub line: String,
+    pub reply: Sender<String>,
+}
+
+/// Queues commands from the reader threads for the systems to carry out.
+#[derive(Resource)]
+pub struct AdminConsole {
+    sender: Sender<AdminRequest>,
+    receiver: Mutex<Receiver<AdminRequest>>,
+}
+
+impl AdminConsole {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            sender,
+            receiver: Mutex::new(receiver),
+        }
+    }
+
+    /// A handle for queueing commands from another thread.
+    pub fn sender(&self) -> Sender<AdminRequest> {
+        self.sender.clone()
+    }
+}
+
+/// Queues `line` and waits for its reply. Returns `None` once the server has shut down.
+pub fn submit(sender: &Sender<AdminRequest>, line: String) -> Option<String> {
+    let (reply, replies) = mpsc::channel();
+    sender.send(AdminRequest { line, reply }).ok()?;
+    replies.recv().ok()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AdminCommand {
+    List,
+    Kick(String),
+    Ban {
+        /// A name or an address.
+        target: String,
+        duration: Option<Duration>,
+        reason: Option<String>,
+    },
+    Unban(String),
+    Bans,
+    Say(String),
+    RestartRound(Option<String>),
+    Set { key: String, value: String },
+    Status,
+    Help,
+}
+
+impl FromStr for AdminCommand {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (command, rest) = s.trim().split_once(' ').unwrap_or((s.trim(), ""));
+        let rest = rest.trim();
+        let name = || {
+            Some(rest.to_owned())
+                .filter(|name| !name.is_empty())
+                .ok_or_else(|| format!("usage: {command} <name>"))
+        };
+
+        match command {
+            "list" => Ok(AdminCommand::List),
+            "kick" => Ok(AdminCommand::Kick(name()?)),
+            "ban" => {
+                let mut words = rest.split_whitespace().peekable();
+                let target = words.next().ok_or("usage: ban <target> [time] [why]")?;
+                let duration = words.peek().and_then(|word| parse_duration(word));
+                if duration.is_some() {
+                    words.next();
+                }
+                let reason = words.collect::<Vec<_>>().join(" ");
+
+                Ok(AdminCommand::Ban {
+                    target: target.to_owned(),
+                    duration,
+                    reason: Some(reason).filter(|reason| !reason.is_empty()),
+                })
+            }
+            "unban" => Ok(AdminCommand::Unban(name()?)),
+            "bans" => Ok(AdminCommand::Bans),
+            "say" if !rest.is_empty() => Ok(AdminCommand::Say(rest.to_owned())),
+            "say" => Err("usage: say <text>".to_owned()),
+            "restart-round" => Ok(AdminCommand::RestartRound(
+                Some(rest.to_owned()).filter(|room| !room.is_empty()),
+            )),
+            "set" => match rest.split_once(' ') {
+                Some((key, value)) => Ok(AdminCommand::Set {
+                    key: key.to_owned(),
+                    value: value.trim().to_owned(),
+                }),
+                None => Err("usage: set <section.name> <value>".to_owned()),
+            },
+            "status" => Ok(AdminCommand::Status),
+            "help" => Ok(AdminCommand::Help),
+            _ => Err(format!("unknown command `{command}`, try `help`")),
+        }
+    }
+}
+
+/// A parsed command, handled by whichever of the systems here it concerns.
+pub struct AdminEvent {
+    pub command: AdminCommand,
+    reply: Sender<String>,
+}
+
+impl AdminEvent {
+    fn reply(&self, text: impl Into<String>) {
+        // The reader may have hung up, in which case nobody is waiting for the reply
+        let _ = self.reply.send(text.into());
+    }
+}
+
+/// Ticks counted over the last second, for `status`.
+#[derive(Resource)]
+pub struct TickRate {
+    per_sec: u32,
+    counted: u32,
+    timer: Timer,
+}
+
+impl Default for TickRate {
+    fn default() -> Self {
+        Self {
+            per_sec: 0,
+            counted: 0,
+            timer: Timer::new(Duration::from_secs(1), TimerMode::Repeating),
+        }
+    }
+}
+
+/// Reads commands from stdin and, when `--admin-socket` is given, from a local socket.
+pub fn start_admin_console(args: Res<Args>, console: Res<AdminConsole>) {
+    let sender = console.sender();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else { break; };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let Some(reply) = submit(&sender, line) else { break; };
+            println!("{reply}");
+        }
+    });
+
+    #[cfg(unix)]
+    if let Some(path) = &args.admin_socket {
+        if let Err(err) = unix::listen(path, console.sender()) {
+            error!("Could not open the admin socket at {}: {err}", path.display());
+        }
+    }
+    #[cfg(not(unix))]
+    if args.admin_socket.is_some() {
+        warn!("The admin socket is only available on Unix");
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::{
+        fs,
+        io::{self, BufRead, BufReader, Write},
+        os::unix::{
+            fs::PermissionsExt,
+            net::{UnixListener, UnixStream},
+        },
+        path::Path,
+        sync::mpsc::Sender,
+        thread,
+    };
+
+    use bevy::prelude::*;
+
+    use super::{submit, AdminRequest};
+
+    /// Serves each client of the socket on its own thread. Only the server's own user may connect.
+    pub fn listen(path: &Path, sender: Sender<AdminRequest>) -> io::Result<()> {
+        // A socket left behind by an earlier run would make binding fail
+        if fs::metadata(path).is_ok() {
+            fs::remove_file(path)?;
+        }
+
+        let listener = UnixListener::bind(path)?;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        info!("Admin socket listening on {}", path.display());
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else { continue; };
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    if let Err(err) = serve(stream, sender) {
+                        warn!("Admin connection closed: {err}");
+                    }
+                });
+            }
+        });
+
+        Ok(())
+    }
+
+    fn serve(stream: UnixStream, sender: Sender<AdminRequest>) -> io::Result<()> {
+        let mut writer = stream.try_clone()?;
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let Some(reply) = submit(&sender, line) else { break; };
+            writeln!(writer, "{reply}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the queued lines into [`AdminEvent`]s, answering malformed ones and `help` directly.
+pub fn receive_admin_commands(console: Res<AdminConsole>, mut events: EventWriter<AdminEvent>) {
+    let receiver = console.receiver.lock().unwrap();
+    for AdminRequest { line, reply } in receiver.try_iter() {
+        info!("Admin command: {line}");
+        match line.parse() {
+            Ok(AdminCommand::Help) => {
+                let _ = reply.send(HELP.to_owned());
+            }
+            Ok(command) => events.send(AdminEvent { command, reply }),
+            Err(err) => {
+                let _ = reply.send(err);
+            }
+        }
+    }
+}
+
+/// Handles `list`, `kick` and `say`.
+pub fn admin_users(
+    mut events: EventReader<AdminEvent>,
+    rooms: Res<RoomManager>,
+    users_names: Res<UserNameMapping>,
+    mut server: Server,
+) {
+    for event in events.iter() {
+        match &event.command {
+            AdminCommand::List => {
+                let mut users = users_names.iter().collect::<Vec<_>>();
+                users.sort_by(|a, b| a.1.cmp(b.1));
+
+                let lines = users
+                    .into_iter()
+                    .filter(|(user_key, _)| server.user_exists(user_key))
+                    .map(|(user_key, name)| {
+                        let room = rooms
+                            .room_of(user_key)
+                            .map_or("-", |room| room.settings.name.as_str());
+                        let address = server.user(user_key).address();
+                        let ping = server
+                            .rtt(user_key)
+                            .map_or("-".to_owned(), |rtt| format!("{rtt:.0}ms"));
+                        format!("{name}\t{room}\t{address}\t{ping}")
+                    })
+                    .collect::<Vec<_>>();
+
+                if lines.is_empty() {
+                    event.reply("no users connected");
+                } else {
+                    event.reply(lines.join("\n"));
+                }
+            }
+            AdminCommand::Kick(name) => {
+                // The name may outlive the user by a frame, until their disconnection is handled
+                let user_key = users_names.get_by_name(name).copied();
+                let Some(user_key) = user_key.filter(|user_key| server.user_exists(user_key)) else {
+                    event.reply(format!("no user named {name}"));
+                    continue;
+                };
+
+                server.user_mut(&user_key).disconnect();
+                event.reply(format!("disconnected {name}"));
+            }
+            AdminCommand::Say(text) => {
+                let message = Announcement { text: text.clone() };
+                for user_key in server.user_keys() {
+                    server.send_message::<GameMessageChannel, Announcement>(&user_key, &message);
+                }
+                event.reply("sent");
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Handles `ban`, `unban` and `bans`.
+pub fn admin_bans(
+    mut events: EventReader<AdminEvent>,
+    users_names: Res<UserNameMapping>,
+    mut bans: ResMut<BanList>,
+    mut server: Server,
+) {
+    for event in events.iter() {
+        match &event.command {
+            AdminCommand::Ban {
+                target,
+                duration,
+                reason,
+            } => {
+                let ban = match target.parse::<IpAddr>() {
+                    Ok(address) => Ban::new(None, Some(address)),
+                    Err(_) => {
+                        let address = users_names
+                            .get_by_name(target)
+                            .filter(|user_key| server.user_exists(user_key))
+                            .map(|user_key| server.user(user_key).address().ip());
+                        Ban::new(Some(target.clone()), address)
+                    }
+                };
+                let ban = ban.lasting(*duration).because(reason.clone());
+
+                let banned_users = users_names
+                    .iter()
+                    .filter(|(user_key, _)| server.user_exists(user_key))
+                    .filter(|(user_key, name)| {
+                        ban.name.as_ref() == Some(*name)
+                            || ban.address == Some(server.user(user_key).address().ip())
+                    })
+                    .map(|(user_key, _)| *user_key)
+                    .collect::<Vec<_>>();
+                for user_key in banned_users.iter() {
+                    server.user_mut(user_key).disconnect();
+                }
+
+                event.reply(format!(
+                    "banned {ban}, disconnected {} users",
+                    banned_users.len()
+                ));
+                bans.add(ban);
+            }
+            AdminCommand::Unban(target) => match bans.remove(target) {
+                0 => event.reply(format!("{target} is not banned")),
+                removed => event.reply(format!("lifted {removed} bans")),
+            },
+            AdminCommand::Bans if bans.bans().is_empty() => event.reply("nobody is banned"),
+            AdminCommand::Bans => {
+                let lines = bans.bans().iter().map(Ban::to_string).collect::<Vec<_>>();
+                event.reply(lines.join("\n"));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Handles `restart-round`.
+pub fn admin_match(mut events: EventReader<AdminEvent>, mut rooms: ResMut<RoomManager>) {
+    for event in events.iter() {
+        let AdminCommand::RestartRound(name) = &event.command else { continue; };
+
+        let mut restarted = Vec::new();
+        for room in rooms.rooms_mut() {
+            if name.as_ref().map_or(false, |name| *name != room.settings.name) {
+                continue;
+            }
+            if matches!(room.phase, MatchPhase::InProgress | MatchPhase::PostGame) {
+                room.restart_requested = true;
+                restarted.push(room.settings.name.clone());
+            }
+        }
+
+        if restarted.is_empty() {
+            event.reply("no matching room is in a match");
+        } else {
+            event.reply(format!("restarting {}", restarted.join(", ")));
+        }
+    }
+}
+
+/// Handles `set`.
+pub fn admin_config(
+    mut events: EventReader<AdminEvent>,
+    mut config: ResMut<Config>,
+    mut server: Server,
+) {
+    for event in events.iter() {
+        let AdminCommand::Set { key, value } = &event.command else { continue; };
+
+        match config.with_value(key, value) {
+            Ok(new) => {
+                config.update(new, &mut server);
+                event.reply(format!("{key} set"));
+            }
+            Err(err) => event.reply(err),
+        }
+    }
+}
+
+/// Counts ticks for `status`.
+pub fn count_ticks(
+    time: Res<Time>,
+    mut ticks: EventReader<TickEvent>,
+    mut rate: ResMut<TickRate>,
+) {
+    rate.counted += ticks.iter().count() as u32;
+    if rate.timer.tick(time.delta()).just_finished() {
+        rate.per_sec = rate.counted;
+        rate.counted = 0;
+    }
+}
+
+/// Handles `status`.
+pub fn admin_status(
+    mut events: EventReader<AdminEvent>,
+    rate: Res<TickRate>,
+    rooms: Res<RoomManager>,
+    entities: Query<Entity>,
+    server: Server,
+) {
+    for event in events.iter() {
+        if event.command != AdminCommand::Status {
+            continue;
+        }
+
+        let mut lines = vec![format!(
+            "tick {}, {} ticks/s, {} entities, {} users",
+            server.current_tick(),
+            rate.per_sec,
+            entities.iter().count(),
+            server.users_count(),
+        )];
+        lines.extend(rooms.rooms().iter().map(|room| {
+            format!(
+                "room {}: {:?}, {}/{} players, {} spectators, {} npcs",
+                room.settings.name,
+                room.phase,
+                room.players(),
+                room.settings.max_players,
+                room.spectators.len(),
+                room.npcs.len(),
+            )
+        }));
+
+        event.reply(lines.join("\n"));
+    }
+}
@@ -0,0 +1,287 @@
+//! Checks on player inputs before they are applied.
+//!
+//! Inputs which no honest client sends are dropped and counted against their sender as
+//! violations, and a user who reaches the configured threshold is kicked. Inputs which are merely
+//! late, e.g. for a character which died a moment ago, are dropped without a violation.
+
+use std::fmt;
+
+use bevy::{prelude::*, utils::HashMap};
+use naia_bevy_server::{Tick, UserKey};
+
+use shared::messages::PlayerInput;
+
+use crate::config::AntiCheatConfig;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Violation {
+    /// More inputs in one tick than a client sends.
+    TooManyInputs,
+    /// An axis which is NaN or infinite.
+    NonFiniteAxis,
+    /// An axis outside of [-1, 1].
+    AxisOutOfRange,
+    /// Steering a character other than one's own.
+    ForeignEntity,
+    /// Attacking in consecutive ticks, which takes releasing and pressing the key again faster
+    /// than anyone can.
+    AttackSpam,
+}
+
+impl Violation {
+    /// A short name for the violation, for metrics.
+    pub fn label(self) -> &'static str {
+        match self {
+            Violation::TooManyInputs => "too_many_inputs",
+            Violation::NonFiniteAxis => "non_finite_axis",
+            Violation::AxisOutOfRange => "out_of_range",
+            Violation::ForeignEntity => "foreign_entity",
+            Violation::AttackSpam => "attack_spam",
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::TooManyInputs => write!(f, "too many inputs in one tick"),
+            Violation::NonFiniteAxis => write!(f, "non-finite axis"),
+            Violation::AxisOutOfRange => write!(f, "axis out of range"),
+            Violation::ForeignEntity => write!(f, "steering another character"),
+            Violation::AttackSpam => write!(f, "attacking in consecutive ticks"),
+        }
+    }
+}
+
+/// What to do with an input.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Verdict {
+    Apply,
+    /// Drop the input, which is stale rather than malicious.
+    Ignore,
+    Reject(Violation),
+}
+
+/// Who controls which character, as far as one input is concerned.
+pub struct Ownership {
+    /// The entity the input asks to steer, if it still exists.
+    pub entity: Option<Entity>,
+    /// The sender's own character, if they are alive.
+    pub avatar: Option<Entity>,
+    /// Whether `entity` is a living character. Corpses do not count, as their owners may still be
+    /// steering them until they hear of their death.
+    pub is_character: bool,
+}
+
+#[derive(Default, Resource)]
+pub struct AntiCheat {
+    users: HashMap<UserKey, UserRecord>,
+}
+
+/// What the checks remember of one user.
+#[derive(Default)]
+struct UserRecord {
+    /// How many inputs the user has sent in the tick being processed.
+    inputs: u8,
+    /// The last tick the user attacked in.
+    last_attack: Option<Tick>,
+    violations: u32,
+    /// Whether the user has already been kicked, so that they are only kicked once.
+    kicked: bool,
+}
+
+impl AntiCheat {
+    /// Forgets the input counts of the previous tick.
+    pub fn start_tick(&mut self) {
+        for record in self.users.values_mut() {
+            record.inputs = 0;
+        }
+    }
+
+    pub fn check(
+        &mut self,
+        cfg: &AntiCheatConfig,
+        user_key: UserKey,
+        tick: Tick,
+        input: &PlayerInput,
+        ownership: Ownership,
+    ) -> Verdict {
+        self.users
+            .entry(user_key)
+            .or_default()
+            .check(cfg, tick, input, ownership)
+    }
+
+    /// Counts a violation against the user, returning whether they should now be kicked. This is
+    /// only true once per user, even if the threshold is lowered below their count.
+    pub fn record(&mut self, cfg: &AntiCheatConfig, user_key: UserKey) -> bool {
+        self.users.entry(user_key).or_default().record(cfg)
+    }
+
+    pub fn violations(&self, user_key: &UserKey) -> u32 {
+        self.users.get(user_key).map_or(0, |record| record.violations)
+    }
+
+    pub fn forget(&mut self, user_key: &UserKey) {
+        self.users.remove(user_key);
+    }
+}
+
+impl UserRecord {
+    fn check(
+        &mut self,
+        cfg: &AntiCheatConfig,
+        tick: Tick,
+        input: &PlayerInput,
+        ownership: Ownership,
+    ) -> Verdict {
+        self.inputs = self.inputs.saturating_add(1);
+        if self.inputs > cfg.max_inputs_per_tick {
+            return Verdict::Reject(Violation::TooManyInputs);
+        }
+
+        if !input.x_axis.is_finite() || !input.y_axis.is_finite() {
+            return Verdict::Reject(Violation::NonFiniteAxis);
+        }
+        if input.x_axis.abs() > 1.0 || input.y_axis.abs() > 1.0 {
+            return Verdict::Reject(Violation::AxisOutOfRange);
+        }
+
+        let Some(entity) = ownership.entity else { return Verdict::Ignore; };
+        if ownership.avatar != Some(entity) {
+            return match ownership.is_character {
+                true => Verdict::Reject(Violation::ForeignEntity),
+                false => Verdict::Ignore,
+            };
+        }
+
+        if input.attack {
+            let previous = self.last_attack.replace(tick);
+            if previous == Some(tick.wrapping_sub(1)) {
+                return Verdict::Reject(Violation::AttackSpam);
+            }
+        }
+
+        Verdict::Apply
+    }
+
+    fn record(&mut self, cfg: &AntiCheatConfig) -> bool {
+        self.violations += 1;
+
+        let kick = cfg.kick_threshold > 0 && self.violations >= cfg.kick_threshold && !self.kicked;
+        self.kicked |= kick;
+        kick
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cfg() -> AntiCheatConfig {
+        AntiCheatConfig {
+            max_inputs_per_tick: 1,
+            kick_threshold: 3,
+        }
+    }
+
+    fn own(entity: Entity) -> Ownership {
+        Ownership {
+            entity: Some(entity),
+            avatar: Some(entity),
+            is_character: true,
+        }
+    }
+
+    #[test]
+    fn foreign_characters_are_rejected_but_corpses_ignored() {
+        let (avatar, other) = (Entity::from_raw(1), Entity::from_raw(2));
+        let input = PlayerInput::from_axes(1.0, 0.0);
+        let steer = |is_character| Ownership {
+            entity: Some(other),
+            avatar: Some(avatar),
+            is_character,
+        };
+
+        let mut record = UserRecord::default();
+        assert_eq!(
+            record.check(&cfg(), 0, &input, steer(true)),
+            Verdict::Reject(Violation::ForeignEntity)
+        );
+
+        let mut record = UserRecord::default();
+        assert_eq!(record.check(&cfg(), 0, &input, steer(false)), Verdict::Ignore);
+
+        // The sender's own character has died and is gone
+        let mut record = UserRecord::default();
+        let gone = Ownership {
+            entity: None,
+            avatar: None,
+            is_character: false,
+        };
+        assert_eq!(record.check(&cfg(), 0, &input, gone), Verdict::Ignore);
+    }
+
+    #[test]
+    fn inputs_are_capped_per_tick() {
+        let avatar = Entity::from_raw(1);
+        let input = PlayerInput::from_axes(0.0, 1.0);
+        let mut record = UserRecord::default();
+
+        assert_eq!(record.check(&cfg(), 0, &input, own(avatar)), Verdict::Apply);
+        assert_eq!(
+            record.check(&cfg(), 0, &input, own(avatar)),
+            Verdict::Reject(Violation::TooManyInputs)
+        );
+
+        // As `AntiCheat::start_tick` does
+        record.inputs = 0;
+        assert_eq!(record.check(&cfg(), 1, &input, own(avatar)), Verdict::Apply);
+    }
+
+    #[test]
+    fn attacks_in_consecutive_ticks_are_spam() {
+        let avatar = Entity::from_raw(1);
+        let attack = PlayerInput::from_axes(0.0, 0.0).with_attack(true);
+        let mut record = UserRecord::default();
+        let mut check = |tick| {
+            record.inputs = 0;
+            record.check(&cfg(), tick, &attack, own(avatar))
+        };
+
+        assert_eq!(check(10), Verdict::Apply);
+        assert_eq!(check(11), Verdict::Reject(Violation::AttackSpam));
+        assert_eq!(check(13), Verdict::Apply);
+
+        // Ticks wrap around
+        assert_eq!(check(Tick::MAX), Verdict::Apply);
+        assert_eq!(check(0), Verdict::Reject(Violation::AttackSpam));
+    }
+
+    #[test]
+    fn users_are_kicked_once_at_the_threshold() {
+        let mut record = UserRecord::default();
+        assert!(!record.record(&cfg()));
+        assert!(!record.record(&cfg()));
+        assert!(record.record(&cfg()));
+        assert!(!record.record(&cfg()));
+
+        // Lowering the threshold below a user's count kicks them on their next violation
+        let high = AntiCheatConfig {
+            kick_threshold: 10,
+            ..cfg()
+        };
+        let mut record = UserRecord::default();
+        assert!((0..5).all(|_| !record.record(&high)));
+        assert!(record.record(&cfg()));
+        assert!(!record.record(&cfg()));
+
+        // Never when disabled
+        let mut record = UserRecord::default();
+        let disabled = AntiCheatConfig {
+            kick_threshold: 0,
+            ..cfg()
+        };
+        assert!((0..100).all(|_| !record.record(&disabled)));
+    }
+}
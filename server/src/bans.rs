@@ -0,0 +1,234 @@
+//! Users and addresses refused at authentication, kept in a TOML file so that bans outlive the
+//! server. The file is rewritten whenever the list changes, and looks like
+//!
+//! ```toml
+//! [[bans]]
+//! name = "griefer"
+//! address = "203.0.113.7"
+//! reason = "team killing"
+//! expires = 1700000000
+//! ```
+
+use std::{
+    fmt, fs, io,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// Refuses a name, an address or both.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Ban {
+    pub name: Option<String>,
+    pub address: Option<IpAddr>,
+    pub reason: Option<String>,
+    /// When the ban lapses, in seconds since the Unix epoch. Permanent when unset.
+    pub expires: Option<u64>,
+}
+
+impl Ban {
+    pub fn new(name: Option<String>, address: Option<IpAddr>) -> Self {
+        Self {
+            name,
+            address,
+            reason: None,
+            expires: None,
+        }
+    }
+
+    /// Lifts the ban after `duration`, or never if `None`.
+    pub fn lasting(mut self, duration: Option<Duration>) -> Self {
+        self.expires = duration.map(|duration| now() + duration.as_secs());
+        self
+    }
+
+    pub fn because(mut self, reason: Option<String>) -> Self {
+        self.reason = reason;
+        self
+    }
+
+    fn matches(&self, name: &str, address: IpAddr) -> bool {
+        self.name.as_deref() == Some(name) || self.address == Some(address)
+    }
+
+    fn expired(&self, now: u64) -> bool {
+        self.expires.map_or(false, |expires| expires <= now)
+    }
+}
+
+impl fmt::Display for Ban {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let targets = self
+            .name
+            .iter()
+            .cloned()
+            .chain(self.address.iter().map(IpAddr::to_string))
+            .collect::<Vec<_>>();
+        write!(f, "{}", targets.join(" at "))?;
+
+        match self.expires {
+            Some(expires) => {
+                let left = expires.saturating_sub(now());
+                write!(f, ", {} left", format_duration(left))?;
+            }
+            None => write!(f, ", permanent")?,
+        }
+        if let Some(reason) = &self.reason {
+            write!(f, " ({reason})")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+struct BanFile {
+    bans: Vec<Ban>,
+}
+
+#[derive(Resource)]
+pub struct BanList {
+    bans: Vec<Ban>,
+    /// Where the list is saved. Bans only last until the server stops when this is unset.
+    path: Option<PathBuf>,
+}
+
+impl BanList {
+    /// Reads the bans at `path`, if any. A missing file is an empty list.
+    pub fn load(path: Option<PathBuf>) -> Result<Self, String> {
+        let Some(path) = path else {
+            return Ok(Self {
+                bans: Vec::new(),
+                path: None,
+            });
+        };
+
+        let file = match fs::read_to_string(&path) {
+            Ok(text) => toml::from_str::<BanFile>(&text)
+                .map_err(|err| format!("invalid bans in {}: {err}", path.display()))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => BanFile::default(),
+            Err(err) => return Err(format!("could not read {}: {err}", path.display())),
+        };
+
+        let mut list = Self {
+            bans: file.bans,
+            path: Some(path),
+        };
+        list.prune();
+        Ok(list)
+    }
+
+    /// The ban which refuses the user, if any.
+    pub fn check(&self, name: &str, address: IpAddr) -> Option<&Ban> {
+        let now = now();
+        self.bans
+            .iter()
+            .find(|ban| !ban.expired(now) && ban.matches(name, address))
+    }
+
+    pub fn bans(&self) -> &[Ban] {
+        &self.bans
+    }
+
+    pub fn add(&mut self, ban: Ban) {
+        info!("Banned {ban}");
+        self.bans.push(ban);
+        self.prune();
+        self.save();
+    }
+
+    /// Lifts every ban on `target`, a name or an address, returning how many there were.
+    pub fn remove(&mut self, target: &str) -> usize {
+        let address = target.parse::<IpAddr>().ok();
+        let before = self.bans.len();
+        self.bans.retain(|ban| {
+            ban.name.as_deref() != Some(target) && (address.is_none() || ban.address != address)
+        });
+
+        let removed = before - self.bans.len();
+        if removed > 0 {
+            info!("Unbanned {target}");
+            self.prune();
+            self.save();
+        }
+        removed
+    }
+
+    fn prune(&mut self) {
+        let now = now();
+        self.bans.retain(|ban| !ban.expired(now));
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.path else { return; };
+
+        if let Err(err) = write_bans(path, &self.bans) {
+            warn!("Could not save bans to {}: {err}", path.display());
+        }
+    }
+}
+
+fn write_bans(path: &Path, bans: &[Ban]) -> io::Result<()> {
+    let file = BanFile {
+        bans: bans.to_vec(),
+    };
+    let text = toml::to_string_pretty(&file)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+    // Write to a temporary file first, so that a crash never leaves half a list behind
+    let temp = path.with_extension("toml.tmp");
+    fs::write(&temp, text)?;
+    fs::rename(temp, path)
+}
+
+/// Reads the ban file named in the config. An unreadable file is reported and left alone, so that
+/// it is not overwritten by the next ban.
+pub fn load_bans(cfg: Res<Config>, mut commands: Commands) {
+    let list = BanList::load(cfg.network.ban_file.clone()).unwrap_or_else(|err| {
+        error!("Starting without bans: {err}");
+        BanList {
+            bans: Vec::new(),
+            path: None,
+        }
+    });
+
+    if let Some(path) = &list.path {
+        info!("Loaded {} bans from {}", list.bans.len(), path.display());
+    }
+    commands.insert_resource(list);
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+/// Parses a duration like `90s`, `30m`, `12h` or `7d`.
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let unit = match s.chars().last()? {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        _ => return None,
+    };
+    let count = s[..s.len() - 1].parse::<u64>().ok()?;
+
+    Some(Duration::from_secs(count.checked_mul(unit)?))
+}
+
+fn format_duration(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{secs}s"),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
@@ -0,0 +1,121 @@
+//! Inspects the replays a server saves with `--replay-dir`.
+//!
+//! `replay info <file>` summarizes a replay, and `replay verify <file>` re-simulates its inputs
+//! and reports every tick where the result differs from the recorded states, exiting with an
+//! error if there are any.
+
+use std::{collections::HashMap, path::PathBuf, process};
+
+use clap::{Parser, Subcommand};
+
+use shared::replay::{verify::verify, Event, Replay};
+
+/// How many mismatches `verify` prints before summarizing the rest.
+const MISMATCHES_SHOWN: usize = 20;
+
+#[derive(Parser)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Prints a replay's header, players and kills.
+    Info { file: PathBuf },
+    /// Checks that a replay's inputs reproduce its states.
+    Verify {
+        file: PathBuf,
+        /// How far a prediction may be off, in meters or meters per second.
+        #[arg(long, default_value_t = 0.05)]
+        tolerance: f32,
+    },
+}
+
+fn main() {
+    let args = Args::parse();
+    let file = match &args.command {
+        Command::Info { file } | Command::Verify { file, .. } => file,
+    };
+
+    let replay = Replay::load(file).unwrap_or_else(|err| {
+        eprintln!("error: could not read {}: {err}", file.display());
+        process::exit(1);
+    });
+    if replay.truncated {
+        eprintln!(
+            "warning: the replay is truncated after {} frames",
+            replay.frames.len()
+        );
+    }
+
+    match args.command {
+        Command::Info { .. } => info(&replay),
+        Command::Verify { tolerance, .. } => {
+            let report = verify(&replay, tolerance);
+            for mismatch in report.mismatches.iter().take(MISMATCHES_SHOWN) {
+                println!("{mismatch}");
+            }
+            if report.mismatches.len() > MISMATCHES_SHOWN {
+                println!(
+                    "... and {} more",
+                    report.mismatches.len() - MISMATCHES_SHOWN
+                );
+            }
+            println!(
+                "{} frames, {} inputs, largest position error {:.4} m, {} mismatches",
+                report.frames,
+                report.inputs,
+                report.max_error,
+                report.mismatches.len()
+            );
+
+            if !report.mismatches.is_empty() {
+                process::exit(1);
+            }
+        }
+    }
+}
+
+fn info(replay: &Replay) {
+    let header = &replay.header;
+    println!("room:    {}", header.room);
+    println!("map:     {}", header.map);
+    println!("started: {} (Unix time)", header.started);
+
+    let ticks = match (replay.frames.first(), replay.frames.last()) {
+        (Some(first), Some(last)) => last.tick.wrapping_sub(first.tick) as u32 + 1,
+        _ => 0,
+    };
+    println!("frames:  {} over {ticks} ticks", replay.frames.len());
+
+    let mut names = HashMap::new();
+    // Which user steered each body, to tell players' avatars from NPCs
+    let mut avatars = HashMap::new();
+    for frame in replay.frames.iter() {
+        for event in frame.events.iter() {
+            match event {
+                Event::Player { user, name } => {
+                    names.insert(*user, name.as_str());
+                    println!("player:  {name}");
+                }
+                Event::Input { user, body, .. } => {
+                    avatars.insert(*body, *user);
+                }
+                Event::Kill { attacker, victim } => {
+                    let name = |user| names.get(user).copied().unwrap_or("?");
+                    let victim = match avatars.get(victim) {
+                        Some(user) => name(user).to_owned(),
+                        None => format!("body {victim}"),
+                    };
+                    println!(
+                        "kill:    tick {}, {} killed {victim}",
+                        frame.tick,
+                        name(attacker)
+                    );
+                }
+                _ => {}
+            }
+        }
+    }
+}
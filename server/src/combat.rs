@@ -0,0 +1,178 @@
+//! Resolving attacks between characters.
+//!
+//! Attacking and dying are replicated as each character's [`CharacterAction`] so that clients can
+//! animate them. A killed character is taken out of the game straight away, but its body is left
+//! lying where it fell for a moment before it is despawned.
+
+use bevy::{prelude::*, utils::HashMap};
+use bevy_rapier2d::prelude::*;
+use naia_bevy_server::{RoomKey, Server, UserKey};
+
+use shared::{
+    channels::GameMessageChannel,
+    components::{Action, CharacterAction, MatchPhase, ATTACK_SECS},
+    messages::EntityAssignment,
+};
+
+use crate::{config::Config, replay::Recorder, resources::UserAvatarMapping, rooms::RoomManager};
+
+/// How long a killed character's body lies on the ground, in seconds.
+const CORPSE_SECS: f32 = 1.5;
+
+/// Sent when a user's input asks to attack this tick.
+pub struct AttackEvent {
+    pub attacker: UserKey,
+}
+
+/// When each user last attacked, in seconds since startup.
+#[derive(Default, Resource)]
+pub struct AttackCooldowns(HashMap<UserKey, f32>);
+
+/// Puts an attacking character back to [`Action::Idle`] when it runs out.
+#[derive(Component)]
+pub struct AttackTimer(Timer);
+
+/// The body of a killed character, despawned when its timer runs out.
+#[derive(Component)]
+pub struct Corpse {
+    pub room: RoomKey,
+    timer: Timer,
+}
+
+/// Who was struck by an attack.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Victim {
+    User(UserKey),
+    Npc(Entity),
+}
+
+/// Kills the character nearest to each attacker, if it is within reach.
+///
+/// Killing one's target scores a kill and passes the victim's target on to the attacker. Killing
+/// anyone else, NPCs included, still kills them, but counts as a wrong-target kill against the
+/// attacker.
+pub fn resolve_attacks(
+    mut reader: EventReader<AttackEvent>,
+    time: Res<Time>,
+    cfg: Res<Config>,
+    mut cooldowns: ResMut<AttackCooldowns>,
+    mut recorder: ResMut<Recorder>,
+    mut rooms: ResMut<RoomManager>,
+    mut users_avatars: ResMut<UserAvatarMapping>,
+    transform_query: Query<&Transform>,
+    mut action_query: Query<&mut CharacterAction>,
+    mut server: Server,
+    mut commands: Commands,
+) {
+    let now = time.elapsed_seconds();
+
+    for AttackEvent { attacker } in reader.iter() {
+        if let Some(last) = cooldowns.0.get(attacker) {
+            if now - last < cfg.gameplay.attack_cooldown_secs {
+                continue;
+            }
+        }
+
+        let Some(room) = rooms.room_of_mut(attacker) else { continue; };
+        if room.phase != MatchPhase::InProgress {
+            continue;
+        }
+
+        let Some(avatar) = users_avatars.get_by_user(attacker) else { continue; };
+        let Ok(origin) = transform_query
+            .get(*avatar)
+            .map(|t| t.translation.truncate())
+        else {
+            continue;
+        };
+
+        cooldowns.0.insert(*attacker, now);
+
+        if let Ok(mut action) = action_query.get_mut(*avatar) {
+            *action.action = Action::Attacking;
+            commands
+                .entity(*avatar)
+                .insert(AttackTimer(Timer::from_seconds(ATTACK_SECS, TimerMode::Once)));
+        }
+
+        let users = room
+            .users
+            .iter()
+            .filter(|user| *user != attacker)
+            .filter_map(|user| Some((Victim::User(*user), *users_avatars.get_by_user(user)?)));
+        let npcs = room.npcs.iter().map(|npc| (Victim::Npc(*npc), *npc));
+
+        let victim = users
+            .chain(npcs)
+            .filter_map(|(victim, entity)| {
+                let pos = transform_query.get(entity).ok()?.translation.truncate();
+                Some((victim, entity, pos.distance(origin)))
+            })
+            .filter(|(_, _, distance)| *distance <= cfg.gameplay.attack_range_m)
+            .min_by(|a, b| a.2.total_cmp(&b.2));
+        let Some((victim, victim_entity, _)) = victim else { continue; };
+
+        recorder.kill(room.key, *attacker, victim_entity);
+
+        let score = room.scores.entry(*attacker).or_default();
+        if matches!(victim, Victim::User(user) if room.targets.target_of(attacker) == Some(user)) {
+            score.kills += 1;
+        } else {
+            score.wrong_kills += 1;
+        }
+
+        match victim {
+            Victim::User(user) => {
+                room.scores.entry(user).or_default().deaths += 1;
+                room.targets.remove(&user);
+                users_avatars.remove_by_user(&user);
+
+                let mut unassign = EntityAssignment::new(false);
+                unassign.entity.set(&server, &victim_entity);
+                server.send_message::<GameMessageChannel, EntityAssignment>(&user, &unassign);
+            }
+            Victim::Npc(npc) => {
+                room.npcs.retain(|entity| *entity != npc);
+            }
+        }
+
+        if let Ok(mut action) = action_query.get_mut(victim_entity) {
+            *action.action = Action::Dead;
+        }
+        commands
+            .entity(victim_entity)
+            .remove::<(RigidBody, Collider)>()
+            .insert(Velocity::zero())
+            .insert(Corpse {
+                room: room.key,
+                timer: Timer::from_seconds(CORPSE_SECS, TimerMode::Once),
+            });
+    }
+}
+
+/// Ends attacks and removes corpses once their time is up.
+pub fn update_actions(
+    time: Res<Time>,
+    mut attack_query: Query<(Entity, &mut AttackTimer, &mut CharacterAction)>,
+    mut corpse_query: Query<(Entity, &mut Corpse)>,
+    mut server: Server,
+    mut commands: Commands,
+) {
+    for (entity, mut timer, mut action) in attack_query.iter_mut() {
+        if !timer.0.tick(time.delta()).finished() {
+            continue;
+        }
+
+        if *action.action == Action::Attacking {
+            *action.action = Action::Idle;
+        }
+        commands.entity(entity).remove::<AttackTimer>();
+    }
+
+    for (entity, mut corpse) in corpse_query.iter_mut() {
+        if corpse.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+            server.room_mut(&corpse.room).remove_entity(&entity);
+        }
+    }
+}
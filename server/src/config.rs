@@ -0,0 +1,458 @@
+//! The server's configuration, read from an optional TOML file with command line flags on top.
+//!
+//! Every value has a default, so a file only needs the values it changes, e.g.
+//!
+//! ```toml
+//! [network]
+//! transport = "both"
+//! addr = "0.0.0.0:2000"
+//! webrtc_addr = "0.0.0.0:2001"
+//! udp_addr = "0.0.0.0:2002"
+//! ban_file = "bans.toml"
+//! metrics_addr = "127.0.0.1:9100"
+//! replay_dir = "replays"
+//!
+//! [[network.rooms]]
+//! name = "main"
+//! max_players = 8
+//! map = "arena"
+//!
+//! [gameplay]
+//! win_condition = "kills:5"
+//! player_speed = 1.2
+//!
+//! [scoping]
+//! view_radius_m = 12.0
+//!
+//! [anti_cheat]
+//! kick_threshold = 50
+//! ```
+//!
+//! While the server runs, [`reload_config`] watches the file and applies changes to gameplay,
+//! scoping and anti-cheat values straight away. Network values are only read at startup.
+
+use std::{
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use bevy::{prelude::*, utils::HashSet};
+use naia_bevy_server::Server;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+#[cfg(feature = "link-conditioner")]
+use shared::link_condition::LinkCondition;
+use shared::{channels::GameMessageChannel, maps::MapLayout, messages::GameplaySettings};
+
+use crate::{match_state::WinCondition, rooms::RoomSettings, transport::Transport, Args};
+
+/// How often the config file is checked for changes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Resource)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub network: NetworkConfig,
+    pub gameplay: GameplayConfig,
+    pub scoping: ScopingConfig,
+    pub anti_cheat: AntiCheatConfig,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+    pub transport: Transport,
+    /// The WebRTC signaling address.
+    pub addr: Option<SocketAddr>,
+    /// The WebRTC data address.
+    pub webrtc_addr: Option<SocketAddr>,
+    /// The address native clients connect to over UDP.
+    pub udp_addr: Option<SocketAddr>,
+
+    /// The player cap of the default room.
+    pub max_players: u8,
+    /// The password of the default room.
+    pub password: Option<String>,
+    /// The rooms to host. When empty, a single room named "main" is created from `max_players` and
+    /// `password`.
+    pub rooms: Vec<RoomSettings>,
+    /// Where bans are kept. Bans only last until the server stops when unset.
+    pub ban_file: Option<PathBuf>,
+    /// Where to serve Prometheus metrics over HTTP. Not served when unset.
+    pub metrics_addr: Option<SocketAddr>,
+    /// Where to save a replay of every match. Matches are not recorded when unset.
+    pub replay_dir: Option<PathBuf>,
+
+    /// Only settable from the command line, as it is meant for testing.
+    #[cfg(feature = "link-conditioner")]
+    #[serde(skip)]
+    pub link_condition: LinkCondition,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            transport: Transport::WebRtc,
+            addr: None,
+            webrtc_addr: None,
+            udp_addr: None,
+            max_players: 8,
+            password: None,
+            rooms: Vec::new(),
+            ban_file: None,
+            metrics_addr: None,
+            replay_dir: None,
+            #[cfg(feature = "link-conditioner")]
+            link_condition: LinkCondition::Off,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameplayConfig {
+    /// How many connected players a room needs before its countdown may start.
+    pub min_players: u8,
+    /// How long the countdown before a match lasts, in seconds.
+    pub countdown_secs: u16,
+    pub win_condition: WinCondition,
+    /// The time limit of a round, in seconds.
+    pub round_secs: u16,
+    /// How many computer-controlled characters to add to each match as cover.
+    pub npcs: u16,
+    /// How long the summary after a match is shown before returning to the lobby, in seconds.
+    pub post_game_secs: u16,
+
+    /// Players' top speed, in meters per second. Clients predict their own movement with this, so
+    /// they are told whenever it changes.
+    pub player_speed: f32,
+    /// NPCs' top speed, in meters per second. The same as players' when unset, so that players
+    /// cannot be picked out of the crowd by how fast they move.
+    pub npc_speed: Option<f32>,
+    /// How far from the attacker's center a victim's center may be, in meters.
+    pub attack_range_m: f32,
+    /// The minimum time between two attacks by the same user, in seconds.
+    pub attack_cooldown_secs: f32,
+    /// The minimum time between two changes of disguise by the same user, in seconds.
+    pub disguise_cooldown_secs: f32,
+    /// How close a hunter must be to their target to see a change of disguise, in meters.
+    pub sight_radius_m: f32,
+
+    /// Points scored for killing one's target.
+    pub kill_points: i16,
+    /// Points lost for killing anyone other than one's target.
+    pub wrong_kill_penalty: i16,
+}
+
+impl GameplayConfig {
+    /// NPCs' top speed, in meters per second.
+    pub fn npc_speed(&self) -> f32 {
+        self.npc_speed.unwrap_or(self.player_speed)
+    }
+}
+
+impl Default for GameplayConfig {
+    fn default() -> Self {
+        Self {
+            min_players: 2,
+            countdown_secs: 5,
+            win_condition: WinCondition::HighestScore,
+            round_secs: 300,
+            npcs: 8,
+            post_game_secs: 10,
+            player_speed: 1.0,
+            npc_speed: None,
+            attack_range_m: 1.5,
+            attack_cooldown_secs: 1.0,
+            disguise_cooldown_secs: 10.0,
+            sight_radius_m: 6.0,
+            kill_points: 1,
+            wrong_kill_penalty: 1,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScopingConfig {
+    /// Characters further than this from a user's own character are not replicated to them, in
+    /// meters. Users without a character, and everything which is not a character, always see the
+    /// whole room. Unlimited when unset.
+    pub view_radius_m: Option<f32>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AntiCheatConfig {
+    /// Inputs from one user beyond this many in a single tick are dropped as violations. Clients
+    /// send one per tick.
+    pub max_inputs_per_tick: u8,
+    /// How many violations get a user kicked. Never when 0.
+    pub kick_threshold: u32,
+}
+
+impl Default for AntiCheatConfig {
+    fn default() -> Self {
+        Self {
+            max_inputs_per_tick: 1,
+            kick_threshold: 20,
+        }
+    }
+}
+
+impl Config {
+    /// Reads the file given with `--config`, if any, and applies the other flags on top of it.
+    pub fn load(args: &Args) -> Result<Self, String> {
+        let mut config = match &args.config {
+            Some(path) => Self::read(path)?,
+            None => Self::default(),
+        };
+
+        config.apply_args(args);
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn read(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("could not read {}: {err}", path.display()))?;
+
+        toml::from_str(&text).map_err(|err| format!("invalid config in {}: {err}", path.display()))
+    }
+
+    fn apply_args(&mut self, args: &Args) {
+        let network = &mut self.network;
+        if let Some(transport) = args.transport {
+            network.transport = transport;
+        }
+        network.addr = args.addr.or(network.addr);
+        network.webrtc_addr = args.webrtc_addr.or(network.webrtc_addr);
+        network.udp_addr = args.udp_addr.or(network.udp_addr);
+        if let Some(max_players) = args.max_players {
+            network.max_players = max_players;
+        }
+        if args.password.is_some() {
+            network.password = args.password.clone();
+        }
+        if !args.rooms.is_empty() {
+            network.rooms = args.rooms.clone();
+        }
+        if args.ban_file.is_some() {
+            network.ban_file = args.ban_file.clone();
+        }
+        network.metrics_addr = args.metrics_addr.or(network.metrics_addr);
+        if args.replay_dir.is_some() {
+            network.replay_dir = args.replay_dir.clone();
+        }
+        #[cfg(feature = "link-conditioner")]
+        if let Some(condition) = args.link_condition {
+            network.link_condition = condition;
+        }
+
+        let gameplay = &mut self.gameplay;
+        if let Some(min_players) = args.min_players {
+            gameplay.min_players = min_players;
+        }
+        if let Some(countdown_secs) = args.countdown_secs {
+            gameplay.countdown_secs = countdown_secs;
+        }
+        if let Some(win_condition) = args.win_condition {
+            gameplay.win_condition = win_condition;
+        }
+        if let Some(round_secs) = args.round_secs {
+            gameplay.round_secs = round_secs;
+        }
+        if let Some(npcs) = args.npcs {
+            gameplay.npcs = npcs;
+        }
+        if let Some(post_game_secs) = args.post_game_secs {
+            gameplay.post_game_secs = post_game_secs;
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let network = &self.network;
+        if network.transport.webrtc() && (network.addr.is_none() || network.webrtc_addr.is_none()) {
+            return Err("WebRTC needs both `addr` and `webrtc_addr`".to_owned());
+        }
+        if network.transport.udp() && network.udp_addr.is_none() {
+            return Err("UDP needs `udp_addr`".to_owned());
+        }
+
+        let mut names = HashSet::new();
+        for room in network.rooms.iter() {
+            if !names.insert(&room.name) {
+                return Err(format!("more than one room is named {}", room.name));
+            }
+            if MapLayout::by_name(&room.map).is_none() {
+                return Err(format!(
+                    "unknown map `{}` for room {}, expected one of {:?}",
+                    room.map,
+                    room.name,
+                    MapLayout::names()
+                ));
+            }
+        }
+
+        let gameplay = &self.gameplay;
+        if gameplay.min_players < 2 {
+            return Err("`min_players` must be at least 2, or nobody would have a target".to_owned());
+        }
+        if gameplay.round_secs == 0 {
+            return Err("`round_secs` must be positive".to_owned());
+        }
+        // Written so that NaN fails too
+        for speed in [gameplay.player_speed, gameplay.npc_speed()] {
+            if !(speed > 0.0 && speed.is_finite()) {
+                return Err("speeds must be positive".to_owned());
+            }
+        }
+        if gameplay.kill_points < 0 || gameplay.wrong_kill_penalty < 0 {
+            return Err("`kill_points` and `wrong_kill_penalty` must not be negative".to_owned());
+        }
+        if self.anti_cheat.max_inputs_per_tick == 0 {
+            return Err("`max_inputs_per_tick` must be at least 1".to_owned());
+        }
+
+        Ok(())
+    }
+
+    /// A copy of this config with one gameplay, scoping or anti-cheat value changed, e.g.
+    /// `with_value("gameplay.player_speed", "1.5")`. Strings may be given without quotes, and
+    /// `none` unsets an optional value.
+    pub fn with_value(&self, key: &str, value: &str) -> Result<Self, String> {
+        let Some((section, name)) = key.split_once('.') else {
+            return Err(format!("expected `<section>.<name>`, got `{key}`"));
+        };
+        let value = match value {
+            "none" => None,
+            _ => Some(
+                toml::from_str::<toml::Table>(&format!("value = {value}"))
+                    .ok()
+                    .and_then(|mut table| table.remove("value"))
+                    .unwrap_or_else(|| toml::Value::String(value.to_owned())),
+            ),
+        };
+
+        let mut new = self.clone();
+        match section {
+            "gameplay" => new.gameplay = with_field(&self.gameplay, name, value)?,
+            "scoping" => new.scoping = with_field(&self.scoping, name, value)?,
+            "anti_cheat" => new.anti_cheat = with_field(&self.anti_cheat, name, value)?,
+            "network" => return Err("network settings cannot change while running".to_owned()),
+            _ => return Err(format!("unknown section `{section}`")),
+        }
+        new.validate()?;
+
+        Ok(new)
+    }
+
+    /// What clients need to know to predict their own movement.
+    pub fn gameplay_settings(&self) -> GameplaySettings {
+        GameplaySettings {
+            player_speed: self.gameplay.player_speed,
+        }
+    }
+
+    /// Takes the gameplay, scoping and anti-cheat values of `new`, telling every user about changes
+    /// which affect prediction. Network values cannot change while running, so differences there
+    /// are only logged.
+    pub fn update(&mut self, mut new: Config, server: &mut Server) {
+        if new.network != self.network {
+            warn!("Network settings only take effect after a restart");
+            new.network = self.network.clone();
+        }
+
+        if new.gameplay != self.gameplay {
+            info!("Gameplay settings are now {:?}", new.gameplay);
+        }
+        if new.scoping != self.scoping {
+            info!("Scoping settings are now {:?}", new.scoping);
+        }
+        if new.anti_cheat != self.anti_cheat {
+            info!("Anti-cheat settings are now {:?}", new.anti_cheat);
+        }
+
+        let prediction_changed = new.gameplay_settings() != self.gameplay_settings();
+        *self = new;
+
+        if prediction_changed {
+            let settings = self.gameplay_settings();
+            for user_key in server.user_keys() {
+                server.send_message::<GameMessageChannel, GameplaySettings>(&user_key, &settings);
+            }
+        }
+    }
+}
+
+/// Round-trips a config section through TOML to change or remove one of its fields.
+fn with_field<T: Serialize + DeserializeOwned>(
+    section: &T,
+    name: &str,
+    value: Option<toml::Value>,
+) -> Result<T, String> {
+    let mut table = toml::Value::try_from(section).map_err(|err| err.to_string())?;
+    let Some(fields) = table.as_table_mut() else {
+        return Err("config sections are tables".to_owned());
+    };
+
+    match value {
+        Some(value) => fields.insert(name.to_owned(), value),
+        None => fields.remove(name),
+    };
+
+    table
+        .try_into()
+        .map_err(|err| format!("invalid value for `{name}`: {err}"))
+}
+
+/// Notices when the config file changes.
+#[derive(Resource)]
+pub struct ConfigWatcher {
+    timer: Timer,
+    modified: Option<SystemTime>,
+}
+
+impl ConfigWatcher {
+    pub fn new(args: &Args) -> Self {
+        Self {
+            timer: Timer::new(RELOAD_INTERVAL, TimerMode::Repeating),
+            modified: args.config.as_deref().and_then(modified),
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// Reloads the config file whenever it is modified. An invalid file is reported and ignored.
+pub fn reload_config(
+    time: Res<Time>,
+    args: Res<Args>,
+    mut watcher: ResMut<ConfigWatcher>,
+    mut config: ResMut<Config>,
+    mut server: Server,
+) {
+    let Some(path) = &args.config else { return; };
+    if !watcher.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    let modified = modified(path);
+    if modified == watcher.modified {
+        return;
+    }
+    watcher.modified = modified;
+
+    match Config::load(&args) {
+        Ok(new) => {
+            info!("Reloading {}", path.display());
+            config.update(new, &mut server);
+        }
+        Err(err) => warn!("Keeping the current config: {err}"),
+    }
+}
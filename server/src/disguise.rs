@@ -0,0 +1,181 @@
+//! Disguises. Every character is given a random [`Look`] from the same pool, and players can change
+//! theirs at the map's wardrobes to shake off their hunter.
+//!
+//! Hunters are told their target's look when the target is assigned. From then on they only learn
+//! of a new disguise by seeing it, i.e. by coming within the configured sight radius of their
+//! target, so a target who changes out of sight leaves their hunter looking for the wrong outfit.
+
+use bevy::{prelude::*, utils::HashMap};
+use naia_bevy_server::{events::MessageEvents, CommandsExt, Random, Server, UserKey};
+
+use shared::{
+    channels::{GameMessageChannel, PlayerActionChannel},
+    components::{Appearance, Look, MatchPhase, PhysicsStateSync, WardrobeEntity},
+    maps::WARDROBE_REACH_M,
+    messages::{ChangeDisguise, TargetHint},
+};
+
+use crate::{
+    config::Config,
+    resources::UserAvatarMapping,
+    rooms::{GameRoom, RoomManager},
+};
+
+/// The number of looks in the pool.
+const LOOKS: u32 = Look::OUTFITS as u32 * Look::PALETTES as u32;
+
+/// When each user last changed disguise, in seconds since startup.
+#[derive(Default, Resource)]
+pub struct DisguiseCooldowns(HashMap<UserKey, f32>);
+
+/// What each hunter has seen of their target.
+#[derive(Default, Resource)]
+pub struct TargetHints {
+    /// Each hunter's target, and the target's look when the hunter last saw them.
+    known: HashMap<UserKey, (UserKey, Look)>,
+}
+
+/// The look at `index` in the pool, which is ordered by outfit and then palette.
+fn look_at(index: u32) -> Look {
+    Look {
+        outfit: (index / Look::PALETTES as u32) as u8,
+        palette: (index % Look::PALETTES as u32) as u8,
+    }
+}
+
+fn index_of(look: Look) -> u32 {
+    look.outfit as u32 * Look::PALETTES as u32 + look.palette as u32
+}
+
+/// Any look from the pool. Players and NPCs are dressed alike with this.
+pub fn random_look() -> Look {
+    look_at(Random::gen_range_u32(0, LOOKS))
+}
+
+/// Any look from the pool other than `current`.
+pub fn new_look(current: Look) -> Look {
+    let index = Random::gen_range_u32(0, LOOKS - 1);
+    if index >= index_of(current) {
+        look_at(index + 1)
+    } else {
+        look_at(index)
+    }
+}
+
+pub fn at_wardrobe(room: &GameRoom, position: Vec2) -> bool {
+    room.map
+        .wardrobes
+        .iter()
+        .any(|(x, y)| position.distance(Vec2::new(*x, *y)) <= WARDROBE_REACH_M)
+}
+
+/// Spawns the room's wardrobes. They have no body, as characters walk up to them rather than
+/// bumping into them.
+pub fn spawn_wardrobes(room: &GameRoom, server: &mut Server, commands: &mut Commands) {
+    for (x, y) in room.map.wardrobes.iter() {
+        let entity = commands
+            .spawn_empty()
+            .enable_replication(server)
+            .insert(WardrobeEntity)
+            .insert(PhysicsStateSync::new_complete(0.0, 0.0, 0.0, *x, *y, 0.0))
+            .id();
+
+        server.room_mut(&room.key).add_entity(&entity);
+    }
+}
+
+/// Handles [`ChangeDisguise`] requests from players standing at a wardrobe.
+pub fn disguise_events(
+    mut event_reader: EventReader<MessageEvents>,
+    time: Res<Time>,
+    cfg: Res<Config>,
+    mut cooldowns: ResMut<DisguiseCooldowns>,
+    rooms: Res<RoomManager>,
+    users_avatars: Res<UserAvatarMapping>,
+    mut character_query: Query<(&Transform, &mut Appearance)>,
+) {
+    let now = time.elapsed_seconds();
+
+    for events in event_reader.iter() {
+        for (user_key, _) in events.read::<PlayerActionChannel, ChangeDisguise>() {
+            if let Some(last) = cooldowns.0.get(&user_key) {
+                if now - last < cfg.gameplay.disguise_cooldown_secs {
+                    continue;
+                }
+            }
+
+            let Some(room) = rooms.room_of(&user_key) else { continue; };
+            if room.phase != MatchPhase::InProgress {
+                continue;
+            }
+
+            let Some(avatar) = users_avatars.get_by_user(&user_key) else { continue; };
+            let Ok((transform, mut appearance)) = character_query.get_mut(*avatar) else {
+                continue;
+            };
+            if !at_wardrobe(room, transform.translation.truncate()) {
+                continue;
+            }
+
+            cooldowns.0.insert(user_key, now);
+            *appearance.look = new_look(*appearance.look);
+        }
+    }
+}
+
+/// Tells each hunter their target's look when they are given a new target, and again whenever
+/// they see their target in a different one. This is all hunters are told of their targets, so
+/// that a disguise cannot be seen through. Hunters who lose their target are told so.
+pub fn update_target_hints(
+    cfg: Res<Config>,
+    rooms: Res<RoomManager>,
+    users_avatars: Res<UserAvatarMapping>,
+    character_query: Query<(&Transform, &Appearance)>,
+    mut hints: ResMut<TargetHints>,
+    mut server: Server,
+) {
+    let position = |user: &UserKey| {
+        let avatar = users_avatars.get_by_user(user)?;
+        let (transform, _) = character_query.get(*avatar).ok()?;
+        Some(transform.translation.truncate())
+    };
+
+    let mut known = HashMap::new();
+    for room in rooms.rooms() {
+        if room.phase != MatchPhase::InProgress {
+            continue;
+        }
+
+        for (hunter, target) in room.targets.assignments() {
+            let Some(avatar) = users_avatars.get_by_user(&target) else { continue; };
+            let Ok((transform, appearance)) = character_query.get(*avatar) else { continue; };
+            let look = *appearance.look;
+
+            let in_sight = position(&hunter).map_or(false, |hunter| {
+                hunter.distance(transform.translation.truncate()) <= cfg.gameplay.sight_radius_m
+            });
+            let seen = match hints.known.get(&hunter) {
+                Some((known_target, known_look)) if *known_target == target && !in_sight => {
+                    *known_look
+                }
+                _ => look,
+            };
+            known.insert(hunter, (target, seen));
+        }
+    }
+
+    for (hunter, (target, look)) in known.iter() {
+        if hints.known.get(hunter) != Some(&(*target, *look)) && server.user_exists(hunter) {
+            let hint = TargetHint { look: Some(*look) };
+            server.send_message::<GameMessageChannel, TargetHint>(hunter, &hint);
+        }
+    }
+    for hunter in hints.known.keys() {
+        if !known.contains_key(hunter) && server.user_exists(hunter) {
+            server
+                .send_message::<GameMessageChannel, TargetHint>(hunter, &TargetHint { look: None });
+        }
+    }
+
+    hints.known = known;
+}
@@ -0,0 +1,300 @@
+//! The game server, split from its binary so that tests can build and drive the same [`App`] over
+//! their own transport.
+
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
+
+use bevy::{
+    app::ScheduleRunnerSettings,
+    diagnostic::{
+        DiagnosticsPlugin, EntityCountDiagnosticsPlugin, FrameTimeDiagnosticsPlugin,
+        LogDiagnosticsPlugin,
+    },
+    prelude::*,
+    scene::ScenePlugin,
+};
+use bevy_rapier2d::prelude::*;
+use clap::Parser;
+use naia_bevy_server::{Plugin as ServerPlugin, ReceiveEvents, Server, ServerConfig};
+
+#[cfg(feature = "link-conditioner")]
+use shared::link_condition::LinkCondition;
+use shared::protocol;
+
+use admin::{
+    admin_bans, admin_config, admin_match, admin_status, admin_users, count_ticks,
+    receive_admin_commands, AdminConsole, AdminEvent, TickRate,
+};
+use anti_cheat::AntiCheat;
+use bans::load_bans;
+use combat::{resolve_attacks, update_actions, AttackCooldowns, AttackEvent};
+use config::{reload_config, Config, ConfigWatcher};
+use disguise::{
+    disguise_events, spawn_wardrobes, update_target_hints, DisguiseCooldowns, TargetHints,
+};
+use logging::LogFormat;
+use match_state::{ready_events, spawn_match_state, update_match_phases};
+use metrics::{
+    observe_tick_duration, serve_metrics, start_update_timer, update_metrics, Metrics, UpdateStart,
+};
+use navigation::update_navigation;
+use npc::steer_npcs;
+use replay::{record_matches, Recorder};
+use resources::{UserAvatarMapping, UserNameMapping};
+use rooms::{spawn_walls, RoomManager, RoomSettings, MAX_ROOMS};
+use server_event_handling::{
+    auth_events, connect_events, disconnect_events, error_events, sync_physics, tick_events,
+};
+use spectating::{spectate_events, update_spectators, SpectatorFeed};
+use stats::update_player_stats;
+use transport::{prune_routes, Routes, Transport};
+
+pub use match_state::WinCondition;
+
+pub mod admin;
+pub mod anti_cheat;
+pub mod bans;
+mod combat;
+pub mod config;
+mod disguise;
+pub mod logging;
+mod match_state;
+pub mod metrics;
+mod navigation;
+mod npc;
+mod replay;
+pub mod resources;
+mod rooms;
+mod server_event_handling;
+mod spectating;
+mod stats;
+pub mod transport;
+
+/// Command line flags. Each of these overrides the same value in the config file.
+#[derive(Parser, Resource)]
+pub struct Args {
+    /// A TOML config file, which is watched for changes to gameplay values while running.
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// `webrtc`, `udp` or `both`.
+    #[arg(short, long)]
+    transport: Option<Transport>,
+
+    /// The WebRTC signaling address.
+    #[arg(short, long)]
+    addr: Option<SocketAddr>,
+    /// The WebRTC data address.
+    #[arg(short, long)]
+    webrtc_addr: Option<SocketAddr>,
+    /// The address native clients connect to over UDP.
+    #[arg(short, long)]
+    udp_addr: Option<SocketAddr>,
+
+    /// The player cap of the default room.
+    #[arg(short, long)]
+    max_players: Option<u8>,
+    /// The password of the default room.
+    #[arg(short, long)]
+    password: Option<String>,
+
+    /// Where bans are kept.
+    #[arg(long)]
+    ban_file: Option<PathBuf>,
+
+    /// A room to host, as `name:max_players[:map[:password]]`. May be given several times. When
+    /// omitted, a single room named "main" is created from `max_players` and `password`.
+    #[arg(long = "room")]
+    rooms: Vec<RoomSettings>,
+
+    /// How many connected players a room needs before its countdown may start.
+    #[arg(long)]
+    min_players: Option<u8>,
+    /// How long the countdown before a match lasts, in seconds.
+    #[arg(long)]
+    countdown_secs: Option<u16>,
+    /// How a round is won: `kills:<n>`, `last-standing` or `score`.
+    #[arg(long)]
+    win_condition: Option<WinCondition>,
+    /// The time limit of a round, in seconds.
+    #[arg(long)]
+    round_secs: Option<u16>,
+    /// How many computer-controlled characters to add to each match as cover.
+    #[arg(long)]
+    npcs: Option<u16>,
+    /// How long the summary after a match is shown before returning to the lobby, in seconds.
+    #[arg(long)]
+    post_game_secs: Option<u16>,
+
+    /// `text` or `json`.
+    #[arg(long, default_value = "text")]
+    log_format: LogFormat,
+
+    /// Periodically logs the frame time and entity count, e.g. while load testing.
+    #[arg(long)]
+    log_diagnostics: bool,
+
+    /// Where to serve Prometheus metrics over HTTP, e.g. `127.0.0.1:9100`.
+    #[arg(long)]
+    metrics_addr: Option<SocketAddr>,
+
+    /// Where to save a replay of every match.
+    #[arg(long)]
+    replay_dir: Option<PathBuf>,
+
+    /// A Unix socket to accept admin commands on, besides stdin.
+    #[arg(long)]
+    admin_socket: Option<PathBuf>,
+
+    /// Simulates a poor connection on incoming packets: `off`, `good`, `average`, `poor` or
+    /// `<latency_ms>:<jitter_ms>:<loss>`.
+    #[cfg(feature = "link-conditioner")]
+    #[arg(long)]
+    link_condition: Option<LinkCondition>,
+}
+
+impl Args {
+    pub fn log_format(&self) -> LogFormat {
+        self.log_format
+    }
+}
+
+/// Builds the server's [`App`] with all of its game logic, but neither a logger nor a socket. The
+/// caller adds a startup system which calls [`Server::listen`], such as [`transport::listen`],
+/// before running or updating it.
+pub fn build_app(args: Args, config: Config) -> App {
+    let mut cfg = RapierConfiguration::default();
+    cfg.gravity = Vec2::new(0.0, 0.0);
+
+    let log_diagnostics = args.log_diagnostics;
+
+    let mut app = App::default();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(AssetPlugin::default())
+        .add_plugin(TransformPlugin)
+        .add_plugin(HierarchyPlugin)
+        .add_plugin(DiagnosticsPlugin)
+        .add_plugin(ScenePlugin)
+        .add_plugin(ServerPlugin::new(ServerConfig::default(), protocol()))
+        .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
+        .insert_resource(cfg)
+        .insert_resource(ScheduleRunnerSettings::run_loop(Duration::from_millis(3)))
+        .insert_resource(ConfigWatcher::new(&args))
+        .insert_resource(args)
+        .insert_resource(config)
+        .add_startup_system(init)
+        .add_startup_system(load_bans)
+        .init_resource::<Metrics>()
+        .init_resource::<Routes>()
+        .init_resource::<UpdateStart>()
+        .add_startup_system(serve_metrics)
+        .add_system(start_update_timer.in_base_set(CoreSet::First))
+        .add_system(update_metrics.in_base_set(CoreSet::Last))
+        .add_system(prune_routes.in_base_set(CoreSet::Last))
+        .add_system(observe_tick_duration.in_base_set(CoreSet::Last))
+        .add_systems(
+            (
+                auth_events,
+                connect_events,
+                disconnect_events,
+                error_events,
+                ready_events,
+                spectate_events,
+                disguise_events,
+                tick_events,
+            )
+                .chain()
+                .in_set(ReceiveEvents),
+        )
+        .add_event::<AttackEvent>()
+        .init_resource::<AttackCooldowns>()
+        .init_resource::<AntiCheat>()
+        .add_system(resolve_attacks.after(ReceiveEvents))
+        .add_system(update_actions.after(resolve_attacks))
+        .add_system(update_match_phases.after(resolve_attacks))
+        .add_system(update_player_stats.after(update_match_phases))
+        .init_resource::<DisguiseCooldowns>()
+        .init_resource::<TargetHints>()
+        .add_system(update_target_hints.after(update_match_phases))
+        .init_resource::<SpectatorFeed>()
+        .add_system(update_spectators.after(update_match_phases))
+        .add_system(update_navigation)
+        .add_system(
+            steer_npcs
+                .after(update_match_phases)
+                .after(update_navigation),
+        )
+        .add_system(sync_physics)
+        .init_resource::<Recorder>()
+        .add_system(
+            record_matches
+                .after(update_match_phases)
+                .after(sync_physics),
+        )
+        .add_system(reload_config)
+        .insert_resource(AdminConsole::new())
+        .init_resource::<TickRate>()
+        .add_event::<AdminEvent>()
+        .add_system(count_ticks.after(ReceiveEvents))
+        .add_systems(
+            (
+                receive_admin_commands,
+                admin_users,
+                admin_bans,
+                admin_match,
+                admin_config,
+                admin_status,
+            )
+                .chain()
+                .after(ReceiveEvents)
+                .before(update_match_phases),
+        );
+
+    if log_diagnostics {
+        app.add_plugin(FrameTimeDiagnosticsPlugin)
+            .add_plugin(EntityCountDiagnosticsPlugin)
+            .add_plugin(LogDiagnosticsPlugin::default());
+    }
+
+    app
+}
+
+fn init(cfg: Res<Config>, mut server: Server, mut commands: Commands) {
+    info!("Initializing server");
+
+    let network = &cfg.network;
+    let room_settings = if network.rooms.is_empty() {
+        vec![RoomSettings {
+            name: "main".to_owned(),
+            max_players: network.max_players,
+            map: "open".to_owned(),
+            password: network.password.clone(),
+        }]
+    } else {
+        network.rooms.clone()
+    };
+
+    if room_settings.len() > MAX_ROOMS {
+        warn!("Only the first {MAX_ROOMS} rooms will be hosted");
+    }
+
+    let mut rooms = RoomManager::new();
+    for settings in room_settings.into_iter().take(MAX_ROOMS) {
+        let key = server.make_room().key();
+        let name = settings.name.clone();
+
+        let Some(index) = rooms.add_room(key, settings) else {
+            server.room_mut(&key).destroy();
+            continue;
+        };
+        let room = rooms.get_mut(index).unwrap();
+        spawn_walls(room, &mut server, &mut commands);
+        spawn_wardrobes(room, &mut server, &mut commands);
+        spawn_match_state(room, &mut server, &mut commands);
+
+        info!("Hosting room {name} on map {}", room.map.name);
+    }
+
+    commands.insert_resource(rooms);
+    commands.insert_resource(UserAvatarMapping::new());
+    commands.insert_resource(UserNameMapping::new());
+}
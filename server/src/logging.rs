@@ -0,0 +1,54 @@
+//! How the server's logs are written.
+//!
+//! Both formats are filtered by `RUST_LOG` as usual, e.g. `RUST_LOG=info,server::input=debug`
+//! turns on the per-input diagnostics.
+
+use std::str::FromStr;
+
+use bevy::{app::App, log::LogPlugin};
+use tracing_subscriber::{fmt, EnvFilter};
+
+/// What to log when `RUST_LOG` is unset, matching Bevy's default.
+const DEFAULT_FILTER: &str = "info,wgpu=error";
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum LogFormat {
+    /// Bevy's usual human-readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line, with the fields of every enclosing span, for log collectors.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!(
+                "unknown log format `{s}`, expected `text` or `json`"
+            )),
+        }
+    }
+}
+
+/// Starts logging in the given format.
+pub fn add_logging(app: &mut App, format: LogFormat) {
+    match format {
+        LogFormat::Text => {
+            app.add_plugin(LogPlugin::default());
+        }
+        LogFormat::Json => {
+            let filter = EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+            fmt()
+                .json()
+                .with_current_span(true)
+                .with_span_list(true)
+                .with_env_filter(filter)
+                .init();
+        }
+    }
+}
@@ -0,0 +1,512 @@
+//! The per-room match state machine: Lobby → Countdown → InProgress → PostGame → Lobby.
+//!
+//! Users wait in the lobby without an avatar. Once enough of them are connected and all of them are
+//! ready, a countdown starts, after which every user in the room is given an avatar and a place in
+//! the target chain. The round lasts until its [`WinCondition`] is met or its time runs out, and a
+//! [`MatchSummary`] is sent to the room before it returns to the lobby.
+
+use std::{fmt, hash::Hash, str::FromStr};
+
+use bevy::{prelude::*, utils::HashMap};
+use bevy_rapier2d::prelude::*;
+use naia_bevy_server::{events::MessageEvents, CommandsExt, Server, UserKey};
+use serde::{Deserialize, Serialize};
+
+use shared::{
+    channels::{GameMessageChannel, PlayerActionChannel},
+    components::{
+        Action, Appearance, CharacterAction, CharacterEntity, MatchPhase, MatchState,
+        PhysicsStateSync, PlayerInfo, PlayerStats,
+    },
+    messages::{EntityAssignment, MatchSummary, ReadyUp, SummaryEntry},
+    units::CHARACTER_HALF_SIZE,
+};
+
+use crate::{
+    config::{Config, GameplayConfig},
+    disguise::random_look,
+    npc::{despawn_npcs, spawn_npcs},
+    resources::{UserAvatarMapping, UserNameMapping},
+    rooms::{GameRoom, RoomManager, Score},
+};
+
+/// How a round is won. Whatever the condition, a round also ends when its time runs out or fewer
+/// than two players are left alive, in which case the highest score wins.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum WinCondition {
+    /// The first player to kill this many of their targets wins.
+    FirstToKills(u16),
+    /// The last player alive wins.
+    LastStanding,
+    /// The highest score when time runs out wins.
+    HighestScore,
+}
+
+impl FromStr for WinCondition {
+    type Err = String;
+
+    /// Parses `kills:<n>`, `last-standing` or `score`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("kills", n)) => n
+                .parse()
+                .map(WinCondition::FirstToKills)
+                .map_err(|e| format!("invalid kill count: {e}")),
+            None if s == "last-standing" => Ok(WinCondition::LastStanding),
+            None if s == "score" => Ok(WinCondition::HighestScore),
+            _ => Err(format!(
+                "unknown win condition `{s}`, expected `kills:<n>`, `last-standing` or `score`"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for WinCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WinCondition::FirstToKills(kills) => write!(f, "kills:{kills}"),
+            WinCondition::LastStanding => write!(f, "last-standing"),
+            WinCondition::HighestScore => write!(f, "score"),
+        }
+    }
+}
+
+impl From<WinCondition> for String {
+    fn from(condition: WinCondition) -> Self {
+        condition.to_string()
+    }
+}
+
+impl TryFrom<String> for WinCondition {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// Spawns the replicated [`MatchState`] of a room.
+pub fn spawn_match_state(room: &mut GameRoom, server: &mut Server, commands: &mut Commands) {
+    let entity = commands
+        .spawn_empty()
+        .enable_replication(server)
+        .insert(MatchState::new_complete(room.phase, 0))
+        .id();
+
+    server.room_mut(&room.key).add_entity(&entity);
+    room.match_entity = Some(entity);
+}
+
+/// Spawns the replicated [`PlayerInfo`] and [`PlayerStats`] of a newly connected user.
+pub fn spawn_player_info(
+    room: &mut GameRoom,
+    user_key: UserKey,
+    name: String,
+    server: &mut Server,
+    commands: &mut Commands,
+) {
+    let entity = commands
+        .spawn_empty()
+        .enable_replication(server)
+        .insert(PlayerInfo::new_complete(name, false))
+        .insert(PlayerStats::new_complete(0, 0, 0, 0, 0))
+        .id();
+
+    server.room_mut(&room.key).add_entity(&entity);
+    room.player_infos.insert(user_key, entity);
+}
+
+/// Spawns a replicated character at the given position in the room. Players and NPCs are built
+/// identically, down to their random look, so that clients cannot tell them apart.
+pub fn spawn_character(
+    room: &GameRoom,
+    (x, y): (f32, f32),
+    server: &mut Server,
+    commands: &mut Commands,
+) -> Entity {
+    let state = PhysicsStateSync::new_complete(0.0, 0.0, 0.0, x, y, 0.0);
+
+    let entity = commands
+        .spawn_empty()
+        .enable_replication(server)
+        .insert(CharacterEntity)
+        .insert(CharacterAction::new_complete(Action::Idle))
+        .insert(Appearance::new_complete(random_look()))
+        .insert(state)
+        .insert(Velocity {
+            linvel: Vec2::new(0.0, 0.0),
+            angvel: 0.0,
+        })
+        .insert(RigidBody::Dynamic)
+        .insert(Damping {
+            linear_damping: 1.0,
+            angular_damping: 1.0,
+        })
+        .insert(Collider::cuboid(CHARACTER_HALF_SIZE.x, CHARACTER_HALF_SIZE.y))
+        .insert(room.collision_groups)
+        .insert(Restitution::coefficient(0.2))
+        .insert(TransformBundle::from_transform(Transform::from_xyz(
+            x, y, 1.0,
+        )))
+        .id();
+
+    server.room_mut(&room.key).add_entity(&entity);
+
+    entity
+}
+
+/// Spawns a character for the user at the room's next spawn point, and tells them it is theirs.
+pub fn spawn_avatar(
+    room: &mut GameRoom,
+    user_key: UserKey,
+    users_avatars: &mut UserAvatarMapping,
+    server: &mut Server,
+    commands: &mut Commands,
+) -> Entity {
+    let position = room.next_spawn_point();
+    let entity = spawn_character(room, position, server, commands);
+
+    users_avatars.insert(user_key, entity);
+
+    let mut assignment_msg = EntityAssignment::new(true);
+    assignment_msg.entity.set(server, &entity);
+
+    server.send_message::<GameMessageChannel, EntityAssignment>(&user_key, &assignment_msg);
+
+    entity
+}
+
+/// Despawns the user's avatar, if they have one.
+pub fn despawn_avatar(
+    room: &GameRoom,
+    user_key: &UserKey,
+    users_avatars: &mut UserAvatarMapping,
+    server: &mut Server,
+    commands: &mut Commands,
+) {
+    if let Some(entity) = users_avatars.get_by_user(user_key) {
+        commands.entity(*entity).despawn();
+        server.room_mut(&room.key).remove_entity(entity);
+    }
+
+    users_avatars.remove_by_user(user_key);
+}
+
+/// Handles [`ReadyUp`] messages from users waiting in the lobby.
+pub fn ready_events(
+    mut event_reader: EventReader<MessageEvents>,
+    rooms: Res<RoomManager>,
+    mut info_query: Query<&mut PlayerInfo>,
+) {
+    for events in event_reader.iter() {
+        for (user_key, message) in events.read::<PlayerActionChannel, ReadyUp>() {
+            let Some(room) = rooms.room_of(&user_key) else { continue; };
+            if !matches!(room.phase, MatchPhase::Lobby | MatchPhase::Countdown) {
+                continue;
+            }
+
+            let Some(entity) = room.player_infos.get(&user_key) else { continue; };
+            let Ok(mut info) = info_query.get_mut(*entity) else { continue; };
+
+            if *info.ready != message.ready {
+                *info.ready = message.ready;
+            }
+        }
+    }
+}
+
+/// Advances each room's match state machine and mirrors it into its replicated [`MatchState`].
+pub fn update_match_phases(
+    time: Res<Time>,
+    cfg: Res<Config>,
+    mut rooms: ResMut<RoomManager>,
+    users_names: Res<UserNameMapping>,
+    mut users_avatars: ResMut<UserAvatarMapping>,
+    mut match_query: Query<&mut MatchState>,
+    mut info_query: Query<&mut PlayerInfo>,
+    mut server: Server,
+    mut commands: Commands,
+) {
+    let gameplay = &cfg.gameplay;
+
+    for room in rooms.rooms_mut() {
+        room.phase_timer.tick(time.delta());
+
+        let enough_players = room.player_infos.len() >= gameplay.min_players.into();
+        let all_ready = room.player_infos.values().all(|entity| {
+            info_query
+                .get(*entity)
+                .map(|info| *info.ready)
+                .unwrap_or(false)
+        });
+        let living = room
+            .users
+            .iter()
+            .filter(|user| users_avatars.get_by_user(user).is_some())
+            .count();
+
+        let restart = std::mem::take(&mut room.restart_requested);
+
+        let next = match room.phase {
+            MatchPhase::InProgress | MatchPhase::PostGame if restart => {
+                Some(MatchPhase::Countdown)
+            }
+            MatchPhase::Lobby if enough_players && all_ready => Some(MatchPhase::Countdown),
+            MatchPhase::Countdown if !(enough_players && all_ready) => Some(MatchPhase::Lobby),
+            MatchPhase::Countdown if room.phase_timer.finished() => Some(MatchPhase::InProgress),
+            MatchPhase::InProgress if round_over(room, gameplay.win_condition, living) => {
+                Some(MatchPhase::PostGame)
+            }
+            MatchPhase::PostGame if room.phase_timer.finished() => Some(MatchPhase::Lobby),
+            _ => None,
+        };
+
+        if let Some(next) = next {
+            info!("Room {} entering {next:?}", room.settings.name);
+
+            match next {
+                // A cancelled countdown keeps everyone's ready state
+                MatchPhase::Lobby if room.phase == MatchPhase::Countdown => {}
+                MatchPhase::Lobby => {
+                    end_match(room, &mut users_avatars, &mut server, &mut commands);
+
+                    for entity in room.player_infos.values() {
+                        if let Ok(mut info) = info_query.get_mut(*entity) {
+                            *info.ready = false;
+                        }
+                    }
+                }
+                MatchPhase::Countdown => {
+                    // A restarted match keeps everyone's ready state
+                    if room.phase != MatchPhase::Lobby {
+                        end_match(room, &mut users_avatars, &mut server, &mut commands);
+                    }
+                    room.phase_timer =
+                        Timer::from_seconds(gameplay.countdown_secs.into(), TimerMode::Once);
+                }
+                MatchPhase::InProgress => {
+                    start_match(
+                        room,
+                        gameplay.npcs,
+                        &mut users_avatars,
+                        &mut server,
+                        &mut commands,
+                    );
+                    room.phase_timer =
+                        Timer::from_seconds(gameplay.round_secs.into(), TimerMode::Once);
+                }
+                MatchPhase::PostGame => {
+                    send_summary(room, gameplay, &users_names, &users_avatars, &mut server);
+                    room.phase_timer =
+                        Timer::from_seconds(gameplay.post_game_secs.into(), TimerMode::Once);
+                }
+            }
+
+            room.phase = next;
+        }
+
+        let Some(Ok(mut state)) = room.match_entity.map(|e| match_query.get_mut(e)) else {
+            continue;
+        };
+
+        if *state.phase != room.phase {
+            *state.phase = room.phase;
+        }
+
+        let seconds_remaining = match room.phase {
+            MatchPhase::Countdown | MatchPhase::InProgress | MatchPhase::PostGame => {
+                room.phase_timer.remaining_secs().ceil() as u16
+            }
+            MatchPhase::Lobby => 0,
+        };
+        if *state.seconds_remaining != seconds_remaining {
+            *state.seconds_remaining = seconds_remaining;
+        }
+    }
+}
+
+/// Whether the round in progress in the room should end.
+fn round_over(room: &GameRoom, condition: WinCondition, living: usize) -> bool {
+    if room.phase_timer.finished() || living < 2 {
+        return true;
+    }
+
+    match condition {
+        WinCondition::FirstToKills(kills) => room.scores.values().any(|s| s.kills >= kills),
+        WinCondition::LastStanding | WinCondition::HighestScore => false,
+    }
+}
+
+/// Picks the winner of the round which just ended in the room, if there is a single one.
+fn winner(
+    room: &GameRoom,
+    gameplay: &GameplayConfig,
+    users_avatars: &UserAvatarMapping,
+) -> Option<UserKey> {
+    let mut living = room
+        .users
+        .iter()
+        .filter(|user| users_avatars.get_by_user(user).is_some());
+    let survivor = match (living.next(), living.next()) {
+        (Some(survivor), None) => Some(*survivor),
+        _ => None,
+    };
+
+    rank_winner(gameplay, &room.scores, survivor)
+}
+
+/// The single best of the scores under the win condition, if there is one. Under
+/// [`WinCondition::LastStanding`] the sole survivor wins regardless of their score.
+fn rank_winner<K: Copy + Eq + Hash>(
+    gameplay: &GameplayConfig,
+    scores: &HashMap<K, Score>,
+    survivor: Option<K>,
+) -> Option<K> {
+    if gameplay.win_condition == WinCondition::LastStanding && survivor.is_some() {
+        return survivor;
+    }
+
+    let rank = |score: &Score| match gameplay.win_condition {
+        WinCondition::FirstToKills(_) => i32::from(score.kills),
+        WinCondition::LastStanding | WinCondition::HighestScore => score.points(gameplay),
+    };
+
+    let best = scores.values().map(rank).max()?;
+    let mut leaders = scores.iter().filter(|(_, score)| rank(score) == best);
+
+    let (leader, _) = leaders.next()?;
+    leaders.next().is_none().then_some(*leader)
+}
+
+/// Sends every user in the room, spectators included, the results of the round which just ended.
+fn send_summary(
+    room: &GameRoom,
+    gameplay: &GameplayConfig,
+    users_names: &UserNameMapping,
+    users_avatars: &UserAvatarMapping,
+    server: &mut Server,
+) {
+    let mut entries = room
+        .scores
+        .iter()
+        .filter_map(|(user, score)| {
+            Some(SummaryEntry {
+                name: users_names.get_by_user(user)?.clone(),
+                kills: score.kills,
+                wrong_kills: score.wrong_kills,
+                deaths: score.deaths,
+                score: score.points(gameplay),
+            })
+        })
+        .collect::<Vec<_>>();
+    entries.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.name.cmp(&b.name)));
+
+    let winner = winner(room, gameplay, users_avatars)
+        .and_then(|user| users_names.get_by_user(&user))
+        .cloned();
+    info!(
+        "Room {} finished, winner: {}",
+        room.settings.name,
+        winner.as_deref().unwrap_or("none")
+    );
+
+    let summary = MatchSummary { winner, entries };
+    for user_key in room.users.iter() {
+        if !server.user_exists(user_key) {
+            continue;
+        }
+        server.send_message::<GameMessageChannel, MatchSummary>(user_key, &summary);
+    }
+}
+
+/// Gives every connected user in the room an avatar and builds the room's target chain.
+fn start_match(
+    room: &mut GameRoom,
+    npc_count: u16,
+    users_avatars: &mut UserAvatarMapping,
+    server: &mut Server,
+    commands: &mut Commands,
+) {
+    room.scores.clear();
+
+    let users = room.player_infos.keys().copied().collect::<Vec<_>>();
+    for user_key in users {
+        spawn_avatar(room, user_key, users_avatars, server, commands);
+        room.targets.insert(user_key);
+        room.scores.insert(user_key, Score::default());
+    }
+
+    spawn_npcs(room, npc_count, server, commands);
+}
+
+/// Removes every character in the room and clears its target chain. Eliminated players who were
+/// watching go back to being players.
+fn end_match(
+    room: &mut GameRoom,
+    users_avatars: &mut UserAvatarMapping,
+    server: &mut Server,
+    commands: &mut Commands,
+) {
+    for user_key in room.users.iter() {
+        despawn_avatar(room, user_key, users_avatars, server, commands);
+    }
+    despawn_npcs(room, server, commands);
+    room.targets.clear();
+    room.scores.clear();
+    room.watching.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gameplay(win_condition: WinCondition) -> GameplayConfig {
+        GameplayConfig {
+            win_condition,
+            ..default()
+        }
+    }
+
+    fn score(kills: u16, wrong_kills: u16) -> Score {
+        Score {
+            kills,
+            wrong_kills,
+            ..default()
+        }
+    }
+
+    fn tally(scores: &[(u32, Score)]) -> HashMap<u32, Score> {
+        scores.iter().copied().collect()
+    }
+
+    #[test]
+    fn first_to_kills_ranks_right_kills_only() {
+        let gameplay = gameplay(WinCondition::FirstToKills(3));
+        let scores = tally(&[(1, score(3, 4)), (2, score(2, 0))]);
+        assert_eq!(rank_winner(&gameplay, &scores, None), Some(1));
+    }
+
+    #[test]
+    fn highest_score_counts_wrong_kills_against() {
+        let gameplay = gameplay(WinCondition::HighestScore);
+        let scores = tally(&[(1, score(3, 4)), (2, score(2, 0))]);
+        assert_eq!(rank_winner(&gameplay, &scores, None), Some(2));
+
+        // A tie is a draw
+        let scores = tally(&[(1, score(3, 1)), (2, score(2, 0))]);
+        assert_eq!(rank_winner(&gameplay, &scores, None), None);
+    }
+
+    #[test]
+    fn last_standing_falls_back_to_the_score() {
+        let gameplay = gameplay(WinCondition::LastStanding);
+        let scores = tally(&[(1, score(0, 0)), (2, score(2, 0)), (3, score(1, 0))]);
+
+        // The survivor wins however few points they have
+        assert_eq!(rank_winner(&gameplay, &scores, Some(1)), Some(1));
+
+        // When time runs out with several alive, the highest score wins
+        assert_eq!(rank_winner(&gameplay, &scores, None), Some(2));
+    }
+}
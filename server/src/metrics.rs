@@ -0,0 +1,394 @@
+//! Health numbers for unattended servers, served in Prometheus' text format when `metrics_addr` is
+//! configured, e.g. `curl http://127.0.0.1:9100/metrics`.
+//!
+//! Counters are bumped by the systems which see the events, gauges are refreshed every update by
+//! [`update_metrics`], and the HTTP thread renders whatever was last recorded.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::{prelude::*, utils::HashMap};
+use naia_bevy_server::{events::TickEvent, Server};
+
+use shared::components::MatchPhase;
+
+use crate::{config::Config, resources::UserNameMapping, rooms::RoomManager};
+
+/// How many metrics requests are answered at once. Connections beyond this are turned away until
+/// one finishes, so that a flood of them cannot pile up threads.
+pub const MAX_REQUESTS: usize = 4;
+
+/// The upper bounds of the tick duration histogram's buckets, in seconds.
+const TICK_BUCKETS: [f64; 8] = [0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1];
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ByteCounts {
+    pub sent: u64,
+    pub received: u64,
+}
+
+/// Bytes sent to and received from each address, counted by the transport.
+#[derive(Clone, Default)]
+pub struct Traffic(Arc<Mutex<HashMap<SocketAddr, ByteCounts>>>);
+
+impl Traffic {
+    pub fn sent(&self, address: SocketAddr, bytes: usize) {
+        self.0.lock().unwrap().entry(address).or_default().sent += bytes as u64;
+    }
+
+    pub fn received(&self, address: SocketAddr, bytes: usize) {
+        self.0.lock().unwrap().entry(address).or_default().received += bytes as u64;
+    }
+}
+
+#[derive(Default)]
+struct Histogram {
+    /// How many observations fell in each of [`TICK_BUCKETS`], not yet accumulated.
+    buckets: [u64; TICK_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        if let Some(bucket) = TICK_BUCKETS.iter().position(|bound| value <= *bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+#[derive(Default)]
+struct Registry {
+    users: usize,
+    entities: usize,
+    /// The name, phase and user count of each room.
+    rooms: Vec<(String, MatchPhase, usize)>,
+    /// The name and address of each connected user.
+    users_addresses: Vec<(String, SocketAddr)>,
+    tick_duration: Histogram,
+    inputs_received: u64,
+    inputs_dropped: BTreeMap<&'static str, u64>,
+    auth_rejections: BTreeMap<&'static str, u64>,
+}
+
+#[derive(Clone, Default, Resource)]
+pub struct Metrics {
+    registry: Arc<Mutex<Registry>>,
+    traffic: Traffic,
+}
+
+impl Metrics {
+    /// The counters for the transport to fill in.
+    pub fn traffic(&self) -> Traffic {
+        self.traffic.clone()
+    }
+
+    pub fn input_received(&self) {
+        self.registry.lock().unwrap().inputs_received += 1;
+    }
+
+    pub fn input_dropped(&self, reason: &'static str) {
+        *self
+            .registry
+            .lock()
+            .unwrap()
+            .inputs_dropped
+            .entry(reason)
+            .or_default() += 1;
+    }
+
+    pub fn auth_rejected(&self, reason: &'static str) {
+        *self
+            .registry
+            .lock()
+            .unwrap()
+            .auth_rejections
+            .entry(reason)
+            .or_default() += 1;
+    }
+
+    /// Everything recorded so far, in Prometheus' text exposition format.
+    pub fn render(&self) -> String {
+        let registry = self.registry.lock().unwrap();
+        let traffic = self.traffic.0.lock().unwrap();
+        let mut out = String::new();
+
+        metric(&mut out, "users", "gauge", "Connected users.");
+        writeln!(out, "assassin_users {}", registry.users).unwrap();
+
+        metric(&mut out, "rooms", "gauge", "Hosted rooms, by match phase.");
+        for phase in [
+            MatchPhase::Lobby,
+            MatchPhase::Countdown,
+            MatchPhase::InProgress,
+            MatchPhase::PostGame,
+        ] {
+            let count = registry.rooms.iter().filter(|room| room.1 == phase).count();
+            writeln!(
+                out,
+                "assassin_rooms{{phase=\"{}\"}} {count}",
+                phase_label(phase)
+            )
+            .unwrap();
+        }
+
+        metric(&mut out, "room_users", "gauge", "Users placed in each room.");
+        for (name, _, users) in registry.rooms.iter() {
+            writeln!(out, "assassin_room_users{{room=\"{}\"}} {users}", escape(name)).unwrap();
+        }
+
+        metric(&mut out, "entities", "gauge", "Entities in the world.");
+        writeln!(out, "assassin_entities {}", registry.entities).unwrap();
+
+        metric(
+            &mut out,
+            "tick_duration_seconds",
+            "histogram",
+            "How long updates which process a tick take.",
+        );
+        let histogram = &registry.tick_duration;
+        let mut cumulative = 0;
+        for (bound, count) in TICK_BUCKETS.iter().zip(histogram.buckets) {
+            cumulative += count;
+            writeln!(
+                out,
+                "assassin_tick_duration_seconds_bucket{{le=\"{bound}\"}} {cumulative}"
+            )
+            .unwrap();
+        }
+        writeln!(
+            out,
+            "assassin_tick_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+            histogram.count
+        )
+        .unwrap();
+        writeln!(out, "assassin_tick_duration_seconds_sum {}", histogram.sum).unwrap();
+        writeln!(out, "assassin_tick_duration_seconds_count {}", histogram.count).unwrap();
+
+        metric(&mut out, "inputs_received_total", "counter", "Player inputs received.");
+        writeln!(out, "assassin_inputs_received_total {}", registry.inputs_received).unwrap();
+
+        metric(
+            &mut out,
+            "inputs_dropped_total",
+            "counter",
+            "Player inputs ignored, by reason.",
+        );
+        for (reason, count) in registry.inputs_dropped.iter() {
+            writeln!(out, "assassin_inputs_dropped_total{{reason=\"{reason}\"}} {count}").unwrap();
+        }
+
+        metric(
+            &mut out,
+            "auth_rejections_total",
+            "counter",
+            "Refused connections, by reason.",
+        );
+        for (reason, count) in registry.auth_rejections.iter() {
+            writeln!(out, "assassin_auth_rejections_total{{reason=\"{reason}\"}} {count}").unwrap();
+        }
+
+        let directions: [(&str, &str, fn(ByteCounts) -> u64); 2] = [
+            ("sent", "Bytes sent to", |counts| counts.sent),
+            ("received", "Bytes received from", |counts| counts.received),
+        ];
+        for (direction, help, bytes) in directions {
+            metric(
+                &mut out,
+                &format!("user_{direction}_bytes_total"),
+                "counter",
+                &format!("{help} each connected user."),
+            );
+            for (name, address) in registry.users_addresses.iter() {
+                let bytes = bytes(traffic.get(address).copied().unwrap_or_default());
+                writeln!(
+                    out,
+                    "assassin_user_{direction}_bytes_total{{user=\"{}\",address=\"{address}\"}} \
+                     {bytes}",
+                    escape(name)
+                )
+                .unwrap();
+            }
+        }
+
+        out
+    }
+}
+
+fn metric(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP assassin_{name} {help}").unwrap();
+    writeln!(out, "# TYPE assassin_{name} {kind}").unwrap();
+}
+
+fn phase_label(phase: MatchPhase) -> &'static str {
+    match phase {
+        MatchPhase::Lobby => "lobby",
+        MatchPhase::Countdown => "countdown",
+        MatchPhase::InProgress => "in_progress",
+        MatchPhase::PostGame => "post_game",
+    }
+}
+
+/// Escapes a label value, which may be anything a player typed.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Refreshes the gauges, and forgets the traffic of addresses which are no longer connected.
+pub fn update_metrics(
+    metrics: Res<Metrics>,
+    rooms: Res<RoomManager>,
+    users_names: Res<UserNameMapping>,
+    entities: Query<Entity>,
+    server: Server,
+) {
+    let users_addresses = users_names
+        .iter()
+        .filter(|(user_key, _)| server.user_exists(user_key))
+        .map(|(user_key, name)| (name.clone(), server.user(user_key).address()))
+        .collect::<Vec<_>>();
+
+    metrics
+        .traffic
+        .0
+        .lock()
+        .unwrap()
+        .retain(|address, _| users_addresses.iter().any(|(_, a)| a == address));
+
+    let mut registry = metrics.registry.lock().unwrap();
+    registry.users = users_addresses.len();
+    registry.users_addresses = users_addresses;
+    registry.entities = entities.iter().count();
+    registry.rooms = rooms
+        .rooms()
+        .iter()
+        .map(|room| (room.settings.name.clone(), room.phase, room.users.len()))
+        .collect();
+}
+
+/// When the current update started.
+#[derive(Resource)]
+pub struct UpdateStart(Instant);
+
+impl Default for UpdateStart {
+    fn default() -> Self {
+        Self(Instant::now())
+    }
+}
+
+pub fn start_update_timer(mut start: ResMut<UpdateStart>) {
+    start.0 = Instant::now();
+}
+
+/// Records how long the update took, if it processed a tick.
+pub fn observe_tick_duration(
+    start: Res<UpdateStart>,
+    mut ticks: EventReader<TickEvent>,
+    metrics: Res<Metrics>,
+) {
+    if ticks.iter().count() == 0 {
+        return;
+    }
+
+    let duration = start.0.elapsed().as_secs_f64();
+    metrics
+        .registry
+        .lock()
+        .unwrap()
+        .tick_duration
+        .observe(duration);
+}
+
+/// The address the metrics are served on, which has the port picked by the OS when `metrics_addr`
+/// asks for port 0.
+#[derive(Resource)]
+pub struct MetricsAddr(pub SocketAddr);
+
+/// Serves the metrics over HTTP when `metrics_addr` is configured.
+pub fn serve_metrics(cfg: Res<Config>, metrics: Res<Metrics>, mut commands: Commands) {
+    let Some(addr) = cfg.network.metrics_addr else { return; };
+
+    let listener = match TcpListener::bind(addr) {
+        Ok(listener) => listener,
+        Err(err) => {
+            error!("Could not serve metrics on {addr}: {err}");
+            return;
+        }
+    };
+    let addr = listener.local_addr().unwrap_or(addr);
+    commands.insert_resource(MetricsAddr(addr));
+    info!("Serving metrics on http://{addr}/metrics");
+
+    let metrics = metrics.clone();
+    let in_flight = Arc::new(AtomicUsize::new(0));
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue; };
+            if in_flight.fetch_add(1, Ordering::SeqCst) >= MAX_REQUESTS {
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\
+                     Connection: close\r\n\r\n"
+                );
+                continue;
+            }
+
+            // Each request gets its own thread, so that a stalled scraper cannot hold up the next
+            let metrics = metrics.clone();
+            let in_flight = in_flight.clone();
+            thread::spawn(move || {
+                if let Err(err) = respond(stream, &metrics) {
+                    debug!("Metrics request failed: {err}");
+                }
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    });
+}
+
+fn respond(stream: TcpStream, metrics: &Metrics) -> io::Result<()> {
+    // Scrapers are trusted to be quick, but a stalled one should not keep its thread forever
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // Skip the headers
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+
+    let mut stream = stream;
+    let path = request.split_whitespace().nth(1).unwrap_or("/");
+    if request.starts_with("GET ") && (path == "/metrics" || path.starts_with("/metrics?")) {
+        let body = metrics.render();
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    } else {
+        write!(
+            stream,
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        )
+    }
+}
@@ -0,0 +1,44 @@
+//! Keeping each room's [`NavGrid`](shared::navigation::NavGrid) in step with its walls.
+
+use bevy::prelude::*;
+
+use shared::{components::WallEntity, maps::WallSpec};
+
+use crate::rooms::RoomManager;
+
+/// Rebakes the cells under walls which were moved, resized or despawned since they were last
+/// baked. Only the cells under the old and new geometry of a wall are touched.
+pub fn update_navigation(
+    mut rooms: ResMut<RoomManager>,
+    wall_query: Query<(&Transform, &WallEntity), Or<(Changed<Transform>, Changed<WallEntity>)>>,
+    mut removed_walls: RemovedComponents<WallEntity>,
+) {
+    let removed = removed_walls.iter().collect::<Vec<_>>();
+
+    for room in rooms.rooms_mut() {
+        for entity in removed.iter() {
+            if let Some(old) = room.nav_walls.remove(entity) {
+                room.nav.remove_wall(&old);
+                room.walls.retain(|wall| wall != entity);
+            }
+        }
+
+        for entity in room.walls.iter() {
+            let Ok((transform, wall)) = wall_query.get(*entity) else { continue; };
+
+            let new = WallSpec::new(
+                transform.translation.x,
+                transform.translation.y,
+                *wall.half_width_m,
+                *wall.half_height_m,
+            );
+
+            match room.nav_walls.insert(*entity, new) {
+                Some(old) if old == new => continue,
+                Some(old) => room.nav.remove_wall(&old),
+                None => {}
+            }
+            room.nav.add_wall(&new);
+        }
+    }
+}
@@ -0,0 +1,199 @@
+//! The crowd of computer-controlled characters which players hide among.
+//!
+//! NPCs are ordinary characters as far as replication is concerned. On the server they carry an
+//! extra [`Npc`] component, and [`steer_npcs`] drives their velocity along a path through the
+//! room's [`NavGrid`] to random waypoints while keeping them off walls and out of each other's way.
+//! Now and then an NPC's waypoint is one of the map's wardrobes, where it changes disguise as a
+//! player would, so that a change of look does not give a player away.
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use naia_bevy_server::{Random, Server};
+
+use shared::{
+    components::{Appearance, MatchPhase},
+    navigation::NavGrid,
+};
+
+use crate::{
+    config::Config,
+    disguise::{at_wardrobe, new_look},
+    match_state::spawn_character,
+    resources::UserAvatarMapping,
+    rooms::{GameRoom, RoomManager},
+};
+
+/// How close an NPC has to get to a point on its path before moving on to the next, in meters.
+const ARRIVE_RADIUS: f32 = 0.5;
+/// How long an NPC may take to reach its waypoint before giving up on it, in seconds.
+const WAYPOINT_TIMEOUT_SECS: f32 = 15.0;
+/// Characters closer than this push each other apart, in meters.
+const SEPARATION_RADIUS: f32 = 1.5;
+/// Walls closer than this push NPCs away, in meters.
+const WALL_AVOID_RADIUS: f32 = 1.0;
+/// How quickly the current velocity turns towards the desired one, per second.
+const STEERING_RATE: f32 = 4.0;
+/// How likely each new waypoint is to be a wardrobe.
+const WARDROBE_CHANCE: f32 = 0.1;
+
+#[derive(Component)]
+pub struct Npc {
+    /// The remaining points to walk through, ending at the NPC's current waypoint.
+    pub path: Vec<Vec2>,
+    pub waypoint_timer: Timer,
+    /// Whether the waypoint is a wardrobe, to change disguise at on arrival.
+    pub to_wardrobe: bool,
+}
+
+impl Npc {
+    /// Picks a new random waypoint and plans a path to it from `position`. The path is left empty
+    /// if the waypoint cannot be reached, so that another is picked next time.
+    fn wander(position: Vec2, room: &GameRoom) -> Self {
+        let wardrobes = &room.map.wardrobes;
+        let to_wardrobe =
+            !wardrobes.is_empty() && Random::gen_range_f32(0.0, 1.0) < WARDROBE_CHANCE;
+        let waypoint = if to_wardrobe {
+            let (x, y) = wardrobes[Random::gen_range_u32(0, wardrobes.len() as u32) as usize];
+            Vec2::new(x, y)
+        } else {
+            random_open_point(&room.nav, room.map.half_width, room.map.half_height)
+        };
+
+        let mut path = room.nav.find_path(position, waypoint).unwrap_or_default();
+        path.reverse();
+
+        Self {
+            path,
+            waypoint_timer: Timer::from_seconds(WAYPOINT_TIMEOUT_SECS, TimerMode::Once),
+            to_wardrobe,
+        }
+    }
+}
+
+/// Picks a random walkable point within the given bounds.
+pub fn random_open_point(nav: &NavGrid, half_width: f32, half_height: f32) -> Vec2 {
+    let mut point = Vec2::ZERO;
+
+    for _ in 0..16 {
+        point = Vec2::new(
+            Random::gen_range_f32(-half_width + 1.0, half_width - 1.0),
+            Random::gen_range_f32(-half_height + 1.0, half_height - 1.0),
+        );
+
+        if nav.is_walkable(point) {
+            break;
+        }
+    }
+
+    point
+}
+
+/// Returns the distance from a point to an axis-aligned wall, and the direction pointing away from
+/// it.
+fn distance_to_wall(point: Vec2, x: f32, y: f32, half_width: f32, half_height: f32) -> (f32, Vec2) {
+    let center = Vec2::new(x, y);
+    let half = Vec2::new(half_width, half_height);
+    let closest = point.clamp(center - half, center + half);
+    let away = point - closest;
+
+    (away.length(), away.normalize_or_zero())
+}
+
+/// Fills the room with its crowd for a new match.
+pub fn spawn_npcs(room: &mut GameRoom, count: u16, server: &mut Server, commands: &mut Commands) {
+    for _ in 0..count {
+        let (half_width, half_height) = (room.map.half_width, room.map.half_height);
+        let position = random_open_point(&room.nav, half_width, half_height);
+        let entity = spawn_character(room, (position.x, position.y), server, commands);
+        commands.entity(entity).insert(Npc::wander(position, room));
+
+        room.npcs.push(entity);
+    }
+}
+
+pub fn despawn_npcs(room: &mut GameRoom, server: &mut Server, commands: &mut Commands) {
+    for entity in room.npcs.drain(..) {
+        commands.entity(entity).despawn();
+        server.room_mut(&room.key).remove_entity(&entity);
+    }
+}
+
+/// Seeks each NPC along its path, steering away from walls and other characters, and changes the
+/// disguise of those which have reached a wardrobe.
+pub fn steer_npcs(
+    time: Res<Time>,
+    cfg: Res<Config>,
+    rooms: Res<RoomManager>,
+    users_avatars: Res<UserAvatarMapping>,
+    mut npc_query: Query<(&Transform, &mut Velocity, &mut Appearance, &mut Npc)>,
+    transform_query: Query<&Transform>,
+) {
+    let dt = time.delta_seconds();
+
+    for room in rooms.rooms() {
+        if room.phase != MatchPhase::InProgress {
+            continue;
+        }
+
+        let others = room
+            .users
+            .iter()
+            .filter_map(|user| users_avatars.get_by_user(user))
+            .chain(room.npcs.iter())
+            .filter_map(|entity| Some((*entity, transform_query.get(*entity).ok()?)))
+            .map(|(entity, transform)| (entity, transform.translation.truncate()))
+            .collect::<Vec<_>>();
+
+        for entity in room.npcs.iter() {
+            let Ok((transform, mut velocity, mut appearance, mut npc)) = npc_query.get_mut(*entity)
+            else {
+                continue;
+            };
+            let position = transform.translation.truncate();
+
+            npc.waypoint_timer.tick(time.delta());
+            while let Some(next) = npc.path.last() {
+                if position.distance(*next) >= ARRIVE_RADIUS {
+                    break;
+                }
+                npc.path.pop();
+            }
+            if npc.path.is_empty() || npc.waypoint_timer.finished() {
+                if npc.path.is_empty() && npc.to_wardrobe && at_wardrobe(room, position) {
+                    *appearance.look = new_look(*appearance.look);
+                }
+                *npc = Npc::wander(position, room);
+            }
+
+            let seek = npc
+                .path
+                .last()
+                .map_or(Vec2::ZERO, |next| (*next - position).normalize_or_zero());
+
+            let separation = others
+                .iter()
+                .filter(|(other, _)| other != entity)
+                .map(|(_, other)| position - *other)
+                .filter(|offset| offset.length() < SEPARATION_RADIUS)
+                .map(|offset| {
+                    offset.normalize_or_zero() * (1.0 - offset.length() / SEPARATION_RADIUS)
+                })
+                .sum::<Vec2>();
+
+            // The room's walls as they stand now, which may have moved since the map was loaded
+            let avoidance = room
+                .nav_walls
+                .values()
+                .map(|wall| {
+                    distance_to_wall(position, wall.x, wall.y, wall.half_width, wall.half_height)
+                })
+                .filter(|(distance, _)| *distance < WALL_AVOID_RADIUS)
+                .map(|(distance, away)| away * (1.0 - distance / WALL_AVOID_RADIUS))
+                .sum::<Vec2>();
+
+            let desired = (seek + separation * 1.5 + avoidance * 2.0).clamp_length_max(1.0)
+                * cfg.gameplay.npc_speed();
+            velocity.linvel = velocity.linvel.lerp(desired, (STEERING_RATE * dt).min(1.0));
+        }
+    }
+}
@@ -0,0 +1,344 @@
+//! Recording matches to replay files when `replay_dir` is configured, one file per match and room.
+//! See [`shared::replay`] for the format, and the `replay` binary for inspecting and verifying the
+//! files.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter},
+    mem,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{prelude::*, utils::HashMap};
+use naia_bevy_server::{events::TickEvent, RoomKey, Tick, UserKey};
+
+use shared::{
+    components::{Appearance, Look, MatchPhase, PhysicsStateSync, WallEntity},
+    messages::PlayerInput,
+    replay::{BodyState, Event, Frame, Header, ReplayWriter, Shape},
+};
+
+use crate::{
+    combat::Corpse,
+    config::Config,
+    resources::{UserAvatarMapping, UserNameMapping},
+    rooms::{GameRoom, RoomManager},
+};
+
+/// The recording of each room's match in progress.
+#[derive(Default, Resource)]
+pub struct Recorder {
+    /// `None` for matches which are not being recorded because of an error.
+    recordings: HashMap<RoomKey, Option<Recording>>,
+}
+
+impl Recorder {
+    /// Records an input which was applied to the user's avatar this tick.
+    pub fn input(&mut self, room: RoomKey, user_key: UserKey, avatar: Entity, input: &PlayerInput) {
+        let Some(Some(recording)) = self.recordings.get_mut(&room) else { return; };
+        let (Some(user), Some(body)) = (
+            recording.users.get(&user_key),
+            recording.bodies.get(&avatar),
+        ) else {
+            return;
+        };
+
+        recording.pending.push(Event::Input {
+            user: *user,
+            body: *body,
+            x_axis: input.x_axis,
+            y_axis: input.y_axis,
+            attack: input.attack,
+        });
+    }
+
+    /// Records a character being killed by the user this tick.
+    pub fn kill(&mut self, room: RoomKey, attacker: UserKey, victim: Entity) {
+        let Some(Some(recording)) = self.recordings.get_mut(&room) else { return; };
+        let (Some(attacker), Some(victim)) = (
+            recording.users.get(&attacker),
+            recording.bodies.get(&victim),
+        ) else {
+            return;
+        };
+
+        recording.pending.push(Event::Kill {
+            attacker: *attacker,
+            victim: *victim,
+        });
+    }
+}
+
+struct Recording {
+    writer: ReplayWriter<BufWriter<File>>,
+    path: PathBuf,
+    next_id: u32,
+    bodies: HashMap<Entity, u32>,
+    users: HashMap<UserKey, u32>,
+    /// The last recorded state of each body.
+    states: HashMap<u32, BodyState>,
+    /// The last recorded look of each character.
+    looks: HashMap<u32, Look>,
+    /// The last recorded target of each hunter.
+    targets: HashMap<u32, u32>,
+    player_speed: f32,
+    /// Events reported by other systems since the last frame.
+    pending: Vec<Event>,
+}
+
+impl Recording {
+    fn start(dir: &Path, room: &GameRoom, player_speed: f32) -> io::Result<Self> {
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        let file_name = room
+            .settings
+            .name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect::<String>();
+
+        fs::create_dir_all(dir)?;
+        let (path, file) = create_new(dir, &format!("{file_name}-{started}"))?;
+        let header = Header {
+            room: room.settings.name.clone(),
+            map: room.map.name.to_owned(),
+            started,
+            player_speed,
+        };
+        let writer = ReplayWriter::new(BufWriter::new(file), &header)?;
+
+        Ok(Self {
+            writer,
+            path,
+            next_id: 0,
+            bodies: HashMap::new(),
+            users: HashMap::new(),
+            states: HashMap::new(),
+            looks: HashMap::new(),
+            targets: HashMap::new(),
+            player_speed,
+            pending: Vec::new(),
+        })
+    }
+
+    fn set_player_speed(&mut self, player_speed: f32) {
+        if player_speed != self.player_speed {
+            self.player_speed = player_speed;
+            self.pending.push(Event::PlayerSpeed(player_speed));
+        }
+    }
+
+    fn next_id(&mut self) -> u32 {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    /// Writes what changed in the room since the last frame.
+    fn record(
+        &mut self,
+        tick: Tick,
+        room: &GameRoom,
+        users_names: &UserNameMapping,
+        users_avatars: &UserAvatarMapping,
+        state_query: &Query<&PhysicsStateSync>,
+        wall_query: &Query<&WallEntity>,
+        corpse_query: &Query<(Entity, &Corpse)>,
+        appearance_query: &Query<&Appearance>,
+    ) -> io::Result<()> {
+        let mut events = mem::take(&mut self.pending);
+
+        for user_key in room.users.iter() {
+            if self.users.contains_key(user_key) || users_avatars.get_by_user(user_key).is_none() {
+                continue;
+            }
+            let Some(name) = users_names.get_by_user(user_key) else { continue; };
+
+            let user = self.next_id();
+            self.users.insert(*user_key, user);
+            events.push(Event::Player {
+                user,
+                name: name.clone(),
+            });
+        }
+
+        let walls = room.walls.iter().filter_map(|entity| {
+            let wall = wall_query.get(*entity).ok()?;
+            let shape = Shape::Wall {
+                half_width: *wall.half_width_m,
+                half_height: *wall.half_height_m,
+            };
+            Some((*entity, shape))
+        });
+        let characters = room
+            .users
+            .iter()
+            .filter_map(|user| users_avatars.get_by_user(user))
+            .chain(room.npcs.iter())
+            .copied()
+            .chain(
+                corpse_query
+                    .iter()
+                    .filter(|(_, corpse)| corpse.room == room.key)
+                    .map(|(entity, _)| entity),
+            )
+            .map(|entity| (entity, Shape::Character));
+
+        let mut present = Vec::new();
+        for (entity, shape) in walls.chain(characters) {
+            let Ok(sync) = state_query.get(entity) else { continue; };
+            let state = BodyState {
+                x: *sync.pos_x_m,
+                y: *sync.pos_y_m,
+                linvel_x: *sync.linvel_x_m,
+                linvel_y: *sync.linvel_y_m,
+            };
+
+            let body = match self.bodies.get(&entity) {
+                Some(body) => *body,
+                None => {
+                    let body = self.next_id();
+                    self.bodies.insert(entity, body);
+                    events.push(Event::Spawn { body, shape, state });
+                    self.states.insert(body, state);
+                    body
+                }
+            };
+            if self.states.get(&body) != Some(&state) {
+                events.push(Event::State { body, state });
+                self.states.insert(body, state);
+            }
+            if let Ok(appearance) = appearance_query.get(entity) {
+                let look = *appearance.look;
+                if self.looks.insert(body, look) != Some(look) {
+                    events.push(Event::Appearance { body, look });
+                }
+            }
+            present.push(entity);
+        }
+
+        let (states, looks) = (&mut self.states, &mut self.looks);
+        self.bodies.retain(|entity, body| {
+            let keep = present.contains(entity);
+            if !keep {
+                events.push(Event::Despawn { body: *body });
+                states.remove(body);
+                looks.remove(body);
+            }
+            keep
+        });
+
+        let targets = room
+            .targets
+            .assignments()
+            .iter()
+            .filter_map(|(hunter, target)| {
+                Some((*self.users.get(hunter)?, *self.users.get(target)?))
+            })
+            .collect::<HashMap<_, _>>();
+        for (hunter, target) in targets.iter() {
+            if self.targets.get(hunter) != Some(target) {
+                events.push(Event::Target {
+                    hunter: *hunter,
+                    target: Some(*target),
+                });
+            }
+        }
+        for hunter in self.targets.keys() {
+            if !targets.contains_key(hunter) {
+                events.push(Event::Target {
+                    hunter: *hunter,
+                    target: None,
+                });
+            }
+        }
+        self.targets = targets;
+
+        if events.is_empty() {
+            return Ok(());
+        }
+        self.writer.write_frame(&Frame { tick, events })
+    }
+
+    fn finish(mut self) {
+        match self.writer.flush() {
+            Ok(()) => info!("Saved replay {}", self.path.display()),
+            Err(err) => error!("Could not save replay {}: {err}", self.path.display()),
+        }
+    }
+}
+
+/// Creates a replay file named after the stem, numbering it when the name is taken, e.g. by a room
+/// whose name is sanitised to the same as another's.
+fn create_new(dir: &Path, stem: &str) -> io::Result<(PathBuf, File)> {
+    let mut number = 1;
+    loop {
+        let path = match number {
+            1 => dir.join(format!("{stem}.replay")),
+            _ => dir.join(format!("{stem}-{number}.replay")),
+        };
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => number += 1,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Starts a recording when a room's match starts, adds a frame to it every tick and finishes it
+/// when the match ends.
+pub fn record_matches(
+    mut ticks: EventReader<TickEvent>,
+    cfg: Res<Config>,
+    mut recorder: ResMut<Recorder>,
+    rooms: Res<RoomManager>,
+    users_names: Res<UserNameMapping>,
+    users_avatars: Res<UserAvatarMapping>,
+    state_query: Query<&PhysicsStateSync>,
+    wall_query: Query<&WallEntity>,
+    corpse_query: Query<(Entity, &Corpse)>,
+    appearance_query: Query<&Appearance>,
+) {
+    let Some(TickEvent(tick)) = ticks.iter().last() else { return; };
+    let Some(dir) = &cfg.network.replay_dir else { return; };
+    let player_speed = cfg.gameplay.player_speed;
+
+    for room in rooms.rooms() {
+        let in_progress = room.phase == MatchPhase::InProgress;
+        if !in_progress && !recorder.recordings.contains_key(&room.key) {
+            continue;
+        }
+
+        let recording = recorder.recordings.entry(room.key).or_insert_with(|| {
+            Recording::start(dir, room, player_speed)
+                .map_err(|err| error!("Not recording room {}: {err}", room.settings.name))
+                .ok()
+        });
+
+        // The tick which ended the match is recorded too, as it holds the final kill
+        if let Some(active) = recording {
+            active.set_player_speed(player_speed);
+            let result = active.record(
+                *tick,
+                room,
+                &users_names,
+                &users_avatars,
+                &state_query,
+                &wall_query,
+                &corpse_query,
+                &appearance_query,
+            );
+            if let Err(err) = result {
+                error!("Stopped recording {}: {err}", active.path.display());
+                *recording = None;
+            }
+        }
+
+        if !in_progress {
+            if let Some(Some(recording)) = recorder.recordings.remove(&room.key) {
+                recording.finish();
+            }
+        }
+    }
+}
@@ -1,8 +1,5 @@
 use bevy::{prelude::*, utils::HashMap};
-use naia_bevy_server::{RoomKey, UserKey};
-
-#[derive(Resource)]
-pub struct MainRoomKey(pub RoomKey);
+use naia_bevy_server::UserKey;
 
 #[derive(Resource)]
 pub struct UserAvatarMapping {
@@ -77,6 +74,10 @@ impl UserNameMapping {
         }
     }
 
+    pub fn iter(&self) -> impl Iterator<Item = (&UserKey, &String)> {
+        self.user_to_name.iter()
+    }
+
     pub fn remove_by_name(&mut self, name: &String) {
         if let Some(key) = self.name_to_user.remove(name) {
             self.user_to_name.remove(&key);
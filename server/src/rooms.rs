@@ -0,0 +1,524 @@
+//! Rooms let a single server host several independent matches. Every room has its own player cap,
+//! password, map and target chain, and entities are only replicated to the members of the room they
+//! were added to.
+
+use std::{fmt, hash::Hash, str::FromStr};
+
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use bevy_rapier2d::prelude::*;
+use naia_bevy_server::{CommandsExt, Random, RoomKey, Server, UserKey};
+use serde::Deserialize;
+
+use shared::{
+    components::{MatchPhase, PhysicsStateSync, WallEntity},
+    maps::{MapLayout, WallSpec},
+    navigation::NavGrid,
+    units::CHARACTER_HALF_SIZE,
+};
+
+use crate::config::GameplayConfig;
+
+/// All rooms share the one Rapier world, so each room is given its own collision group to keep
+/// its characters and walls from touching those of other rooms. This caps the number of rooms.
+pub const MAX_ROOMS: usize = 32;
+
+/// The size of a room's navigation cells, in meters.
+const NAV_CELL_SIZE: f32 = 0.25;
+/// How far server-controlled agents must keep their centers from walls, in meters.
+const NAV_AGENT_RADIUS: f32 = CHARACTER_HALF_SIZE.x;
+
+/// The user-facing configuration of a room, parsed from `name:max_players[:map[:password]]` on the
+/// command line or read from a table in the config file.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RoomSettings {
+    pub name: String,
+    pub max_players: u8,
+    #[serde(default = "RoomSettings::default_map")]
+    pub map: String,
+    pub password: Option<String>,
+}
+
+impl RoomSettings {
+    fn default_map() -> String {
+        "open".to_owned()
+    }
+}
+
+impl FromStr for RoomSettings {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(4, ':');
+
+        let name = parts
+            .next()
+            .filter(|name| !name.is_empty())
+            .ok_or("missing room name")?
+            .to_owned();
+        let max_players = parts
+            .next()
+            .ok_or("missing player cap")?
+            .parse::<u8>()
+            .map_err(|e| format!("invalid player cap: {e}"))?;
+        let map = parts
+            .next()
+            .filter(|map| !map.is_empty())
+            .unwrap_or("open")
+            .to_owned();
+        let password = parts.next().map(str::to_owned);
+
+        if MapLayout::by_name(&map).is_none() {
+            return Err(format!(
+                "unknown map `{map}`, expected one of {:?}",
+                MapLayout::names()
+            ));
+        }
+
+        Ok(Self {
+            name,
+            max_players,
+            map,
+            password,
+        })
+    }
+}
+
+/// Why a user could not be placed in a room.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RoomRejection {
+    NoSuchRoom,
+    Full,
+    WrongPassword,
+}
+
+impl fmt::Display for RoomRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RoomRejection::NoSuchRoom => write!(f, "no such room"),
+            RoomRejection::Full => write!(f, "room is full"),
+            RoomRejection::WrongPassword => write!(f, "wrong password"),
+        }
+    }
+}
+
+/// A user's tally for the current match.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Score {
+    pub kills: u16,
+    /// Kills of anyone other than the user's target.
+    pub wrong_kills: u16,
+    pub deaths: u16,
+    pub survival_secs: f32,
+}
+
+impl Score {
+    /// Kills of the right target score points, and kills of anyone else cost points. Cannot
+    /// overflow, as the config keeps both amounts between 0 and `i16::MAX`.
+    pub fn points(&self, gameplay: &GameplayConfig) -> i32 {
+        i32::from(self.kills) * i32::from(gameplay.kill_points)
+            - i32::from(self.wrong_kills) * i32::from(gameplay.wrong_kill_penalty)
+    }
+}
+
+/// The ring of who is hunting whom. Every user hunts the next user in the ring, and the last user
+/// hunts the first. Generic over the key so that it can be tested without a naia server.
+pub struct TargetChain<K = UserKey> {
+    ring: Vec<K>,
+}
+
+impl<K> Default for TargetChain<K> {
+    fn default() -> Self {
+        Self { ring: Vec::new() }
+    }
+}
+
+impl<K: Copy + Eq + Hash> TargetChain<K> {
+    /// Inserts a user at a random position in the ring.
+    pub fn insert(&mut self, user: K) {
+        let index = Random::gen_range_u32(0, self.ring.len() as u32 + 1) as usize;
+        self.ring.insert(index.min(self.ring.len()), user);
+    }
+
+    pub fn remove(&mut self, user: &K) {
+        self.ring.retain(|u| u != user);
+    }
+
+    pub fn clear(&mut self) {
+        self.ring.clear();
+    }
+
+    pub fn users(&self) -> &[K] {
+        &self.ring
+    }
+
+    pub fn target_of(&self, hunter: &K) -> Option<K> {
+        if self.ring.len() < 2 {
+            return None;
+        }
+
+        let index = self.ring.iter().position(|u| u == hunter)?;
+        Some(self.ring[(index + 1) % self.ring.len()])
+    }
+
+    pub fn hunter_of(&self, target: &K) -> Option<K> {
+        if self.ring.len() < 2 {
+            return None;
+        }
+
+        let index = self.ring.iter().position(|u| u == target)?;
+        Some(self.ring[(index + self.ring.len() - 1) % self.ring.len()])
+    }
+
+    /// Every hunter and their target.
+    pub fn assignments(&self) -> HashMap<K, K> {
+        self.ring
+            .iter()
+            .filter_map(|hunter| Some((*hunter, self.target_of(hunter)?)))
+            .collect()
+    }
+}
+
+pub struct GameRoom {
+    pub key: RoomKey,
+    pub settings: RoomSettings,
+    pub map: MapLayout,
+    pub collision_groups: CollisionGroups,
+
+    /// Users placed in this room, including those which have been accepted but have not yet
+    /// finished connecting, and spectators.
+    pub users: Vec<UserKey>,
+    /// Users who joined only to watch. They take no part in matches and do not count towards the
+    /// player cap.
+    pub spectators: HashSet<UserKey>,
+    /// Eliminated players who asked to watch the rest of the current match.
+    pub watching: HashSet<UserKey>,
+    pub targets: TargetChain,
+    pub walls: Vec<Entity>,
+    /// Where server-controlled agents may walk in the room.
+    pub nav: NavGrid,
+    /// The geometry each wall was last baked into `nav` with, to unbake it when the wall changes.
+    pub nav_walls: HashMap<Entity, WallSpec>,
+    /// The crowd of computer-controlled characters for the current match.
+    pub npcs: Vec<Entity>,
+
+    /// The authoritative match phase, mirrored into the room's replicated `MatchState`.
+    pub phase: MatchPhase,
+    /// Counts down the current phase, for phases which have a time limit.
+    pub phase_timer: Timer,
+    pub match_entity: Option<Entity>,
+    /// The `PlayerInfo` entity of each connected user.
+    pub player_infos: HashMap<UserKey, Entity>,
+    /// The tally of each user who took part in the current match.
+    pub scores: HashMap<UserKey, Score>,
+    /// Set to abandon the current match and count down to a new one with the same players.
+    pub restart_requested: bool,
+
+    next_spawn: usize,
+}
+
+impl GameRoom {
+    /// The number of users placed in this room to play.
+    pub fn players(&self) -> usize {
+        self.users.len() - self.spectators.len()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.players() >= self.settings.max_players.into()
+    }
+
+    /// Whether the user should be told what spectators see, having joined to watch or asked to
+    /// after being eliminated.
+    pub fn is_spectating(&self, user: &UserKey) -> bool {
+        self.spectators.contains(user) || self.watching.contains(user)
+    }
+
+    /// Cycles through the map's spawn points.
+    pub fn next_spawn_point(&mut self) -> (f32, f32) {
+        if self.map.spawn_points.is_empty() {
+            return (0.0, 0.0);
+        }
+
+        let point = self.map.spawn_points[self.next_spawn % self.map.spawn_points.len()];
+        self.next_spawn = self.next_spawn.wrapping_add(1);
+        point
+    }
+}
+
+#[derive(Resource)]
+pub struct RoomManager {
+    rooms: Vec<GameRoom>,
+    user_rooms: HashMap<UserKey, usize>,
+}
+
+impl RoomManager {
+    pub fn new() -> Self {
+        Self {
+            rooms: Vec::new(),
+            user_rooms: HashMap::new(),
+        }
+    }
+
+    /// Registers a room, returning its index. Returns `None` if [`MAX_ROOMS`] has been reached or
+    /// the map is unknown.
+    pub fn add_room(&mut self, key: RoomKey, settings: RoomSettings) -> Option<usize> {
+        if self.rooms.len() >= MAX_ROOMS {
+            return None;
+        }
+
+        let map = MapLayout::by_name(&settings.map)?;
+        let nav = NavGrid::new(
+            map.half_width,
+            map.half_height,
+            NAV_CELL_SIZE,
+            NAV_AGENT_RADIUS,
+        );
+        let group = Group::from_bits_truncate(1 << self.rooms.len());
+
+        self.rooms.push(GameRoom {
+            key,
+            settings,
+            map,
+            collision_groups: CollisionGroups::new(group, group),
+            users: Vec::new(),
+            spectators: HashSet::new(),
+            watching: HashSet::new(),
+            targets: TargetChain::default(),
+            walls: Vec::new(),
+            nav,
+            nav_walls: HashMap::new(),
+            npcs: Vec::new(),
+            phase: MatchPhase::Lobby,
+            phase_timer: Timer::default(),
+            match_entity: None,
+            player_infos: HashMap::new(),
+            scores: HashMap::new(),
+            restart_requested: false,
+            next_spawn: 0,
+        });
+
+        Some(self.rooms.len() - 1)
+    }
+
+    pub fn rooms(&self) -> &[GameRoom] {
+        &self.rooms
+    }
+
+    pub fn rooms_mut(&mut self) -> &mut [GameRoom] {
+        &mut self.rooms
+    }
+
+    pub fn get(&self, index: usize) -> Option<&GameRoom> {
+        self.rooms.get(index)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut GameRoom> {
+        self.rooms.get_mut(index)
+    }
+
+    pub fn room_index_of(&self, user: &UserKey) -> Option<usize> {
+        self.user_rooms.get(user).copied()
+    }
+
+    pub fn room_of(&self, user: &UserKey) -> Option<&GameRoom> {
+        self.rooms.get(self.room_index_of(user)?)
+    }
+
+    pub fn room_of_mut(&mut self, user: &UserKey) -> Option<&mut GameRoom> {
+        let index = self.room_index_of(user)?;
+        self.rooms.get_mut(index)
+    }
+
+    /// Picks a room for a user who is authenticating. If a room was requested by name, only that
+    /// room is considered. Otherwise the fullest room which the user may join is chosen, so that
+    /// matches fill up before new ones are started. Spectators may join full rooms.
+    pub fn choose(
+        &self,
+        requested: Option<&str>,
+        password: &str,
+        spectate: bool,
+    ) -> Result<usize, RoomRejection> {
+        let may_join = |room: &GameRoom| match &room.settings.password {
+            Some(expected) => expected == password,
+            None => true,
+        };
+
+        if let Some(requested) = requested {
+            let index = self
+                .rooms
+                .iter()
+                .position(|room| room.settings.name == requested)
+                .ok_or(RoomRejection::NoSuchRoom)?;
+            let room = &self.rooms[index];
+
+            if !may_join(room) {
+                return Err(RoomRejection::WrongPassword);
+            }
+            if room.is_full() && !spectate {
+                return Err(RoomRejection::Full);
+            }
+
+            return Ok(index);
+        }
+
+        let mut candidates = self
+            .rooms
+            .iter()
+            .enumerate()
+            .filter(|(_, room)| may_join(room))
+            .peekable();
+
+        if candidates.peek().is_none() {
+            return Err(RoomRejection::WrongPassword);
+        }
+
+        candidates
+            .filter(|(_, room)| !room.is_full() || spectate)
+            .max_by_key(|(_, room)| room.players())
+            .map(|(index, _)| index)
+            .ok_or(RoomRejection::Full)
+    }
+
+    pub fn join(&mut self, user: UserKey, index: usize, spectate: bool) {
+        if let Some(room) = self.rooms.get_mut(index) {
+            room.users.push(user);
+            if spectate {
+                room.spectators.insert(user);
+            }
+            self.user_rooms.insert(user, index);
+        }
+    }
+
+    /// Removes the user from their room and its target chain, returning the room's index.
+    pub fn leave(&mut self, user: &UserKey) -> Option<usize> {
+        let index = self.user_rooms.remove(user)?;
+        if let Some(room) = self.rooms.get_mut(index) {
+            room.users.retain(|u| u != user);
+            room.spectators.remove(user);
+            room.watching.remove(user);
+            room.targets.remove(user);
+        }
+        Some(index)
+    }
+}
+
+/// Spawns the room's map geometry, adds it to the room and bakes it into the room's [`NavGrid`].
+pub fn spawn_walls(room: &mut GameRoom, server: &mut Server, commands: &mut Commands) {
+    for wall in room.map.walls.iter() {
+        let entity = commands
+            .spawn_empty()
+            .enable_replication(server)
+            .insert(WallEntity::new_complete(wall.half_width, wall.half_height))
+            .insert(PhysicsStateSync::new_complete(
+                0.0, 0.0, 0.0, wall.x, wall.y, 0.0,
+            ))
+            .insert(RigidBody::Fixed)
+            .insert(Collider::cuboid(wall.half_width, wall.half_height))
+            .insert(room.collision_groups)
+            .insert(TransformBundle::from_transform(Transform::from_xyz(
+                wall.x, wall.y, 0.0,
+            )))
+            .id();
+
+        server.room_mut(&room.key).add_entity(&entity);
+        room.walls.push(entity);
+        room.nav.add_wall(wall);
+        room.nav_walls.insert(entity, *wall);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ring(users: &[u32]) -> TargetChain<u32> {
+        TargetChain {
+            ring: users.to_vec(),
+        }
+    }
+
+    #[test]
+    fn everyone_hunts_the_next_in_the_ring() {
+        let chain = ring(&[1, 2, 3]);
+        assert_eq!(chain.target_of(&1), Some(2));
+        assert_eq!(chain.target_of(&3), Some(1));
+        assert_eq!(chain.hunter_of(&1), Some(3));
+        assert_eq!(chain.target_of(&4), None);
+
+        // Nobody hunts themselves
+        assert_eq!(ring(&[1]).target_of(&1), None);
+    }
+
+    #[test]
+    fn inserted_users_form_a_single_ring() {
+        let mut chain = TargetChain::default();
+        for user in 0..6 {
+            chain.insert(user);
+        }
+
+        // Following the targets from anyone visits everyone once before coming back
+        let mut seen = HashSet::new();
+        let mut hunter = 0;
+        for _ in 0..6 {
+            hunter = chain.target_of(&hunter).unwrap();
+            assert!(seen.insert(hunter));
+        }
+        assert_eq!(hunter, 0);
+        assert_eq!(chain.assignments().len(), 6);
+    }
+
+    #[test]
+    fn the_ring_closes_after_a_death() {
+        let mut chain = ring(&[1, 2, 3, 4]);
+
+        // 1 kills their target and is handed the victim's target
+        chain.remove(&2);
+        assert_eq!(chain.target_of(&1), Some(3));
+        assert_eq!(chain.hunter_of(&3), Some(1));
+
+        // A wrong kill closes the ring the same way, around the victim's hunter
+        chain.remove(&4);
+        assert_eq!(chain.target_of(&3), Some(1));
+        assert_eq!(chain.target_of(&1), Some(3));
+
+        // The last one standing hunts nobody
+        chain.remove(&3);
+        assert_eq!(chain.target_of(&1), None);
+        assert!(chain.assignments().is_empty());
+    }
+
+    #[test]
+    fn wrong_kills_cost_points() {
+        let gameplay = GameplayConfig {
+            kill_points: 3,
+            wrong_kill_penalty: 2,
+            ..default()
+        };
+        let score = Score {
+            kills: 2,
+            wrong_kills: 1,
+            ..default()
+        };
+        assert_eq!(score.points(&gameplay), 4);
+
+        // Even the largest tallies and amounts fit
+        let gameplay = GameplayConfig {
+            kill_points: i16::MAX,
+            wrong_kill_penalty: i16::MAX,
+            ..default()
+        };
+        let hero = Score {
+            kills: u16::MAX,
+            ..default()
+        };
+        let villain = Score {
+            wrong_kills: u16::MAX,
+            ..default()
+        };
+        assert_eq!(hero.points(&gameplay), 65535 * 32767);
+        assert_eq!(villain.points(&gameplay), -65535 * 32767);
+    }
+}
@@ -1,43 +1,103 @@
+use std::fmt;
+
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
 use naia_bevy_server::{
     events::{AuthEvents, ConnectEvent, DisconnectEvent, ErrorEvent, TickEvent},
-    CommandsExt, Random, Server,
+    Server, UserKey,
 };
 
 use shared::{
     channels::{GameMessageChannel, PlayerInputChannel},
     components::{CharacterEntity, PhysicsStateSync},
-    messages::{Auth, EntityAssignment, PlayerInput},
+    messages::{Auth, GameplaySettings, PlayerInput},
 };
 
 use crate::{
-    resources::{MainRoomKey, UserAvatarMapping, UserNameMapping},
-    Args,
+    anti_cheat::{AntiCheat, Ownership, Verdict},
+    bans::BanList,
+    combat::{AttackEvent, Corpse},
+    config::Config,
+    match_state::{despawn_avatar, spawn_player_info},
+    metrics::Metrics,
+    replay::Recorder,
+    resources::{UserAvatarMapping, UserNameMapping},
+    rooms::{RoomManager, RoomRejection},
 };
 
+/// The log target of per-input diagnostics, which are only logged at debug level. Enable them
+/// with e.g. `RUST_LOG=info,server::input=debug`.
+pub const INPUT_TARGET: &str = "server::input";
+
+/// Why a user could not authenticate.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Rejection {
+    Banned(Option<String>),
+    NameTaken,
+    Room(RoomRejection),
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::Banned(Some(reason)) => write!(f, "banned ({reason})"),
+            Rejection::Banned(None) => write!(f, "banned"),
+            Rejection::NameTaken => write!(f, "name is taken"),
+            Rejection::Room(reason) => write!(f, "{reason}"),
+        }
+    }
+}
+
+impl Rejection {
+    /// A short name for the reason, for metrics.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Rejection::Banned(_) => "banned",
+            Rejection::NameTaken => "name_taken",
+            Rejection::Room(RoomRejection::NoSuchRoom) => "no_such_room",
+            Rejection::Room(RoomRejection::Full) => "room_full",
+            Rejection::Room(RoomRejection::WrongPassword) => "wrong_password",
+        }
+    }
+}
+
 pub fn auth_events(
     mut event_reader: EventReader<AuthEvents>,
-    cfg: Res<Args>,
+    bans: Res<BanList>,
+    metrics: Res<Metrics>,
+    mut rooms: ResMut<RoomManager>,
     mut users_names: ResMut<UserNameMapping>,
     mut server: Server,
 ) {
     for events in event_reader.iter() {
         for (user_key, auth) in events.read::<Auth>() {
-            if server.users_count() > cfg.max_players.into() {
-                server.reject_connection(&user_key);
-            }
+            let address = server.user(&user_key).address().ip();
+            let _span = info_span!("auth", user = %auth.name, %address).entered();
 
-            if let Some(password) = &cfg.password {
-                if &auth.channel_password != password {
+            let room = if let Some(ban) = bans.check(&auth.name, address) {
+                Err(Rejection::Banned(ban.reason.clone()))
+            } else if users_names.get_by_name(&auth.name).is_some() {
+                Err(Rejection::NameTaken)
+            } else {
+                rooms
+                    .choose(auth.room.as_deref(), &auth.channel_password, auth.spectate)
+                    .map_err(Rejection::Room)
+            };
+
+            let room = match room {
+                Ok(room) => room,
+                Err(reason) => {
+                    info!(%reason, "Rejected");
+                    metrics.auth_rejected(reason.label());
                     server.reject_connection(&user_key);
+                    continue;
                 }
-            }
+            };
 
-            if users_names.get_by_name(&auth.name).is_some() {
-                server.reject_connection(&user_key);
-            }
+            let room_name = &rooms.get(room).unwrap().settings.name;
+            info!(room = %room_name, spectate = auth.spectate, "Accepted");
 
+            rooms.join(user_key, room, auth.spectate);
             users_names.insert(user_key, auth.name);
             server.accept_connection(&user_key);
         }
@@ -46,78 +106,70 @@ pub fn auth_events(
 
 pub fn connect_events(
     mut event_reader: EventReader<ConnectEvent>,
-    main_room_key: Res<MainRoomKey>,
+    cfg: Res<Config>,
+    mut rooms: ResMut<RoomManager>,
     users_names: Res<UserNameMapping>,
-    mut users_avatars: ResMut<UserAvatarMapping>,
     mut server: Server,
     mut commands: Commands,
 ) {
     for ConnectEvent(user_key) in event_reader.iter() {
-        let address = server
-            .user_mut(user_key)
-            .enter_room(&main_room_key.0)
-            .address();
-
-        let Some(name) = users_names.get_by_user(&user_key) else { return; };
-        info!("User {name} connected on {address}");
-
-        // TODO: Round-robin spawn points?
-        let x = Random::gen_range_f32(-2.0, 2.0);
-        let y = Random::gen_range_f32(-2.0, 2.0);
-
-        let state = PhysicsStateSync::new_complete(0.0, 0.0, 0.0, x, y, 0.0);
-
-        let entity = commands
-            .spawn_empty()
-            .enable_replication(&mut server)
-            .insert(CharacterEntity)
-            .insert(state)
-            .insert(Velocity {
-                linvel: Vec2::new(0.0, 0.0),
-                angvel: 0.0,
-            })
-            .insert(RigidBody::Dynamic)
-            .insert(Damping {
-                linear_damping: 1.0,
-                angular_damping: 1.0,
-            })
-            .insert(Collider::cuboid(0.5, 0.5))
-            .insert(Restitution::coefficient(0.2))
-            .insert(TransformBundle::from_transform(Transform::from_xyz(
-                x, y, 1.0,
-            )))
-            .id();
-
-        server.room_mut(&main_room_key.0).add_entity(&entity);
-
-        users_avatars.insert(*user_key, entity);
-
-        let mut assignment_msg = EntityAssignment::new(true);
-        assignment_msg.entity.set(&server, &entity);
-
-        server.send_message::<GameMessageChannel, EntityAssignment>(user_key, &assignment_msg);
+        let Some(room) = rooms.room_of_mut(user_key) else { continue; };
+        let address = server.user_mut(user_key).enter_room(&room.key).address();
+
+        let Some(name) = users_names.get_by_user(&user_key) else { continue; };
+        let _span =
+            info_span!("connect", user = %name, room = %room.settings.name, %address).entered();
+        info!("Connected");
+
+        // Spectators are left out of the lobby, and so of every match
+        if !room.spectators.contains(user_key) {
+            spawn_player_info(room, *user_key, name.clone(), &mut server, &mut commands);
+        }
+
+        let settings = cfg.gameplay_settings();
+        server.send_message::<GameMessageChannel, GameplaySettings>(user_key, &settings);
     }
 }
 
 pub fn disconnect_events(
     mut event_reader: EventReader<DisconnectEvent>,
-    main_room_key: Res<MainRoomKey>,
+    mut rooms: ResMut<RoomManager>,
     mut users_names: ResMut<UserNameMapping>,
     mut users_avatars: ResMut<UserAvatarMapping>,
+    mut anti_cheat: ResMut<AntiCheat>,
     mut server: Server,
     mut commands: Commands,
 ) {
     for DisconnectEvent(user_key, _user) in event_reader.iter() {
-        let Some(name) = users_names.get_by_user(user_key) else { return; };
-        info!("User {name} disconnecting");
+        anti_cheat.forget(user_key);
+        let Some(name) = users_names.get_by_user(user_key) else { continue; };
+        let room_name = rooms
+            .room_of(user_key)
+            .map_or("-", |room| room.settings.name.as_str());
+        let _span = info_span!("disconnect", user = %name, room = %room_name).entered();
+        info!("Disconnecting");
 
-        if let Some(entity) = users_avatars.get_by_user(user_key) {
-            commands.entity(*entity).despawn();
-            server.room_mut(&main_room_key.0).remove_entity(entity);
-        }
+        let room_index = rooms.leave(user_key);
 
-        users_avatars.remove_by_user(user_key);
         users_names.remove_by_user(user_key);
+
+        let Some(room) = room_index.and_then(|index| rooms.get_mut(index)) else {
+            users_avatars.remove_by_user(user_key);
+            continue;
+        };
+
+        despawn_avatar(
+            room,
+            user_key,
+            &mut users_avatars,
+            &mut server,
+            &mut commands,
+        );
+
+        if let Some(entity) = room.player_infos.remove(user_key) {
+            commands.entity(entity).despawn();
+            server.room_mut(&room.key).remove_entity(&entity);
+        }
     }
 }
 
@@ -130,55 +182,148 @@ pub fn error_events(mut event_reader: EventReader<ErrorEvent>) {
 /// "Main loopt" happens here
 pub fn tick_events(
     mut event_reader: EventReader<TickEvent>,
+    cfg: Res<Config>,
+    metrics: Res<Metrics>,
+    mut anti_cheat: ResMut<AntiCheat>,
+    mut recorder: ResMut<Recorder>,
+    rooms: Res<RoomManager>,
+    users_names: Res<UserNameMapping>,
+    users_avatars: Res<UserAvatarMapping>,
     mut velocity_query: Query<&mut Velocity>,
     transform_query: Query<&Transform>,
+    character_query: Query<&Transform, (With<CharacterEntity>, Without<Corpse>)>,
     mut position_query: Query<&mut PhysicsStateSync>,
+    mut attacks: EventWriter<AttackEvent>,
     mut server: Server,
 ) {
     let mut has_ticked = false;
 
     for TickEvent(server_tick) in event_reader.iter() {
         has_ticked = true;
+        let _tick_span = info_span!("tick", tick = server_tick).entered();
+        anti_cheat.start_tick();
 
         let mut messages = server.receive_tick_buffer_messages(server_tick);
-        for (_user_key, input) in messages.read::<PlayerInputChannel, PlayerInput>() {
-            let Some(entity) = &input.entity.get(&server) else { continue; };
+        for (user_key, input) in messages.read::<PlayerInputChannel, PlayerInput>() {
+            metrics.input_received();
 
-            let Ok(mut velocity) = velocity_query.get_mut(*entity) else { continue; };
-            let Ok(transform) = transform_query.get(*entity) else { continue; };
-            let Ok(mut physics) = position_query.get_mut(*entity) else { continue; };
+            // Per-input diagnostics are off by default, see `INPUT_TARGET`
+            let _input_span = debug_span!(
+                target: INPUT_TARGET,
+                "input",
+                user = users_names.get_by_user(&user_key).map_or("-", String::as_str),
+                room = rooms
+                    .room_of(&user_key)
+                    .map_or("-", |room| room.settings.name.as_str()),
+            )
+            .entered();
+            debug!(
+                target: INPUT_TARGET,
+                x = input.x_axis,
+                y = input.y_axis,
+                attack = input.attack,
+                "Received"
+            );
 
-            warn!("{}, {}", input.x_axis, input.y_axis);
+            let entity = input.entity.get(&server);
+            let ownership = Ownership {
+                entity,
+                avatar: users_avatars.get_by_user(&user_key).copied(),
+                is_character: entity.map_or(false, |entity| character_query.contains(entity)),
+            };
+            match anti_cheat.check(&cfg.anti_cheat, user_key, *server_tick, &input, ownership) {
+                Verdict::Apply => {}
+                Verdict::Ignore => {
+                    debug!(target: INPUT_TARGET, "Dropped: no character");
+                    metrics.input_dropped("no_character");
+                    continue;
+                }
+                Verdict::Reject(violation) => {
+                    metrics.input_dropped(violation.label());
+                    let kick = anti_cheat.record(&cfg.anti_cheat, user_key);
+                    let violations = anti_cheat.violations(&user_key);
+                    if kick {
+                        warn!(%violation, violations, "Kicking");
+                        if server.user_exists(&user_key) {
+                            server.user_mut(&user_key).disconnect();
+                        }
+                    } else if violations == 1 {
+                        warn!(%violation, "Dropped");
+                    } else {
+                        debug!(%violation, violations, "Dropped");
+                    }
+                    continue;
+                }
+            }
 
-            // Do not process invalid inputs
-            if input.x_axis > 1.0
-                || input.y_axis > 1.0
-                || input.x_axis < -1.0
-                || input.y_axis < -1.0
-            {
-                continue;
+            if let (Some(room), Some(entity)) = (rooms.room_of(&user_key), entity) {
+                recorder.input(room.key, user_key, entity, &input);
             }
 
+            let character = entity.and_then(|entity| {
+                Some((
+                    velocity_query.get_mut(entity).ok()?,
+                    transform_query.get(entity).ok()?,
+                    position_query.get_mut(entity).ok()?,
+                ))
+            });
+            let Some((mut velocity, transform, mut physics)) = character else {
+                debug!(target: INPUT_TARGET, "Dropped: no character");
+                metrics.input_dropped("no_character");
+                continue;
+            };
+
             // Set velocity
-            velocity.linvel.x = input.x_axis;
-            velocity.linvel.y = input.y_axis;
-            *physics.linvel_x_m = input.x_axis;
-            *physics.linvel_y_m = input.y_axis;
+            let speed = cfg.gameplay.player_speed;
+            velocity.linvel.x = input.x_axis * speed;
+            velocity.linvel.y = input.y_axis * speed;
+            *physics.linvel_x_m = velocity.linvel.x;
+            *physics.linvel_y_m = velocity.linvel.y;
             // Update network position
             *physics.pos_x_m = transform.translation.x;
             *physics.pos_y_m = transform.translation.y;
-            error!("{}, {}", transform.translation.x, transform.translation.y);
+            debug!(
+                target: INPUT_TARGET,
+                x = transform.translation.x,
+                y = transform.translation.y,
+                "Applied"
+            );
+
+            if input.attack {
+                attacks.send(AttackEvent { attacker: user_key });
+            }
         }
     }
 
     if has_ticked {
         for (_, user_key, entity) in server.scope_checks() {
-            // TODO: Interest management here
-            server.user_scope(&user_key).include(&entity);
+            if in_view(&cfg, &user_key, entity, &users_avatars, &character_query) {
+                server.user_scope(&user_key).include(&entity);
+            } else {
+                server.user_scope(&user_key).exclude(&entity);
+            }
         }
     }
 }
 
+/// Whether `entity` is close enough to the user's own character to be replicated to them, going by
+/// the configured view radius.
+fn in_view(
+    cfg: &Config,
+    user_key: &UserKey,
+    entity: Entity,
+    users_avatars: &UserAvatarMapping,
+    character_query: &Query<&Transform, (With<CharacterEntity>, Without<Corpse>)>,
+) -> bool {
+    let Some(radius) = cfg.scoping.view_radius_m else { return true; };
+    let Some(avatar) = users_avatars.get_by_user(user_key) else { return true; };
+    let (Ok(own), Ok(other)) = (character_query.get(*avatar), character_query.get(entity)) else {
+        return true;
+    };
+
+    own.translation.truncate().distance(other.translation.truncate()) <= radius
+}
+
 pub fn sync_physics(
     rapier_query: Query<(&Transform, &Velocity)>,
     mut state_query: Query<(Entity, &mut PhysicsStateSync)>,
@@ -0,0 +1,116 @@
+//! Telling spectators who everyone is. Spectators receive every entity in their room like any user
+//! without a character, but names and targets are not replicated, so each spectator is sent a
+//! [`SpectatedPlayer`] for every player, and again whenever that player's character or target
+//! changes.
+
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use naia_bevy_server::{events::MessageEvents, Server, UserKey};
+
+use shared::{
+    channels::{PlayerActionChannel, SpectatorChannel},
+    components::MatchPhase,
+    messages::{Spectate, SpectatedPlayer},
+};
+
+use crate::{
+    resources::{UserAvatarMapping, UserNameMapping},
+    rooms::RoomManager,
+};
+
+/// A player's character and their target's character.
+type Sighting = (Option<Entity>, Option<Entity>);
+
+/// What spectators have been told so far.
+#[derive(Default, Resource)]
+pub struct SpectatorFeed {
+    /// The last sighting sent of each player.
+    sent: HashMap<UserKey, Sighting>,
+    /// Spectators who have been sent every player.
+    briefed: HashSet<UserKey>,
+}
+
+/// Handles [`Spectate`] requests from players eliminated from the match in progress.
+pub fn spectate_events(
+    mut event_reader: EventReader<MessageEvents>,
+    mut rooms: ResMut<RoomManager>,
+    users_avatars: Res<UserAvatarMapping>,
+) {
+    for events in event_reader.iter() {
+        for (user_key, _) in events.read::<PlayerActionChannel, Spectate>() {
+            let Some(room) = rooms.room_of_mut(&user_key) else { continue; };
+            let eliminated = room.phase == MatchPhase::InProgress
+                && room.scores.contains_key(&user_key)
+                && users_avatars.get_by_user(&user_key).is_none();
+
+            if eliminated && room.watching.insert(user_key) {
+                info!(
+                    "Room {}: eliminated player now spectating",
+                    room.settings.name
+                );
+            }
+        }
+    }
+}
+
+/// Sends spectators each player whose character or target has changed, and newly arrived
+/// spectators every player.
+pub fn update_spectators(
+    rooms: Res<RoomManager>,
+    users_names: Res<UserNameMapping>,
+    users_avatars: Res<UserAvatarMapping>,
+    mut feed: ResMut<SpectatorFeed>,
+    mut server: Server,
+) {
+    let feed = &mut *feed;
+    let mut players = HashSet::new();
+    let mut spectators = HashSet::new();
+
+    for room in rooms.rooms() {
+        let watchers = room
+            .users
+            .iter()
+            .filter(|user| room.is_spectating(user) && server.user_exists(user))
+            .copied()
+            .collect::<Vec<_>>();
+        spectators.extend(watchers.iter().copied());
+
+        for user_key in room.player_infos.keys() {
+            players.insert(*user_key);
+            let Some(name) = users_names.get_by_user(user_key) else { continue; };
+
+            let avatar = users_avatars.get_by_user(user_key).copied();
+            let target = room
+                .targets
+                .target_of(user_key)
+                .and_then(|target| users_avatars.get_by_user(&target))
+                .copied();
+            let changed = feed.sent.insert(*user_key, (avatar, target)) != Some((avatar, target));
+
+            let recipients = watchers
+                .iter()
+                .filter(|watcher| changed || !feed.briefed.contains(watcher))
+                .collect::<Vec<_>>();
+            if recipients.is_empty() {
+                continue;
+            }
+
+            let mut message = SpectatedPlayer::new(name.clone());
+            if let Some(avatar) = &avatar {
+                message.avatar.set(&server, avatar);
+            }
+            if let Some(target) = &target {
+                message.target.set(&server, target);
+            }
+
+            for watcher in recipients {
+                server.send_message::<SpectatorChannel, SpectatedPlayer>(watcher, &message);
+            }
+        }
+    }
+
+    feed.sent.retain(|user, _| players.contains(user));
+    feed.briefed = spectators;
+}
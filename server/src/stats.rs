@@ -0,0 +1,54 @@
+//! Keeping each user's replicated [`PlayerStats`] in step with their [`Score`].
+//!
+//! [`Score`]: crate::rooms::Score
+
+use bevy::prelude::*;
+
+use shared::components::{MatchPhase, PlayerStats};
+
+use crate::{config::Config, resources::UserAvatarMapping, rooms::RoomManager};
+
+/// Accumulates survival time for living avatars and mirrors every user's score into their
+/// [`PlayerStats`]. Users without a score, such as those who joined mid-match, show zeroes.
+pub fn update_player_stats(
+    cfg: Res<Config>,
+    time: Res<Time>,
+    mut rooms: ResMut<RoomManager>,
+    users_avatars: Res<UserAvatarMapping>,
+    mut stats_query: Query<&mut PlayerStats>,
+) {
+    for room in rooms.rooms_mut() {
+        if room.phase == MatchPhase::InProgress {
+            for (user, score) in room.scores.iter_mut() {
+                if users_avatars.get_by_user(user).is_some() {
+                    score.survival_secs += time.delta_seconds();
+                }
+            }
+        }
+
+        for (user, entity) in room.player_infos.iter() {
+            let Ok(mut stats) = stats_query.get_mut(*entity) else { continue; };
+            let score = room.scores.get(user).copied().unwrap_or_default();
+
+            if *stats.kills != score.kills {
+                *stats.kills = score.kills;
+            }
+            if *stats.wrong_kills != score.wrong_kills {
+                *stats.wrong_kills = score.wrong_kills;
+            }
+            if *stats.deaths != score.deaths {
+                *stats.deaths = score.deaths;
+            }
+
+            let survival_secs = score.survival_secs as u16;
+            if *stats.survival_secs != survival_secs {
+                *stats.survival_secs = survival_secs;
+            }
+
+            let points = score.points(&cfg.gameplay);
+            if *stats.points != points {
+                *stats.points = points;
+            }
+        }
+    }
+}
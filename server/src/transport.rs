@@ -0,0 +1,219 @@
+//! Choosing which transports the server listens on.
+//!
+//! Naia only listens on a single socket, so when several transports are enabled they are combined
+//! into a [`MultiSocket`], which lets browser and native clients share the same rooms.
+
+use std::{
+    net::SocketAddr,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use naia_bevy_server::{
+    transport::{udp, webrtc, PacketReceiver, PacketSender, RecvError, SendError, Socket},
+    Server,
+};
+use serde::Deserialize;
+
+use shared::link_condition::LinkCondition;
+
+use crate::{
+    config::Config,
+    metrics::{Metrics, Traffic},
+};
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    /// WebRTC data channels, which browsers need. Takes a signaling and a data address.
+    WebRtc,
+    /// Plain UDP on a single address, for native clients on a LAN or during development.
+    Udp,
+    Both,
+}
+
+impl Transport {
+    pub fn webrtc(self) -> bool {
+        matches!(self, Transport::WebRtc | Transport::Both)
+    }
+
+    pub fn udp(self) -> bool {
+        matches!(self, Transport::Udp | Transport::Both)
+    }
+}
+
+impl FromStr for Transport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "webrtc" => Ok(Transport::WebRtc),
+            "udp" => Ok(Transport::Udp),
+            "both" => Ok(Transport::Both),
+            _ => Err(format!(
+                "unknown transport `{s}`, expected `webrtc`, `udp` or `both`"
+            )),
+        }
+    }
+}
+
+/// How long the route of an address which is not a user is kept after its last packet, so that
+/// clients part way through the handshake can still be answered.
+const STRAY_ROUTE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Starts listening on every transport enabled in the [`Config`].
+pub fn listen(cfg: Res<Config>, metrics: Res<Metrics>, routes: Res<Routes>, mut server: Server) {
+    let network = &cfg.network;
+
+    #[cfg(feature = "link-conditioner")]
+    let condition = network.link_condition;
+    #[cfg(not(feature = "link-conditioner"))]
+    let condition = LinkCondition::Off;
+
+    if condition != LinkCondition::Off {
+        warn!("Simulating a {condition} link on incoming packets");
+    }
+
+    let mut config = server.socket_config().clone();
+    config.link_condition = condition.config();
+
+    let mut sockets: Vec<Box<dyn Socket>> = Vec::new();
+
+    if network.transport.webrtc() {
+        // The config is checked for both addresses whenever WebRTC is enabled
+        let (addr, webrtc_addr) = (network.addr.unwrap(), network.webrtc_addr.unwrap());
+        let addrs = webrtc::ServerAddrs::new(addr, webrtc_addr, &format!("http://{webrtc_addr}"));
+
+        info!("Listening for WebRTC clients on {addr}");
+        sockets.push(webrtc::Socket::new(&addrs, &config).into());
+    }
+
+    if network.transport.udp() {
+        let udp_addr = network.udp_addr.unwrap();
+
+        info!("Listening for UDP clients on {udp_addr}");
+        sockets.push(udp::Socket::new(&udp_addr, &config).into());
+    }
+
+    server.listen(MultiSocket::new(sockets, metrics.traffic(), routes.clone()));
+}
+
+/// Forgets the routes of addresses which are no longer users, such as those which disconnected or
+/// were rejected, once they have gone quiet.
+pub fn prune_routes(routes: Res<Routes>, server: Server) {
+    let addresses = server
+        .user_keys()
+        .iter()
+        .map(|user_key| server.user(user_key).address())
+        .collect::<HashSet<_>>();
+
+    routes.0.lock().unwrap().retain(|address, route| {
+        addresses.contains(address) || route.heard.elapsed() < STRAY_ROUTE_TIMEOUT
+    });
+}
+
+/// A socket which listens on several others, answering each client over the socket its packets
+/// arrived on. It also counts each client's traffic.
+pub struct MultiSocket {
+    sockets: Vec<Box<dyn Socket>>,
+    traffic: Traffic,
+    routes: Routes,
+}
+
+impl MultiSocket {
+    pub fn new(sockets: Vec<Box<dyn Socket>>, traffic: Traffic, routes: Routes) -> Self {
+        Self {
+            sockets,
+            traffic,
+            routes,
+        }
+    }
+}
+
+impl Socket for MultiSocket {
+    fn listen(self: Box<Self>) -> (Box<dyn PacketSender>, Box<dyn PacketReceiver>) {
+        let (senders, receivers) = self
+            .sockets
+            .into_iter()
+            .map(|socket| socket.listen())
+            .unzip();
+        let routes = self.routes;
+
+        (
+            Box::new(MultiSender {
+                senders,
+                routes: routes.clone(),
+                traffic: self.traffic.clone(),
+            }),
+            Box::new(MultiReceiver {
+                receivers,
+                routes,
+                traffic: self.traffic,
+            }),
+        )
+    }
+}
+
+impl From<MultiSocket> for Box<dyn Socket> {
+    fn from(socket: MultiSocket) -> Self {
+        Box::new(socket)
+    }
+}
+
+/// Which of the combined sockets each client address was last heard from, shared between the
+/// socket's halves and [`prune_routes`].
+#[derive(Clone, Default, Resource)]
+pub struct Routes(Arc<Mutex<HashMap<SocketAddr, Route>>>);
+
+#[derive(Clone, Copy)]
+struct Route {
+    socket: usize,
+    heard: Instant,
+}
+
+struct MultiSender {
+    senders: Vec<Box<dyn PacketSender>>,
+    routes: Routes,
+    traffic: Traffic,
+}
+
+impl PacketSender for MultiSender {
+    fn send(&self, address: &SocketAddr, payload: &[u8]) -> Result<(), SendError> {
+        let route = self.routes.0.lock().unwrap().get(address).copied();
+        let Some(Route { socket, .. }) = route else { return Err(SendError); };
+
+        self.senders[socket].send(address, payload)?;
+        self.traffic.sent(*address, payload.len());
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+struct MultiReceiver {
+    receivers: Vec<Box<dyn PacketReceiver>>,
+    routes: Routes,
+    traffic: Traffic,
+}
+
+impl PacketReceiver for MultiReceiver {
+    fn receive(&mut self) -> Result<Option<(SocketAddr, &[u8])>, RecvError> {
+        for (index, receiver) in self.receivers.iter_mut().enumerate() {
+            if let Some((address, payload)) = receiver.receive()? {
+                let route = Route {
+                    socket: index,
+                    heard: Instant::now(),
+                };
+                self.routes.0.lock().unwrap().insert(address, route);
+                self.traffic.received(address, payload.len());
+                return Ok(Some((address, payload)));
+            }
+        }
+
+        Ok(None)
+    }
+}
@@ -0,0 +1,119 @@
+use std::{env, fs, process};
+
+use server::{config::Config, resources::UserNameMapping};
+
+use common::{Harness, TIMEOUT};
+
+mod common;
+
+#[test]
+fn list_shows_connected_users() {
+    let mut harness = Harness::new(&[]);
+    let client = harness.add_client("alice", "");
+    assert!(harness.run_until(TIMEOUT, |h| h.log(client).connected));
+
+    let reply = harness.admin("list");
+    assert!(reply.starts_with("alice\tmain\t"), "{reply}");
+}
+
+#[test]
+fn kick_disconnects_the_user() {
+    let mut harness = Harness::new(&[]);
+    let client = harness.add_client("alice", "");
+    assert!(harness.run_until(TIMEOUT, |h| h.log(client).connected));
+
+    assert_eq!(harness.admin("kick alice"), "disconnected alice");
+    assert!(harness.run_until(TIMEOUT, |h| h.log(client).disconnected));
+    assert!(harness
+        .server
+        .world
+        .resource::<UserNameMapping>()
+        .get_by_name(&"alice".to_owned())
+        .is_none());
+
+    assert_eq!(harness.admin("kick alice"), "no user named alice");
+}
+
+#[test]
+fn kick_disconnects_users_still_connecting() {
+    let mut harness = Harness::new(&[]);
+    harness.add_client("alice", "");
+
+    // The name is taken as soon as the server accepts alice. Dropping her client then leaves her
+    // handshake unfinished for as long as the server waits
+    let named = |h: &mut Harness| {
+        h.server
+            .world
+            .resource::<UserNameMapping>()
+            .get_by_name(&"alice".to_owned())
+            .is_some()
+    };
+    assert!(harness.run_until(TIMEOUT, named));
+    harness.clients.clear();
+
+    assert_eq!(harness.admin("kick alice"), "disconnected alice");
+    assert!(harness.run_until(TIMEOUT, |h| !named(h)));
+    assert_eq!(harness.admin("list"), "no users connected");
+    assert_eq!(harness.admin("kick alice"), "no user named alice");
+}
+
+#[test]
+fn set_changes_gameplay_values_only() {
+    let mut harness = Harness::new(&[]);
+
+    assert_eq!(
+        harness.admin("set gameplay.player_speed 2.5"),
+        "gameplay.player_speed set"
+    );
+    assert_eq!(
+        harness
+            .server
+            .world
+            .resource::<Config>()
+            .gameplay
+            .player_speed,
+        2.5
+    );
+
+    assert!(harness
+        .admin("set gameplay.player_speed -1")
+        .contains("positive"));
+    assert!(harness
+        .admin("set network.max_players 2")
+        .contains("while running"));
+    assert!(harness
+        .admin("set gameplay.no_such_value 1")
+        .contains("no_such_value"));
+}
+
+#[test]
+fn unknown_commands_are_answered() {
+    let mut harness = Harness::new(&[]);
+
+    assert!(harness.admin("frobnicate").contains("unknown command"));
+    assert!(harness.admin("status").contains("room main: Lobby"));
+}
+
+#[test]
+fn bans_refuse_names_and_addresses_until_lifted() {
+    let path = env::temp_dir().join(format!("assassin-bans-{}.toml", process::id()));
+    let mut harness = Harness::new(&["--ban-file", path.to_str().unwrap()]);
+    let alice = harness.add_client("alice", "");
+    assert!(harness.run_until(TIMEOUT, |h| h.log(alice).connected));
+
+    let reply = harness.admin("ban alice 1h griefing");
+    assert!(reply.ends_with("disconnected 1 users"), "{reply}");
+    assert!(harness.run_until(TIMEOUT, |h| h.log(alice).disconnected));
+    assert!(fs::read_to_string(&path).unwrap().contains("griefing"));
+
+    // Every test client shares alice's address
+    let bob = harness.add_client("bob", "");
+    assert!(harness.run_until(TIMEOUT, |h| h.log(bob).rejected));
+
+    assert_eq!(harness.admin("unban alice"), "lifted 1 bans");
+    assert_eq!(harness.admin("bans"), "nobody is banned");
+    let carol = harness.add_client("carol", "");
+    assert!(harness.run_until(TIMEOUT, |h| h.log(carol).connected));
+
+    fs::remove_file(path).unwrap();
+}
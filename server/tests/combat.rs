@@ -0,0 +1,46 @@
+use std::time::Duration;
+
+use shared::components::MatchPhase;
+
+use common::{Harness, TIMEOUT};
+
+mod common;
+
+#[test]
+fn killing_the_target_wins_the_match() {
+    let mut harness = Harness::new(&[
+        "--countdown-secs",
+        "0",
+        "--npcs",
+        "0",
+        "--win-condition",
+        "kills:1",
+    ]);
+    let alice = harness.add_client("alice", "");
+    let bob = harness.add_client("bob", "");
+    assert!(harness.run_until(TIMEOUT, |h| h.log(alice).connected && h.log(bob).connected));
+
+    harness.ready_up(alice, true);
+    harness.ready_up(bob, true);
+    assert!(harness.run_until(TIMEOUT, |h| {
+        h.log(alice).avatar.is_some() && h.log(bob).avatar.is_some()
+    }));
+
+    // With two players bob is alice's target, so striking him from within reach is a right kill
+    harness.teleport("alice", 0.0, 0.0);
+    harness.teleport("bob", 1.0, 0.0);
+    harness.run_for(Duration::from_millis(100));
+    harness.attack(alice);
+
+    assert!(harness.run_until(TIMEOUT, |h| h.log(bob).summary.is_some()));
+    let summary = harness.log(bob).summary.as_ref().unwrap();
+    assert_eq!(summary.winner.as_deref(), Some("alice"));
+
+    let entry = |name: &str| summary.entries.iter().find(|e| e.name == name).unwrap();
+    let winner = entry("alice");
+    assert_eq!((winner.kills, winner.wrong_kills, winner.score), (1, 0, 1));
+    assert_eq!(entry("bob").deaths, 1);
+
+    // The room shows the summary before returning to the lobby
+    assert!(harness.run_until(TIMEOUT, |h| h.phase(alice) == Some(MatchPhase::PostGame)));
+}
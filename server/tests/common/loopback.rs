@@ -0,0 +1,195 @@
+//! An in-memory transport which connects a server and its clients within one process.
+
+use std::{
+    collections::VecDeque,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    sync::{Arc, Mutex},
+};
+
+use bevy::utils::HashMap;
+use naia_bevy_client::transport as client;
+use naia_bevy_server::transport as server;
+
+const SERVER_PORT: u16 = 14191;
+
+pub fn server_addr() -> SocketAddr {
+    SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, SERVER_PORT))
+}
+
+/// The packets in flight between one server and any number of clients. Packets are delivered in
+/// order and never lost.
+#[derive(Clone, Default)]
+pub struct LoopbackNetwork {
+    queues: Arc<Mutex<Queues>>,
+}
+
+#[derive(Default)]
+struct Queues {
+    to_server: VecDeque<(SocketAddr, Vec<u8>)>,
+    to_clients: HashMap<SocketAddr, VecDeque<Vec<u8>>>,
+    next_port: u16,
+}
+
+impl LoopbackNetwork {
+    pub fn server_socket(&self) -> ServerSocket {
+        ServerSocket {
+            network: self.clone(),
+        }
+    }
+
+    /// Creates a socket for a new client, with an address of its own.
+    pub fn client_socket(&self) -> ClientSocket {
+        let mut queues = self.queues.lock().unwrap();
+        queues.next_port += 1;
+        let addr = SocketAddr::V4(SocketAddrV4::new(
+            Ipv4Addr::LOCALHOST,
+            SERVER_PORT + queues.next_port,
+        ));
+        queues.to_clients.insert(addr, VecDeque::new());
+
+        ClientSocket {
+            network: self.clone(),
+            addr,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ServerSocket {
+    network: LoopbackNetwork,
+}
+
+impl server::Socket for ServerSocket {
+    fn listen(
+        self: Box<Self>,
+    ) -> (
+        Box<dyn server::PacketSender>,
+        Box<dyn server::PacketReceiver>,
+    ) {
+        (
+            Box::new(ServerSender {
+                network: self.network.clone(),
+            }),
+            Box::new(ServerReceiver {
+                network: self.network,
+                buffer: Vec::new(),
+            }),
+        )
+    }
+}
+
+impl From<ServerSocket> for Box<dyn server::Socket> {
+    fn from(socket: ServerSocket) -> Self {
+        Box::new(socket)
+    }
+}
+
+struct ServerSender {
+    network: LoopbackNetwork,
+}
+
+impl server::PacketSender for ServerSender {
+    fn send(&self, address: &SocketAddr, payload: &[u8]) -> Result<(), server::SendError> {
+        let mut queues = self.network.queues.lock().unwrap();
+        let Some(queue) = queues.to_clients.get_mut(address) else {
+            return Err(server::SendError);
+        };
+        queue.push_back(payload.to_vec());
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+struct ServerReceiver {
+    network: LoopbackNetwork,
+    /// The last packet received, which the returned slice borrows from.
+    buffer: Vec<u8>,
+}
+
+impl server::PacketReceiver for ServerReceiver {
+    fn receive(&mut self) -> Result<Option<(SocketAddr, &[u8])>, server::RecvError> {
+        let next = self.network.queues.lock().unwrap().to_server.pop_front();
+        let Some((addr, packet)) = next else { return Ok(None); };
+
+        self.buffer = packet;
+        Ok(Some((addr, &self.buffer)))
+    }
+}
+
+#[derive(Clone)]
+pub struct ClientSocket {
+    network: LoopbackNetwork,
+    addr: SocketAddr,
+}
+
+impl client::Socket for ClientSocket {
+    fn connect(
+        self: Box<Self>,
+    ) -> (
+        Box<dyn client::PacketSender>,
+        Box<dyn client::PacketReceiver>,
+    ) {
+        (
+            Box::new(ClientSender {
+                network: self.network.clone(),
+                addr: self.addr,
+            }),
+            Box::new(ClientReceiver {
+                network: self.network,
+                addr: self.addr,
+                buffer: Vec::new(),
+            }),
+        )
+    }
+}
+
+impl From<ClientSocket> for Box<dyn client::Socket> {
+    fn from(socket: ClientSocket) -> Self {
+        Box::new(socket)
+    }
+}
+
+struct ClientSender {
+    network: LoopbackNetwork,
+    addr: SocketAddr,
+}
+
+impl client::PacketSender for ClientSender {
+    fn send(&self, payload: &[u8]) -> Result<(), client::SendError> {
+        let mut queues = self.network.queues.lock().unwrap();
+        queues.to_server.push_back((self.addr, payload.to_vec()));
+        Ok(())
+    }
+
+    fn server_addr(&self) -> client::ServerAddr {
+        client::ServerAddr::Found(server_addr())
+    }
+}
+
+#[derive(Clone)]
+struct ClientReceiver {
+    network: LoopbackNetwork,
+    addr: SocketAddr,
+    buffer: Vec<u8>,
+}
+
+impl client::PacketReceiver for ClientReceiver {
+    fn receive(&mut self) -> Result<Option<&[u8]>, client::RecvError> {
+        let next = self
+            .network
+            .queues
+            .lock()
+            .unwrap()
+            .to_clients
+            .get_mut(&self.addr)
+            .and_then(VecDeque::pop_front);
+        let Some(packet) = next else { return Ok(None); };
+
+        self.buffer = packet;
+        Ok(Some(&self.buffer))
+    }
+
+    fn server_addr(&self) -> client::ServerAddr {
+        client::ServerAddr::Found(server_addr())
+    }
+}
@@ -0,0 +1,399 @@
+//! Runs a server and its clients in one process over a [`LoopbackNetwork`], stepping every [`App`]
+//! and its clock by hand.
+
+#![allow(dead_code)]
+
+use std::{sync::mpsc, thread, time::Duration};
+
+use bevy::{prelude::*, time::TimeUpdateStrategy, utils::HashMap};
+use clap::Parser;
+use naia_bevy_client::{
+    events::{ClientTickEvent, ConnectEvent, DisconnectEvent, MessageEvents, RejectEvent},
+    Client, ClientConfig, Plugin as ClientPlugin, ReceiveEvents,
+};
+use naia_bevy_server::Server;
+
+use server::{
+    admin::{AdminConsole, AdminRequest},
+    build_app,
+    config::Config,
+    resources::{UserAvatarMapping, UserNameMapping},
+    Args,
+};
+use shared::{
+    channels::{GameMessageChannel, PlayerActionChannel, PlayerInputChannel, SpectatorChannel},
+    components::{Look, MatchPhase, MatchState},
+    messages::{
+        Auth, ChangeDisguise, EntityAssignment, MatchSummary, PlayerInput, ReadyUp, Spectate,
+        SpectatedPlayer, TargetHint,
+    },
+    protocol,
+};
+
+use loopback::LoopbackNetwork;
+
+pub mod loopback;
+
+/// How long tests wait with [`Harness::run_until`] for something to happen. The connection
+/// handshake alone takes several ticks, so this is generous.
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How far every app's [`Time`] advances in one [`Harness::update`], whatever the wall clock says,
+/// so that timers in the game logic do not depend on how fast the test runs. Naia still ticks on
+/// the wall clock, so updates are also spaced this far apart in real time.
+pub const FRAME: Duration = Duration::from_millis(2);
+
+pub struct Harness {
+    network: LoopbackNetwork,
+    pub server: App,
+    pub clients: Vec<App>,
+}
+
+/// What a test client has seen of the server.
+#[derive(Default, Resource)]
+pub struct ClientLog {
+    pub connected: bool,
+    pub rejected: bool,
+    pub disconnected: bool,
+    /// The avatar most recently assigned by an [`EntityAssignment`].
+    pub avatar: Option<Entity>,
+    /// The latest character of each player, as told to spectators.
+    pub spectated: HashMap<String, Option<Entity>>,
+    /// The target's look from the latest [`TargetHint`].
+    pub target_look: Option<Look>,
+    /// The latest [`MatchSummary`].
+    pub summary: Option<MatchSummary>,
+}
+
+/// Something a test asks a client to do on its next update.
+enum ClientAction {
+    ReadyUp(bool),
+    Spectate,
+    ChangeDisguise,
+    Steer(f32, f32),
+    Attack,
+    Disconnect,
+}
+
+#[derive(Default, Resource)]
+struct PendingActions(Vec<ClientAction>);
+
+/// What a client tells its avatar to do every tick, as a player holding keys would.
+#[derive(Default, Resource)]
+struct Controls {
+    x_axis: f32,
+    y_axis: f32,
+    /// Set to strike on the next tick only.
+    attack: bool,
+}
+
+impl Harness {
+    /// Starts a server with the given extra arguments, e.g. `["--npcs", "0"]`. The socket
+    /// addresses and player cap are filled in.
+    pub fn new(extra_args: &[&str]) -> Self {
+        let args = Args::parse_from(
+            [
+                "server",
+                "--addr",
+                "127.0.0.1:0",
+                "--webrtc-addr",
+                "127.0.0.1:0",
+                "--max-players",
+                "4",
+            ]
+            .iter()
+            .chain(extra_args),
+        );
+
+        let network = LoopbackNetwork::default();
+        let socket = network.server_socket();
+
+        let config = Config::load(&args).unwrap();
+        let mut server = build_app(args, config);
+        server.insert_resource(TimeUpdateStrategy::ManualDuration(FRAME));
+        server.add_startup_system(move |mut server: Server| server.listen(socket.clone()));
+        server.update();
+
+        Self {
+            network,
+            server,
+            clients: Vec::new(),
+        }
+    }
+
+    /// Adds a client which authenticates with the given name and password, returning its index.
+    pub fn add_client(&mut self, name: &str, password: &str) -> usize {
+        self.add_user(name, password, false)
+    }
+
+    /// Adds a client which joins to watch rather than play, returning its index.
+    pub fn add_spectator(&mut self, name: &str) -> usize {
+        self.add_user(name, "", true)
+    }
+
+    fn add_user(&mut self, name: &str, password: &str, spectate: bool) -> usize {
+        let (name, password) = (name.to_owned(), password.to_owned());
+        let socket = self.network.client_socket();
+
+        let mut app = App::default();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(ClientPlugin::new(ClientConfig::default(), protocol()))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(FRAME))
+            .init_resource::<ClientLog>()
+            .init_resource::<PendingActions>()
+            .init_resource::<Controls>()
+            .add_startup_system(move |mut client: Client| {
+                client.auth(Auth {
+                    name: name.clone(),
+                    channel_password: password.clone(),
+                    room: None,
+                    spectate,
+                });
+                client.connect(socket.clone());
+            })
+            .add_systems(
+                (
+                    record_connection,
+                    record_rejection,
+                    record_disconnection,
+                    record_assignment,
+                    record_spectated,
+                    record_target_hint,
+                    record_summary,
+                )
+                    .chain()
+                    .in_set(ReceiveEvents),
+            )
+            .add_systems((apply_actions, send_controls).chain().after(ReceiveEvents));
+        app.update();
+
+        self.clients.push(app);
+        self.clients.len() - 1
+    }
+
+    /// Updates the server and then every client once.
+    pub fn update(&mut self) {
+        self.server.update();
+        for client in self.clients.iter_mut() {
+            client.update();
+        }
+    }
+
+    /// Updates everything until `done` returns true, giving up after `timeout` of game time.
+    pub fn run_until(
+        &mut self,
+        timeout: Duration,
+        mut done: impl FnMut(&mut Self) -> bool,
+    ) -> bool {
+        let frames = timeout.as_micros() / FRAME.as_micros();
+        for _ in 0..frames {
+            self.update();
+            if done(self) {
+                return true;
+            }
+            thread::sleep(FRAME);
+        }
+        false
+    }
+
+    /// Updates everything for a fixed duration, e.g. to check that something does not happen.
+    pub fn run_for(&mut self, duration: Duration) {
+        self.run_until(duration, |_| false);
+    }
+
+    pub fn log(&self, client: usize) -> &ClientLog {
+        self.clients[client].world.resource::<ClientLog>()
+    }
+
+    /// The phase of the client's room, once it has been replicated.
+    pub fn phase(&mut self, client: usize) -> Option<MatchPhase> {
+        let world = &mut self.clients[client].world;
+        let mut states = world.query::<&MatchState>();
+        states.iter(world).next().map(|state| *state.phase)
+    }
+
+    pub fn ready_up(&mut self, client: usize, ready: bool) {
+        self.push_action(client, ClientAction::ReadyUp(ready));
+    }
+
+    pub fn spectate(&mut self, client: usize) {
+        self.push_action(client, ClientAction::Spectate);
+    }
+
+    pub fn change_disguise(&mut self, client: usize) {
+        self.push_action(client, ClientAction::ChangeDisguise);
+    }
+
+    /// Keeps the client's avatar moving along the given axes until told otherwise.
+    pub fn steer(&mut self, client: usize, x_axis: f32, y_axis: f32) {
+        self.push_action(client, ClientAction::Steer(x_axis, y_axis));
+    }
+
+    /// Strikes the character nearest to the client's avatar.
+    pub fn attack(&mut self, client: usize) {
+        self.push_action(client, ClientAction::Attack);
+    }
+
+    pub fn disconnect(&mut self, client: usize) {
+        self.push_action(client, ClientAction::Disconnect);
+    }
+
+    /// Runs an admin console command, updating everything until the server replies.
+    pub fn admin(&mut self, line: &str) -> String {
+        let (reply, replies) = mpsc::channel();
+        let request = AdminRequest {
+            line: line.to_owned(),
+            reply,
+        };
+        let console = self.server.world.resource::<AdminConsole>();
+        console.sender().send(request).unwrap();
+
+        let mut text = None;
+        self.run_until(TIMEOUT, |_| {
+            text = replies.try_recv().ok();
+            text.is_some()
+        });
+        text.expect("no reply from the admin console")
+    }
+
+    /// The named player's character on the server.
+    pub fn avatar_of(&self, name: &str) -> Entity {
+        let user = self
+            .server
+            .world
+            .resource::<UserNameMapping>()
+            .get_by_name(&name.to_owned())
+            .copied()
+            .unwrap();
+        *self
+            .server
+            .world
+            .resource::<UserAvatarMapping>()
+            .get_by_user(&user)
+            .unwrap()
+    }
+
+    /// Moves the named player's character on the server.
+    pub fn teleport(&mut self, name: &str, x: f32, y: f32) {
+        let avatar = self.avatar_of(name);
+        let mut transform = self.server.world.get_mut::<Transform>(avatar).unwrap();
+        transform.translation.x = x;
+        transform.translation.y = y;
+    }
+
+    fn push_action(&mut self, client: usize, action: ClientAction) {
+        self.clients[client]
+            .world
+            .resource_mut::<PendingActions>()
+            .0
+            .push(action);
+    }
+}
+
+fn record_connection(mut event_reader: EventReader<ConnectEvent>, mut log: ResMut<ClientLog>) {
+    for _ in event_reader.iter() {
+        log.connected = true;
+    }
+}
+
+fn record_rejection(mut event_reader: EventReader<RejectEvent>, mut log: ResMut<ClientLog>) {
+    for _ in event_reader.iter() {
+        log.rejected = true;
+    }
+}
+
+fn record_disconnection(
+    mut event_reader: EventReader<DisconnectEvent>,
+    mut log: ResMut<ClientLog>,
+) {
+    for _ in event_reader.iter() {
+        log.disconnected = true;
+    }
+}
+
+fn record_assignment(
+    mut event_reader: EventReader<MessageEvents>,
+    mut log: ResMut<ClientLog>,
+    client: Client,
+) {
+    for events in event_reader.iter() {
+        for message in events.read::<GameMessageChannel, EntityAssignment>() {
+            if message.assign {
+                log.avatar = message.entity.get(&client);
+            }
+        }
+    }
+}
+
+fn record_spectated(
+    mut event_reader: EventReader<MessageEvents>,
+    mut log: ResMut<ClientLog>,
+    client: Client,
+) {
+    for events in event_reader.iter() {
+        for message in events.read::<SpectatorChannel, SpectatedPlayer>() {
+            let avatar = message.avatar.get(&client);
+            log.spectated.insert(message.name, avatar);
+        }
+    }
+}
+
+fn record_target_hint(mut event_reader: EventReader<MessageEvents>, mut log: ResMut<ClientLog>) {
+    for events in event_reader.iter() {
+        for hint in events.read::<GameMessageChannel, TargetHint>() {
+            log.target_look = hint.look;
+        }
+    }
+}
+
+fn record_summary(mut event_reader: EventReader<MessageEvents>, mut log: ResMut<ClientLog>) {
+    for events in event_reader.iter() {
+        for summary in events.read::<GameMessageChannel, MatchSummary>() {
+            log.summary = Some(summary);
+        }
+    }
+}
+
+fn apply_actions(
+    mut actions: ResMut<PendingActions>,
+    mut controls: ResMut<Controls>,
+    mut client: Client,
+) {
+    for action in actions.0.drain(..) {
+        match action {
+            ClientAction::ReadyUp(ready) => {
+                client.send_message::<PlayerActionChannel, ReadyUp>(&ReadyUp { ready })
+            }
+            ClientAction::Spectate => {
+                client.send_message::<PlayerActionChannel, Spectate>(&Spectate)
+            }
+            ClientAction::ChangeDisguise => {
+                client.send_message::<PlayerActionChannel, ChangeDisguise>(&ChangeDisguise)
+            }
+            ClientAction::Steer(x_axis, y_axis) => {
+                controls.x_axis = x_axis;
+                controls.y_axis = y_axis;
+            }
+            ClientAction::Attack => controls.attack = true,
+            ClientAction::Disconnect => client.disconnect(),
+        }
+    }
+}
+
+/// Sends the client's controls for its avatar on every tick, once it has one.
+fn send_controls(
+    mut event_reader: EventReader<ClientTickEvent>,
+    mut controls: ResMut<Controls>,
+    log: Res<ClientLog>,
+    mut client: Client,
+) {
+    let Some(avatar) = log.avatar else { return; };
+
+    for ClientTickEvent(tick) in event_reader.iter() {
+        let mut input =
+            PlayerInput::from_axes(controls.x_axis, controls.y_axis).with_attack(controls.attack);
+        input.entity.set(&client, &avatar);
+        client.send_tick_buffer_message::<PlayerInputChannel, PlayerInput>(tick, &input);
+        controls.attack = false;
+    }
+}
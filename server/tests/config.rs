@@ -0,0 +1,150 @@
+use std::{env, fs, path::PathBuf, process};
+
+use clap::Parser;
+use server::{config::Config, Args, WinCondition};
+
+/// Writes `text` to a config file unique to this test process.
+fn config_file(name: &str, text: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("assassin-{}-{name}.toml", process::id()));
+    fs::write(&path, text).unwrap();
+    path
+}
+
+fn load(path: &PathBuf, extra_args: &[&str]) -> Result<Config, String> {
+    let path = path.to_str().unwrap();
+    let args = Args::parse_from(["server", "--config", path].iter().chain(extra_args));
+    let config = Config::load(&args);
+    fs::remove_file(path).unwrap();
+    config
+}
+
+#[test]
+fn flags_override_the_file() {
+    let path = config_file(
+        "override",
+        r#"
+            [network]
+            addr = "127.0.0.1:2000"
+            webrtc_addr = "127.0.0.1:2001"
+
+            [gameplay]
+            win_condition = "kills:3"
+            player_speed = 1.5
+            round_secs = 120
+        "#,
+    );
+
+    let config = load(&path, &["--round-secs", "60"]).unwrap();
+
+    assert_eq!(config.gameplay.win_condition, WinCondition::FirstToKills(3));
+    assert_eq!(config.gameplay.player_speed, 1.5);
+    assert_eq!(config.gameplay.npc_speed(), 1.5);
+    assert_eq!(config.gameplay.round_secs, 60);
+    assert_eq!(config.gameplay.npcs, 8);
+}
+
+#[test]
+fn invalid_files_are_rejected() {
+    let unknown = config_file("unknown", "[gameplay]\nplayer_sped = 2.0\n");
+    assert!(load(&unknown, &[]).is_err());
+
+    let missing_addr = config_file("missing-addr", "[network]\ntransport = \"udp\"\n");
+    assert!(load(&missing_addr, &[]).is_err());
+
+    let stopped = config_file(
+        "stopped",
+        r#"
+            [network]
+            transport = "udp"
+            udp_addr = "127.0.0.1:2002"
+
+            [gameplay]
+            player_speed = 0.0
+        "#,
+    );
+    assert!(load(&stopped, &[]).is_err());
+
+    let negative = config_file(
+        "negative",
+        r#"
+            [network]
+            transport = "udp"
+            udp_addr = "127.0.0.1:2002"
+
+            [gameplay]
+            wrong_kill_penalty = -1
+        "#,
+    );
+    assert!(load(&negative, &[]).is_err());
+}
+
+#[test]
+fn duplicate_room_names_are_rejected() {
+    let path = config_file(
+        "duplicate-rooms",
+        r#"
+            [network]
+            transport = "udp"
+            udp_addr = "127.0.0.1:2004"
+
+            [[network.rooms]]
+            name = "main"
+            max_players = 8
+
+            [[network.rooms]]
+            name = "main"
+            max_players = 4
+        "#,
+    );
+
+    let err = load(&path, &[]).unwrap_err();
+    assert!(err.contains("main"), "{err}");
+}
+
+#[test]
+fn anti_cheat_values_can_change() {
+    let path = config_file(
+        "anti-cheat",
+        r#"
+            [network]
+            transport = "udp"
+            udp_addr = "127.0.0.1:2003"
+
+            [anti_cheat]
+            kick_threshold = 50
+        "#,
+    );
+    let config = load(&path, &[]).unwrap();
+    assert_eq!(config.anti_cheat.kick_threshold, 50);
+    assert_eq!(config.anti_cheat.max_inputs_per_tick, 1);
+
+    let config = config.with_value("anti_cheat.kick_threshold", "0").unwrap();
+    assert_eq!(config.anti_cheat.kick_threshold, 0);
+    assert!(config.with_value("anti_cheat.max_inputs", "2").is_err());
+    assert!(config
+        .with_value("anti_cheat.max_inputs_per_tick", "0")
+        .is_err());
+}
+
+#[test]
+fn nonsensical_gameplay_values_are_rejected() {
+    let path = config_file(
+        "gameplay",
+        r#"
+            [network]
+            transport = "udp"
+            udp_addr = "127.0.0.1:2005"
+        "#,
+    );
+    let config = load(&path, &[]).unwrap();
+
+    for (key, value) in [
+        ("gameplay.min_players", "1"),
+        ("gameplay.round_secs", "0"),
+        ("gameplay.npc_speed", "nan"),
+        ("gameplay.player_speed", "inf"),
+    ] {
+        assert!(config.with_value(key, value).is_err(), "{key} = {value}");
+    }
+    assert!(config.with_value("gameplay.npc_speed", "0.5").is_ok());
+}
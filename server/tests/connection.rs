@@ -0,0 +1,169 @@
+use bevy::prelude::*;
+
+use server::resources::{UserAvatarMapping, UserNameMapping};
+use shared::components::{PhysicsStateSync, WallEntity};
+
+use common::{Harness, TIMEOUT};
+
+mod common;
+
+#[test]
+fn wrong_password_is_rejected() {
+    let mut harness = Harness::new(&["--password", "hunter2"]);
+    let client = harness.add_client("alice", "wrong");
+
+    assert!(harness.run_until(TIMEOUT, |h| h.log(client).rejected));
+    assert!(!harness.log(client).connected);
+    assert!(harness
+        .server
+        .world
+        .resource::<UserNameMapping>()
+        .get_by_name(&"alice".to_owned())
+        .is_none());
+}
+
+#[test]
+fn taken_name_is_rejected() {
+    let mut harness = Harness::new(&[]);
+    let first = harness.add_client("alice", "");
+    assert!(harness.run_until(TIMEOUT, |h| h.log(first).connected));
+
+    let second = harness.add_client("alice", "");
+    assert!(harness.run_until(TIMEOUT, |h| h.log(second).rejected));
+    assert!(harness.log(first).connected);
+}
+
+#[test]
+fn walls_are_replicated_on_connect() {
+    let mut harness = Harness::new(&[]);
+    let client = harness.add_client("alice", "");
+
+    assert!(harness.run_until(TIMEOUT, |h| {
+        let world = &mut h.clients[client].world;
+        world
+            .query_filtered::<(), (With<WallEntity>, With<PhysicsStateSync>)>()
+            .iter(world)
+            .count()
+            > 0
+    }));
+}
+
+#[test]
+fn match_start_assigns_and_replicates_avatars() {
+    let mut harness = Harness::new(&["--countdown-secs", "0", "--npcs", "0"]);
+    let alice = harness.add_client("alice", "");
+    let bob = harness.add_client("bob", "");
+    assert!(harness.run_until(TIMEOUT, |h| h.log(alice).connected && h.log(bob).connected));
+
+    harness.ready_up(alice, true);
+    harness.ready_up(bob, true);
+
+    // The assignment may arrive before the avatar's components, so wait for both
+    let avatar_synced = |h: &mut Harness, client: usize| {
+        let Some(avatar) = h.log(client).avatar else { return false; };
+        h.clients[client]
+            .world
+            .get::<PhysicsStateSync>(avatar)
+            .is_some()
+    };
+    let both_synced = |h: &mut Harness| avatar_synced(h, alice) && avatar_synced(h, bob);
+    assert!(harness.run_until(TIMEOUT, both_synced));
+
+    // Every avatar starts on one of the map's spawn points
+    let spawn_points = shared::maps::MapLayout::open().spawn_points;
+    for client in [alice, bob] {
+        let avatar = harness.log(client).avatar.unwrap();
+        let sync = harness.clients[client]
+            .world
+            .get::<PhysicsStateSync>(avatar)
+            .unwrap();
+        let position = Vec2::new(*sync.pos_x_m, *sync.pos_y_m);
+
+        assert!(spawn_points
+            .iter()
+            .any(|(x, y)| position.distance(Vec2::new(*x, *y)) < 0.5));
+    }
+
+    let avatars = harness.server.world.resource::<UserAvatarMapping>();
+    let names = harness.server.world.resource::<UserNameMapping>();
+    for name in ["alice", "bob"] {
+        let user = names.get_by_name(&name.to_owned()).unwrap();
+        assert!(avatars.get_by_user(user).is_some());
+    }
+}
+
+#[test]
+fn disconnect_cleans_up_mappings() {
+    let mut harness = Harness::new(&["--countdown-secs", "0", "--npcs", "0"]);
+    let alice = harness.add_client("alice", "");
+    let bob = harness.add_client("bob", "");
+    assert!(harness.run_until(TIMEOUT, |h| h.log(alice).connected && h.log(bob).connected));
+
+    harness.ready_up(alice, true);
+    harness.ready_up(bob, true);
+    assert!(harness.run_until(TIMEOUT, |h| h.log(alice).avatar.is_some()));
+
+    let user = *harness
+        .server
+        .world
+        .resource::<UserNameMapping>()
+        .get_by_name(&"alice".to_owned())
+        .unwrap();
+    let avatar = *harness
+        .server
+        .world
+        .resource::<UserAvatarMapping>()
+        .get_by_user(&user)
+        .unwrap();
+
+    harness.disconnect(alice);
+    assert!(harness.run_until(TIMEOUT, |h| {
+        h.server
+            .world
+            .resource::<UserNameMapping>()
+            .get_by_user(&user)
+            .is_none()
+    }));
+
+    let avatars = harness.server.world.resource::<UserAvatarMapping>();
+    assert!(avatars.get_by_user(&user).is_none());
+    assert!(avatars.get_by_entity(&avatar).is_none());
+    assert!(harness
+        .server
+        .world
+        .resource::<UserNameMapping>()
+        .get_by_name(&"alice".to_owned())
+        .is_none());
+    assert!(harness.server.world.get_entity(avatar).is_none());
+
+    // The other player stays connected
+    assert!(!harness.log(bob).disconnected);
+}
+
+#[test]
+fn spectators_watch_without_playing() {
+    let mut harness = Harness::new(&["--countdown-secs", "0", "--npcs", "0"]);
+    let carol = harness.add_spectator("carol");
+    let alice = harness.add_client("alice", "");
+    let bob = harness.add_client("bob", "");
+    assert!(harness.run_until(TIMEOUT, |h| {
+        [carol, alice, bob]
+            .into_iter()
+            .all(|client| h.log(client).connected)
+    }));
+
+    // The spectator is not in the lobby, so the players alone can start the match
+    harness.ready_up(alice, true);
+    harness.ready_up(bob, true);
+
+    let told_of = |h: &mut Harness, name: &str| {
+        let Some(Some(avatar)) = h.log(carol).spectated.get(name).copied() else { return false; };
+        h.clients[carol]
+            .world
+            .get::<PhysicsStateSync>(avatar)
+            .is_some()
+    };
+    assert!(harness.run_until(TIMEOUT, |h| told_of(h, "alice") && told_of(h, "bob")));
+    assert!(harness.log(carol).avatar.is_none());
+    assert!(!harness.log(carol).spectated.contains_key("carol"));
+}
@@ -0,0 +1,47 @@
+use std::time::Duration;
+
+use shared::components::{Appearance, Look};
+
+use common::{Harness, TIMEOUT};
+
+mod common;
+
+fn look(harness: &Harness, name: &str) -> Look {
+    let avatar = harness.avatar_of(name);
+    *harness.server.world.get::<Appearance>(avatar).unwrap().look
+}
+
+#[test]
+fn disguises_change_at_wardrobes_and_hunters_see_them_up_close() {
+    let mut harness = Harness::new(&["--countdown-secs", "0", "--npcs", "0"]);
+    let alice = harness.add_client("alice", "");
+    let bob = harness.add_client("bob", "");
+    assert!(harness.run_until(TIMEOUT, |h| h.log(alice).connected && h.log(bob).connected));
+
+    harness.ready_up(alice, true);
+    harness.ready_up(bob, true);
+
+    // With two players each hunts the other, and is told how they look
+    assert!(harness.run_until(TIMEOUT, |h| h.log(bob).target_look.is_some()));
+    let before = look(&harness, "alice");
+    assert_eq!(harness.log(bob).target_look, Some(before));
+
+    // The spawn points are away from the open map's wardrobes
+    harness.change_disguise(alice);
+    harness.run_for(Duration::from_millis(300));
+    assert_eq!(look(&harness, "alice"), before);
+
+    // At opposite wardrobes the change is out of bob's sight
+    harness.teleport("alice", -7.0, 0.0);
+    harness.teleport("bob", 7.0, 0.0);
+    harness.run_for(Duration::from_millis(100));
+    harness.change_disguise(alice);
+    assert!(harness.run_until(TIMEOUT, |h| look(h, "alice") != before));
+    harness.run_for(Duration::from_millis(300));
+    assert_eq!(harness.log(bob).target_look, Some(before));
+
+    // Up close, bob sees the new disguise
+    harness.teleport("bob", -5.0, 0.0);
+    let after = look(&harness, "alice");
+    assert!(harness.run_until(TIMEOUT, |h| h.log(bob).target_look == Some(after)));
+}
@@ -0,0 +1,82 @@
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+    time::Duration,
+};
+
+use server::metrics::{Metrics, MetricsAddr, MAX_REQUESTS};
+
+use common::{Harness, TIMEOUT};
+
+mod common;
+
+#[test]
+fn users_and_rejections_are_counted() {
+    let mut harness = Harness::new(&["--password", "hunter2"]);
+    let alice = harness.add_client("alice", "hunter2");
+    let mallory = harness.add_client("mallory", "wrong");
+    assert!(harness.run_until(TIMEOUT, |h| {
+        h.log(alice).connected && h.log(mallory).rejected
+    }));
+    harness.update();
+
+    let text = harness.server.world.resource::<Metrics>().render();
+    assert!(text.contains("\nassassin_users 1\n"), "{text}");
+    assert!(text.contains("assassin_rooms{phase=\"lobby\"} 1"), "{text}");
+    assert!(
+        text.contains("assassin_room_users{room=\"main\"} 1"),
+        "{text}"
+    );
+    assert!(
+        text.contains("assassin_auth_rejections_total{reason=\"wrong_password\"} 1"),
+        "{text}"
+    );
+}
+
+/// Starts a server serving metrics on a port picked by the OS, returning it and the address.
+fn serve() -> (Harness, SocketAddr) {
+    let harness = Harness::new(&["--metrics-addr", "127.0.0.1:0"]);
+    let addr = harness.server.world.resource::<MetricsAddr>().0;
+    (harness, addr)
+}
+
+fn scrape(addr: SocketAddr) -> String {
+    let mut scraper = TcpStream::connect(addr).unwrap();
+    scraper
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    scraper.write_all(b"GET /metrics HTTP/1.1\r\n\r\n").unwrap();
+
+    let mut response = String::new();
+    scraper.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn stalled_scrapers_do_not_block_others() {
+    let (_harness, addr) = serve();
+
+    // Connects first but never sends its request
+    let _stalled = TcpStream::connect(addr).unwrap();
+
+    let response = scrape(addr);
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+}
+
+#[test]
+fn scrapers_beyond_the_cap_are_turned_away() {
+    let (_harness, addr) = serve();
+
+    let _stalled = (0..MAX_REQUESTS)
+        .map(|_| TcpStream::connect(addr).unwrap())
+        .collect::<Vec<_>>();
+
+    // Answered without waiting for the request
+    let mut refused = TcpStream::connect(addr).unwrap();
+    refused
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let mut response = String::new();
+    refused.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 503"), "{response}");
+}
@@ -0,0 +1,47 @@
+use std::{env, fs, process};
+
+use shared::{
+    components::MatchPhase,
+    replay::{verify::verify, Replay},
+};
+
+use common::{Harness, TIMEOUT};
+
+mod common;
+
+#[test]
+fn recorded_matches_verify() {
+    let dir = env::temp_dir().join(format!("assassin-replays-{}", process::id()));
+    let mut harness = Harness::new(&[
+        "--countdown-secs",
+        "0",
+        "--npcs",
+        "0",
+        "--round-secs",
+        "1",
+        "--replay-dir",
+        dir.to_str().unwrap(),
+    ]);
+    let alice = harness.add_client("alice", "");
+    let bob = harness.add_client("bob", "");
+    assert!(harness.run_until(TIMEOUT, |h| h.log(alice).connected && h.log(bob).connected));
+
+    harness.ready_up(alice, true);
+    harness.ready_up(bob, true);
+    harness.steer(alice, 1.0, 0.0);
+    harness.steer(bob, 0.0, 1.0);
+    assert!(harness.run_until(TIMEOUT, |h| h.phase(alice) == Some(MatchPhase::PostGame)));
+
+    let files = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    assert_eq!(files.len(), 1, "{files:?}");
+    let replay = Replay::load(&files[0]).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert!(!replay.truncated);
+    let report = verify(&replay, 0.05);
+    assert!(report.inputs > 0);
+    assert!(report.mismatches.is_empty(), "{:?}", report.mismatches);
+}
@@ -8,10 +8,23 @@ pub struct PlayerInputChannel;
 
 /// For "messages" to individual players related to the game. This includes:
 ///   * Entity assignment
-///   * Target assignment
+///   * Target assignment and hints
+///   * Match summaries
+///   * Gameplay settings
+///   * Announcements
 #[derive(Channel)]
 pub struct GameMessageChannel;
 
+/// For client-to-server requests which are not tied to a tick, such as readying up in the lobby,
+/// changing disguise or asking to spectate.
+#[derive(Channel)]
+pub struct PlayerActionChannel;
+
+/// For what spectators are told about every player, i.e. their characters and targets. Ordered, so
+/// that a player's latest state always wins.
+#[derive(Channel)]
+pub struct SpectatorChannel;
+
 pub struct ChannelsPlugin;
 impl ProtocolPlugin for ChannelsPlugin {
     fn build(&self, protocol: &mut naia_bevy_shared::Protocol) {
@@ -23,6 +36,14 @@ impl ProtocolPlugin for ChannelsPlugin {
             .add_channel::<GameMessageChannel>(
                 ChannelDirection::ServerToClient,
                 ChannelMode::UnorderedReliable(ReliableSettings::default()),
+            )
+            .add_channel::<PlayerActionChannel>(
+                ChannelDirection::ClientToServer,
+                ChannelMode::OrderedReliable(ReliableSettings::default()),
+            )
+            .add_channel::<SpectatorChannel>(
+                ChannelDirection::ServerToClient,
+                ChannelMode::OrderedReliable(ReliableSettings::default()),
             );
     }
 }
@@ -1,5 +1,5 @@
 use bevy::prelude::Component;
-use naia_bevy_shared::{Property, ProtocolPlugin, Replicate};
+use naia_bevy_shared::{Property, ProtocolPlugin, Replicate, Serde};
 
 pub struct ComponentsPlugin;
 impl ProtocolPlugin for ComponentsPlugin {
@@ -7,7 +7,13 @@ impl ProtocolPlugin for ComponentsPlugin {
         protocol
             .add_component::<PhysicsStateSync>()
             .add_component::<CharacterEntity>()
-            .add_component::<WallEntity>();
+            .add_component::<WallEntity>()
+            .add_component::<MatchState>()
+            .add_component::<PlayerInfo>()
+            .add_component::<PlayerStats>()
+            .add_component::<CharacterAction>()
+            .add_component::<Appearance>()
+            .add_component::<WardrobeEntity>();
     }
 }
 
@@ -23,10 +29,101 @@ pub struct PhysicsStateSync {
     pub ang_rad: Property<f32>,
 }
 
+/// Static, axis-aligned level geometry. Its position comes from the [`PhysicsStateSync`] it is
+/// spawned with.
+#[derive(Component, Replicate)]
+pub struct WallEntity {
+    pub half_width_m: Property<f32>,
+    pub half_height_m: Property<f32>,
+}
+
+#[derive(Serde, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MatchPhase {
+    /// Waiting for enough players to join and ready up.
+    Lobby,
+    /// Everyone is ready and the match is about to start.
+    Countdown,
+    InProgress,
+    /// The match is over and the room will soon return to the lobby.
+    PostGame,
+}
+
+/// The state of the match in a room. There is exactly one of these in each room.
+#[derive(Component, Replicate)]
+pub struct MatchState {
+    pub phase: Property<MatchPhase>,
+    /// The time left in the current phase, for phases which have a time limit.
+    pub seconds_remaining: Property<u16>,
+}
+
+/// What everyone in a room may know about one of its users. This is deliberately not attached to
+/// the user's avatar, so it does not reveal who controls which character.
+#[derive(Component, Replicate)]
+pub struct PlayerInfo {
+    pub name: Property<String>,
+    pub ready: Property<bool>,
+}
+
+/// A user's tally for the current match, attached to the same entity as their [`PlayerInfo`].
+#[derive(Component, Replicate)]
+pub struct PlayerStats {
+    pub kills: Property<u16>,
+    /// Kills of anyone other than the user's target.
+    pub wrong_kills: Property<u16>,
+    pub deaths: Property<u16>,
+    /// How long the user's avatar has been alive this match, in whole seconds.
+    pub survival_secs: Property<u16>,
+    /// The score, as the server's scoring settings weigh the kills and wrong kills.
+    pub points: Property<i32>,
+}
+
+/// How long a character is shown [`Action::Attacking`] after an attack, in seconds.
+pub const ATTACK_SECS: f32 = 0.3;
+
+#[derive(Serde, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// Standing or walking, depending on the character's velocity.
+    Idle,
+    /// Striking at whoever is nearest.
+    Attacking,
+    /// Killed, and about to be removed.
+    Dead,
+}
+
+/// What a character is doing, for clients to animate. Players and NPCs have it alike.
+#[derive(Component, Replicate)]
+pub struct CharacterAction {
+    pub action: Property<Action>,
+}
+
+/// One of the looks a character can have: an outfit, and the color palette it is dyed in.
+#[derive(Serde, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Look {
+    pub outfit: u8,
+    pub palette: u8,
+}
+
+impl Look {
+    /// How many outfits there are to choose from.
+    pub const OUTFITS: u8 = 4;
+    /// How many color palettes there are to choose from.
+    pub const PALETTES: u8 = 8;
+}
+
+/// How a character looks. Players and NPCs draw their looks from the same pool, so a player can
+/// only be picked out of the crowd by what their hunter has seen of them. Players may change theirs
+/// at a [`WardrobeEntity`].
+#[derive(Component, Replicate)]
+pub struct Appearance {
+    pub look: Property<Look>,
+}
+
 // Tags
 
 #[derive(Component, Replicate)]
 pub struct CharacterEntity;
 
+/// A place where players can change their disguise. Its position comes from the
+/// [`PhysicsStateSync`] it is spawned with.
 #[derive(Component, Replicate)]
-pub struct WallEntity;
+pub struct WardrobeEntity;
@@ -1,27 +1,33 @@
 use std::time::Duration;
 
-use naia_bevy_shared::{LinkConditionerConfig, Protocol};
+use naia_bevy_shared::Protocol;
 
 pub mod channels;
 pub mod components;
+pub mod link_condition;
+pub mod maps;
 pub mod messages;
+pub mod navigation;
 pub mod physics;
+pub mod replay;
+pub mod units;
 
 use channels::ChannelsPlugin;
 use components::ComponentsPlugin;
 use messages::MessagesPlugin;
 
+/// How many times a second the server and clients tick.
+pub const TICKS_PER_SEC: u32 = 20;
+pub const TICK_INTERVAL: Duration = Duration::from_millis(1000 / TICKS_PER_SEC as u64);
+
 pub fn protocol() -> Protocol {
     let mut protocol = Protocol::builder();
 
     protocol
-        .tick_interval(Duration::from_millis(50))
+        .tick_interval(TICK_INTERVAL)
         .add_plugin(ChannelsPlugin)
         .add_plugin(MessagesPlugin)
         .add_plugin(ComponentsPlugin);
 
-    #[cfg(debug_assertions)]
-    protocol.link_condition(LinkConditionerConfig::average_condition());
-
     protocol.build()
 }
@@ -0,0 +1,112 @@
+//! Presets for naia's link conditioner, which simulates a poor connection on incoming packets.
+
+use std::{fmt, str::FromStr};
+
+use naia_bevy_shared::LinkConditionerConfig;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LinkCondition {
+    #[default]
+    Off,
+    Good,
+    Average,
+    Poor,
+    Custom {
+        latency_ms: u32,
+        jitter_ms: u32,
+        /// The fraction of packets dropped, from 0 to 1.
+        loss: f32,
+    },
+}
+
+impl LinkCondition {
+    /// The presets, for listing in a UI. A custom condition starts from the average one.
+    pub const ALL: [LinkCondition; 5] = [
+        LinkCondition::Off,
+        LinkCondition::Good,
+        LinkCondition::Average,
+        LinkCondition::Poor,
+        LinkCondition::Custom {
+            latency_ms: 100,
+            jitter_ms: 25,
+            loss: 0.02,
+        },
+    ];
+
+    pub fn config(self) -> Option<LinkConditionerConfig> {
+        match self {
+            LinkCondition::Off => None,
+            LinkCondition::Good => Some(LinkConditionerConfig::good_condition()),
+            LinkCondition::Average => Some(LinkConditionerConfig::average_condition()),
+            LinkCondition::Poor => Some(LinkConditionerConfig::poor_condition()),
+            LinkCondition::Custom {
+                latency_ms,
+                jitter_ms,
+                loss,
+            } => Some(LinkConditionerConfig::new(latency_ms, jitter_ms, loss)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LinkCondition::Off => "off",
+            LinkCondition::Good => "good",
+            LinkCondition::Average => "average",
+            LinkCondition::Poor => "poor",
+            LinkCondition::Custom { .. } => "custom",
+        }
+    }
+}
+
+impl fmt::Display for LinkCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkCondition::Custom {
+                latency_ms,
+                jitter_ms,
+                loss,
+            } => write!(f, "{latency_ms}:{jitter_ms}:{loss}"),
+            _ => f.write_str(self.name()),
+        }
+    }
+}
+
+impl FromStr for LinkCondition {
+    type Err = String;
+
+    /// Parses a preset name, or `<latency_ms>:<jitter_ms>:<loss>` for a custom condition.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => return Ok(LinkCondition::Off),
+            "good" => return Ok(LinkCondition::Good),
+            "average" => return Ok(LinkCondition::Average),
+            "poor" => return Ok(LinkCondition::Poor),
+            _ => {}
+        }
+
+        let parts = s.split(':').collect::<Vec<_>>();
+        let [latency_ms, jitter_ms, loss] = parts[..] else {
+            return Err(format!(
+                "unknown link condition `{s}`, expected `off`, `good`, `average`, `poor` or \
+                 `<latency_ms>:<jitter_ms>:<loss>`"
+            ));
+        };
+
+        let loss = loss
+            .parse::<f32>()
+            .map_err(|_| format!("invalid packet loss `{loss}`"))?;
+        if !(0.0..=1.0).contains(&loss) {
+            return Err(format!("packet loss must be between 0 and 1, got {loss}"));
+        }
+
+        Ok(LinkCondition::Custom {
+            latency_ms: latency_ms
+                .parse()
+                .map_err(|_| format!("invalid latency `{latency_ms}`"))?,
+            jitter_ms: jitter_ms
+                .parse()
+                .map_err(|_| format!("invalid jitter `{jitter_ms}`"))?,
+            loss,
+        })
+    }
+}
@@ -0,0 +1,119 @@
+//! Built-in map layouts. Maps are known to both the server and the client so that only the map's
+//! name has to be agreed on; the walls and wardrobes themselves are still replicated as
+//! [`WallEntity`]s and [`WardrobeEntity`]s.
+//!
+//! [`WallEntity`]: crate::components::WallEntity
+//! [`WardrobeEntity`]: crate::components::WardrobeEntity
+
+/// How close a character's center must be to a wardrobe's to change disguise there, in meters.
+pub const WARDROBE_REACH_M: f32 = 1.5;
+
+/// An axis-aligned wall, in meters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WallSpec {
+    pub x: f32,
+    pub y: f32,
+    pub half_width: f32,
+    pub half_height: f32,
+}
+
+impl WallSpec {
+    pub const fn new(x: f32, y: f32, half_width: f32, half_height: f32) -> Self {
+        Self {
+            x,
+            y,
+            half_width,
+            half_height,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MapLayout {
+    pub name: &'static str,
+
+    /// Half of the playable area's width and height, in meters, centered on the origin.
+    pub half_width: f32,
+    pub half_height: f32,
+
+    pub walls: Vec<WallSpec>,
+    pub spawn_points: Vec<(f32, f32)>,
+    /// Where players can change their disguise, in meters.
+    pub wardrobes: Vec<(f32, f32)>,
+}
+
+impl MapLayout {
+    /// Looks up a built-in map by name.
+    pub fn by_name(name: &str) -> Option<Self> {
+        match name {
+            "open" => Some(Self::open()),
+            "arena" => Some(Self::arena()),
+            _ => None,
+        }
+    }
+
+    pub fn names() -> &'static [&'static str] {
+        &["open", "arena"]
+    }
+
+    /// An empty square enclosed by four walls.
+    pub fn open() -> Self {
+        Self {
+            name: "open",
+            half_width: 10.0,
+            half_height: 10.0,
+            walls: Self::boundary(10.0, 10.0),
+            spawn_points: vec![(-5.0, -5.0), (5.0, -5.0), (5.0, 5.0), (-5.0, 5.0)],
+            wardrobes: vec![(-7.0, 0.0), (7.0, 0.0)],
+        }
+    }
+
+    /// A larger enclosure with a few pillars to break line of sight.
+    pub fn arena() -> Self {
+        let mut walls = Self::boundary(16.0, 12.0);
+        walls.extend([
+            WallSpec::new(-8.0, -5.0, 1.0, 1.0),
+            WallSpec::new(8.0, -5.0, 1.0, 1.0),
+            WallSpec::new(-8.0, 5.0, 1.0, 1.0),
+            WallSpec::new(8.0, 5.0, 1.0, 1.0),
+            WallSpec::new(0.0, 0.0, 3.0, 0.5),
+        ]);
+
+        Self {
+            name: "arena",
+            half_width: 16.0,
+            half_height: 12.0,
+            walls,
+            spawn_points: vec![
+                (-12.0, -8.0),
+                (12.0, -8.0),
+                (12.0, 8.0),
+                (-12.0, 8.0),
+                (0.0, -8.0),
+                (0.0, 8.0),
+            ],
+            wardrobes: vec![(-12.0, 0.0), (12.0, 0.0), (0.0, -3.0), (0.0, 3.0)],
+        }
+    }
+
+    fn boundary(half_width: f32, half_height: f32) -> Vec<WallSpec> {
+        const THICKNESS: f32 = 0.5;
+
+        vec![
+            WallSpec::new(
+                0.0,
+                half_height + THICKNESS,
+                half_width + THICKNESS * 2.0,
+                THICKNESS,
+            ),
+            WallSpec::new(
+                0.0,
+                -half_height - THICKNESS,
+                half_width + THICKNESS * 2.0,
+                THICKNESS,
+            ),
+            WallSpec::new(half_width + THICKNESS, 0.0, THICKNESS, half_height),
+            WallSpec::new(-half_width - THICKNESS, 0.0, THICKNESS, half_height),
+        ]
+    }
+}
@@ -1,4 +1,6 @@
-use naia_bevy_shared::{EntityProperty, Message, ProtocolPlugin};
+use naia_bevy_shared::{EntityProperty, Message, ProtocolPlugin, Serde};
+
+use crate::components::Look;
 
 pub struct MessagesPlugin;
 impl ProtocolPlugin for MessagesPlugin {
@@ -7,7 +9,14 @@ impl ProtocolPlugin for MessagesPlugin {
             .add_message::<Auth>()
             .add_message::<PlayerInput>()
             .add_message::<EntityAssignment>()
-            .add_message::<NewTarget>();
+            .add_message::<ReadyUp>()
+            .add_message::<MatchSummary>()
+            .add_message::<GameplaySettings>()
+            .add_message::<Announcement>()
+            .add_message::<Spectate>()
+            .add_message::<SpectatedPlayer>()
+            .add_message::<ChangeDisguise>()
+            .add_message::<TargetHint>();
     }
 }
 
@@ -15,6 +24,11 @@ impl ProtocolPlugin for MessagesPlugin {
 pub struct Auth {
     pub name: String,
     pub channel_password: String,
+    /// The name of the room to join. When `None`, the server picks a room with free slots.
+    pub room: Option<String>,
+    /// Joins to watch rather than play. Spectators take no part in matches and do not count
+    /// towards the room's player cap.
+    pub spectate: bool,
 }
 
 #[derive(Message)]
@@ -23,6 +37,8 @@ pub struct PlayerInput {
 
     pub x_axis: f32,
     pub y_axis: f32,
+    /// Whether to strike the nearest character this tick.
+    pub attack: bool,
 }
 
 impl PlayerInput {
@@ -42,6 +58,7 @@ impl PlayerInput {
             entity: EntityProperty::new(),
             x_axis,
             y_axis,
+            attack: false,
         }
     }
 
@@ -50,8 +67,14 @@ impl PlayerInput {
             entity: EntityProperty::new(),
             x_axis: x,
             y_axis: y,
+            attack: false,
         }
     }
+
+    pub fn with_attack(mut self, attack: bool) -> Self {
+        self.attack = attack;
+        self
+    }
 }
 
 /// How the player knows which character they control.
@@ -70,15 +93,77 @@ impl EntityAssignment {
     }
 }
 
+/// What a hunter knows of their target's look. Sent with every new target, and again whenever the
+/// hunter sees that their target has changed disguise. Hunters are never told which character is
+/// their target, only how they look.
 #[derive(Message)]
-pub struct NewTarget {
-    pub entity: EntityProperty,
+pub struct TargetHint {
+    /// The target's look when the hunter last saw them, or `None` without a target.
+    pub look: Option<Look>,
+}
+
+/// Sent by a player standing at a wardrobe to change into a new disguise.
+#[derive(Message)]
+pub struct ChangeDisguise;
+
+/// Sent by a player in the lobby to mark themselves as (un)ready.
+#[derive(Message)]
+pub struct ReadyUp {
+    pub ready: bool,
 }
 
-impl NewTarget {
-    pub fn new() -> Self {
+#[derive(Serde, Clone, Debug, PartialEq)]
+pub struct SummaryEntry {
+    pub name: String,
+    pub kills: u16,
+    pub wrong_kills: u16,
+    pub deaths: u16,
+    pub score: i32,
+}
+
+/// Broadcast to a room when its match ends.
+#[derive(Message)]
+pub struct MatchSummary {
+    /// The name of the winner, or `None` for a draw.
+    pub winner: Option<String>,
+    pub entries: Vec<SummaryEntry>,
+}
+
+/// Sent on connection and whenever the server's config changes values which clients need to
+/// predict their own movement.
+#[derive(Message, PartialEq)]
+pub struct GameplaySettings {
+    /// Players' top speed, in meters per second.
+    pub player_speed: f32,
+}
+
+/// A message from the server's operator, shown to every player.
+#[derive(Message)]
+pub struct Announcement {
+    pub text: String,
+}
+
+/// Sent by an eliminated player to watch the rest of the match as a spectator.
+#[derive(Message)]
+pub struct Spectate;
+
+/// Sent to spectators whenever a player's character or target changes, so that they can follow
+/// anyone and see who is hunting whom.
+#[derive(Message)]
+pub struct SpectatedPlayer {
+    pub name: String,
+    /// The player's character. Unset while they have none, e.g. once eliminated.
+    pub avatar: EntityProperty,
+    /// The character the player is hunting. Unset while they have no target.
+    pub target: EntityProperty,
+}
+
+impl SpectatedPlayer {
+    pub fn new(name: String) -> Self {
         Self {
-            entity: EntityProperty::new(),
+            name,
+            avatar: EntityProperty::new(),
+            target: EntityProperty::new(),
         }
     }
 }
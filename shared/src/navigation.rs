@@ -0,0 +1,399 @@
+//! Grid-based navigation around static level geometry.
+//!
+//! A [`NavGrid`] covers a map's bounds with square cells and marks every cell which an agent of the
+//! given radius cannot stand in. Walls can be added and removed after the fact, which only touches
+//! the cells under the wall. Paths are found with A* over the eight neighbouring cells, then
+//! smoothed by skipping waypoints which are in direct line of sight of each other.
+
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use bevy::prelude::Vec2;
+
+use crate::maps::WallSpec;
+
+const SQRT_2: f32 = std::f32::consts::SQRT_2;
+
+#[derive(Clone, Debug)]
+pub struct NavGrid {
+    origin: Vec2,
+    cell_size: f32,
+    width: usize,
+    height: usize,
+    agent_radius: f32,
+
+    /// How many walls cover each cell. A cell is walkable when no wall covers it.
+    blockers: Vec<u16>,
+}
+
+impl NavGrid {
+    /// Creates an empty grid covering `half_width` by `half_height` meters around the origin.
+    pub fn new(half_width: f32, half_height: f32, cell_size: f32, agent_radius: f32) -> Self {
+        let width = ((half_width * 2.0) / cell_size).ceil().max(1.0) as usize;
+        let height = ((half_height * 2.0) / cell_size).ceil().max(1.0) as usize;
+
+        Self {
+            origin: Vec2::new(-half_width, -half_height),
+            cell_size,
+            width,
+            height,
+            agent_radius,
+            blockers: vec![0; width * height],
+        }
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    /// Marks the cells under the wall, grown by the agent radius, as blocked.
+    pub fn add_wall(&mut self, wall: &WallSpec) {
+        for index in self.cells_under(wall) {
+            self.blockers[index] = self.blockers[index].saturating_add(1);
+        }
+    }
+
+    /// Undoes [`NavGrid::add_wall`] for a wall which has been moved or removed.
+    pub fn remove_wall(&mut self, wall: &WallSpec) {
+        for index in self.cells_under(wall) {
+            self.blockers[index] = self.blockers[index].saturating_sub(1);
+        }
+    }
+
+    pub fn is_walkable(&self, point: Vec2) -> bool {
+        self.cell_of(point)
+            .map_or(false, |(x, y)| self.blockers[self.index(x, y)] == 0)
+    }
+
+    /// Finds a smoothed path from `start` to `goal`. The returned points exclude `start` and end at
+    /// `goal`. Returns `None` if the goal cannot be reached.
+    ///
+    /// A start inside a blocked cell, which happens when an agent is pushed against a wall, is
+    /// searched from as if it were walkable.
+    pub fn find_path(&self, start: Vec2, goal: Vec2) -> Option<Vec<Vec2>> {
+        let start_cell = self.cell_of(start)?;
+        let goal_cell = self.cell_of(goal)?;
+        if !self.walkable_cell(goal_cell.0 as isize, goal_cell.1 as isize) {
+            return None;
+        }
+
+        let start_index = self.index(start_cell.0, start_cell.1);
+        let goal_index = self.index(goal_cell.0, goal_cell.1);
+
+        let mut cost = vec![f32::INFINITY; self.blockers.len()];
+        let mut came_from = vec![usize::MAX; self.blockers.len()];
+        let mut open = BinaryHeap::new();
+
+        cost[start_index] = 0.0;
+        open.push(OpenNode {
+            estimate: self.heuristic(start_cell, goal_cell),
+            index: start_index,
+        });
+
+        while let Some(OpenNode { index, .. }) = open.pop() {
+            if index == goal_index {
+                return Some(self.smooth(start, goal, self.reconstruct(&came_from, index)));
+            }
+
+            let (x, y) = (index % self.width, index / self.width);
+
+            for (dx, dy) in NEIGHBOURS {
+                let (nx, ny) = (x as isize + dx, y as isize + dy);
+                if !self.walkable_cell(nx, ny) {
+                    continue;
+                }
+
+                // Do not cut corners diagonally past a blocked cell
+                let diagonal = dx != 0 && dy != 0;
+                if diagonal
+                    && (!self.walkable_cell(x as isize + dx, y as isize)
+                        || !self.walkable_cell(x as isize, y as isize + dy))
+                {
+                    continue;
+                }
+
+                let neighbour = self.index(nx as usize, ny as usize);
+                let step = if diagonal { SQRT_2 } else { 1.0 };
+                let new_cost = cost[index] + step;
+
+                if new_cost < cost[neighbour] {
+                    cost[neighbour] = new_cost;
+                    came_from[neighbour] = index;
+                    open.push(OpenNode {
+                        estimate: new_cost + self.heuristic((nx as usize, ny as usize), goal_cell),
+                        index: neighbour,
+                    });
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Whether an agent could walk in a straight line between two points.
+    pub fn line_of_sight(&self, from: Vec2, to: Vec2) -> bool {
+        let distance = from.distance(to);
+        let steps = (distance / (self.cell_size * 0.25)).ceil().max(1.0) as usize;
+
+        (0..=steps).all(|step| self.is_walkable(from.lerp(to, step as f32 / steps as f32)))
+    }
+
+    fn cells_under(&self, wall: &WallSpec) -> Vec<usize> {
+        let min = Vec2::new(
+            wall.x - wall.half_width - self.agent_radius,
+            wall.y - wall.half_height - self.agent_radius,
+        );
+        let max = Vec2::new(
+            wall.x + wall.half_width + self.agent_radius,
+            wall.y + wall.half_height + self.agent_radius,
+        );
+
+        let to_cell = |v: f32, origin: f32, len: usize| {
+            (((v - origin) / self.cell_size).floor().max(0.0) as usize).min(len - 1)
+        };
+        let (x0, x1) = (
+            to_cell(min.x, self.origin.x, self.width),
+            to_cell(max.x, self.origin.x, self.width),
+        );
+        let (y0, y1) = (
+            to_cell(min.y, self.origin.y, self.height),
+            to_cell(max.y, self.origin.y, self.height),
+        );
+
+        // Only cells whose centers fall within the grown wall are blocked, so that the agent
+        // radius is not rounded up to a whole cell
+        (y0..=y1)
+            .flat_map(|y| (x0..=x1).map(move |x| (x, y)))
+            .filter(|(x, y)| {
+                let center = self.center_of(*x, *y);
+                center.x >= min.x && center.x <= max.x && center.y >= min.y && center.y <= max.y
+            })
+            .map(|(x, y)| self.index(x, y))
+            .collect()
+    }
+
+    fn cell_of(&self, point: Vec2) -> Option<(usize, usize)> {
+        let local = (point - self.origin) / self.cell_size;
+        if local.x < 0.0 || local.y < 0.0 {
+            return None;
+        }
+
+        let (x, y) = (local.x as usize, local.y as usize);
+        (x < self.width && y < self.height).then_some((x, y))
+    }
+
+    fn center_of(&self, x: usize, y: usize) -> Vec2 {
+        self.origin + (Vec2::new(x as f32, y as f32) + Vec2::splat(0.5)) * self.cell_size
+    }
+
+    fn index(&self, x: usize, y: usize) -> usize {
+        y * self.width + x
+    }
+
+    fn walkable_cell(&self, x: isize, y: isize) -> bool {
+        x >= 0
+            && y >= 0
+            && (x as usize) < self.width
+            && (y as usize) < self.height
+            && self.blockers[self.index(x as usize, y as usize)] == 0
+    }
+
+    /// The octile distance between two cells.
+    fn heuristic(&self, (ax, ay): (usize, usize), (bx, by): (usize, usize)) -> f32 {
+        let dx = (ax as f32 - bx as f32).abs();
+        let dy = (ay as f32 - by as f32).abs();
+        dx.max(dy) + (SQRT_2 - 1.0) * dx.min(dy)
+    }
+
+    fn reconstruct(&self, came_from: &[usize], mut index: usize) -> Vec<Vec2> {
+        let mut cells = vec![index];
+        while came_from[index] != usize::MAX {
+            index = came_from[index];
+            cells.push(index);
+        }
+        cells.reverse();
+
+        cells
+            .into_iter()
+            .map(|index| self.center_of(index % self.width, index / self.width))
+            .collect()
+    }
+
+    /// Replaces the cell centers of a path with as few straight segments as line of sight allows.
+    fn smooth(&self, start: Vec2, goal: Vec2, mut cells: Vec<Vec2>) -> Vec<Vec2> {
+        // The first and last cells are replaced by the exact start and goal
+        cells.pop();
+        cells.push(goal);
+        if !cells.is_empty() {
+            cells.remove(0);
+        }
+        if cells.is_empty() {
+            cells.push(goal);
+        }
+
+        let mut path = Vec::new();
+        let mut from = start;
+        let mut next = 0;
+
+        while next < cells.len() {
+            let mut furthest = next;
+            for candidate in (next + 1..cells.len()).rev() {
+                if self.line_of_sight(from, cells[candidate]) {
+                    furthest = candidate;
+                    break;
+                }
+            }
+
+            from = cells[furthest];
+            path.push(from);
+            next = furthest + 1;
+        }
+
+        path
+    }
+}
+
+const NEIGHBOURS: [(isize, isize); 8] = [
+    (1, 0),
+    (-1, 0),
+    (0, 1),
+    (0, -1),
+    (1, 1),
+    (1, -1),
+    (-1, 1),
+    (-1, -1),
+];
+
+/// An entry of the A* open set, ordered so that the lowest estimate is popped first.
+struct OpenNode {
+    estimate: f32,
+    index: usize,
+}
+
+impl PartialEq for OpenNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for OpenNode {}
+
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OpenNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .estimate
+            .total_cmp(&self.estimate)
+            .then_with(|| other.index.cmp(&self.index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 10 by 10 meter grid with a wall down the middle, leaving gaps at the top and bottom.
+    fn divided() -> (NavGrid, WallSpec) {
+        let wall = WallSpec::new(0.0, 0.0, 0.5, 3.0);
+        let mut grid = NavGrid::new(5.0, 5.0, 0.25, 0.25);
+        grid.add_wall(&wall);
+        (grid, wall)
+    }
+
+    #[test]
+    fn open_paths_are_straight() {
+        let grid = NavGrid::new(5.0, 5.0, 0.25, 0.25);
+        let goal = Vec2::new(4.0, 2.0);
+
+        assert_eq!(
+            grid.find_path(Vec2::new(-4.0, -3.0), goal),
+            Some(vec![goal])
+        );
+    }
+
+    #[test]
+    fn paths_go_around_walls() {
+        let (grid, _) = divided();
+        let (start, goal) = (Vec2::new(-3.0, 0.0), Vec2::new(3.0, 0.0));
+        assert!(!grid.line_of_sight(start, goal));
+
+        let path = grid.find_path(start, goal).unwrap();
+        assert!(path.len() > 1);
+        assert_eq!(path.last(), Some(&goal));
+
+        // Smoothing only skips points which are in line of sight of each other
+        let mut from = start;
+        for point in path {
+            assert!(grid.line_of_sight(from, point), "{from} to {point}");
+            from = point;
+        }
+    }
+
+    #[test]
+    fn unreachable_goals_have_no_path() {
+        let (grid, _) = divided();
+        assert_eq!(grid.find_path(Vec2::new(-3.0, 0.0), Vec2::ZERO), None);
+        assert_eq!(
+            grid.find_path(Vec2::new(-3.0, 0.0), Vec2::new(6.0, 0.0)),
+            None
+        );
+
+        let mut boxed = NavGrid::new(5.0, 5.0, 0.25, 0.25);
+        for wall in [
+            WallSpec::new(3.0, 1.5, 1.5, 0.25),
+            WallSpec::new(3.0, -1.5, 1.5, 0.25),
+            WallSpec::new(1.5, 0.0, 0.25, 1.5),
+            WallSpec::new(4.5, 0.0, 0.25, 1.5),
+        ] {
+            boxed.add_wall(&wall);
+        }
+        assert_eq!(
+            boxed.find_path(Vec2::new(-3.0, 0.0), Vec2::new(3.0, 0.0)),
+            None
+        );
+    }
+
+    #[test]
+    fn corners_are_not_cut() {
+        // Two cells blocked diagonally from each other leave only a diagonal step between the
+        // other two
+        let mut grid = NavGrid::new(1.0, 1.0, 1.0, 0.0);
+        grid.add_wall(&WallSpec::new(-0.5, 0.5, 0.25, 0.25));
+        grid.add_wall(&WallSpec::new(0.5, -0.5, 0.25, 0.25));
+
+        let (start, goal) = (Vec2::new(-0.5, -0.5), Vec2::new(0.5, 0.5));
+        assert!(grid.is_walkable(start) && grid.is_walkable(goal));
+        assert_eq!(grid.find_path(start, goal), None);
+    }
+
+    #[test]
+    fn blocked_starts_are_searched_from() {
+        let (grid, _) = divided();
+        // Just inside the wall's grown edge, as if pushed against it
+        let start = Vec2::new(-0.7, 0.0);
+        let goal = Vec2::new(-3.0, 0.0);
+        assert!(!grid.is_walkable(start));
+
+        let path = grid.find_path(start, goal).unwrap();
+        assert_eq!(path.last(), Some(&goal));
+    }
+
+    #[test]
+    fn removing_walls_restores_walkability() {
+        let (mut grid, wall) = divided();
+        let (start, goal) = (Vec2::new(-3.0, 0.0), Vec2::new(3.0, 0.0));
+
+        // Overlapping walls keep their shared cells blocked until both are gone
+        grid.add_wall(&wall);
+        grid.remove_wall(&wall);
+        assert!(!grid.is_walkable(Vec2::ZERO));
+
+        grid.remove_wall(&wall);
+        assert!(grid.is_walkable(Vec2::ZERO));
+        assert_eq!(grid.find_path(start, goal), Some(vec![goal]));
+    }
+}
@@ -88,9 +88,29 @@ impl PhysicsWorld {
         (rb, col)
     }
 
+    /// Removes a rigid body along with its attached colliders.
+    pub fn remove(&mut self, handle: RigidBodyHandle) {
+        self.rigid_body_set.remove(
+            handle,
+            &mut self.island_manager,
+            &mut self.collider_set,
+            &mut self.impulse_joint_set,
+            &mut self.multibody_joint_set,
+            true,
+        );
+    }
+
+    pub fn get_rigid_body(&self, handle: RigidBodyHandle) -> Option<&RigidBody> {
+        self.rigid_body_set.get(handle)
+    }
+
     pub fn get_rigid_body_mut(&mut self, handle: RigidBodyHandle) -> Option<&mut RigidBody> {
         self.rigid_body_set.get_mut(handle)
     }
+
+    pub fn get_collider_mut(&mut self, handle: ColliderHandle) -> Option<&mut Collider> {
+        self.collider_set.get_mut(handle)
+    }
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -0,0 +1,419 @@
+//! Recordings of matches, written by the server and read by the replay tools.
+//!
+//! A replay file is a [`Header`] followed by one [`Frame`] per recorded server tick, each holding
+//! what changed in that tick. Bodies and users are given small ids in the order they first appear,
+//! as entities and user keys mean nothing outside of the server which recorded them. States are
+//! only written when they change, so a body keeps its last recorded state until the next one.
+//!
+//! Everything is little-endian, and ids and counts are LEB128 varints. A server which stops while
+//! recording leaves a truncated file, which reads fine up to the last whole frame. Files from older
+//! versions of the format are still read, as each version only added events.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Write},
+    path::Path,
+};
+
+use naia_bevy_shared::Tick;
+
+use crate::components::Look;
+
+pub mod verify;
+
+const MAGIC: [u8; 4] = *b"ASRP";
+/// Version 2 added [`Event::Appearance`].
+const VERSION: u16 = 2;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Header {
+    pub room: String,
+    pub map: String,
+    /// When the match started, in seconds since the Unix epoch.
+    pub started: u64,
+    /// The speed player inputs were scaled by when the match started.
+    pub player_speed: f32,
+}
+
+/// The replicated physics state of a body, in meters and meters per second.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BodyState {
+    pub x: f32,
+    pub y: f32,
+    pub linvel_x: f32,
+    pub linvel_y: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shape {
+    /// A player's avatar or an NPC, which cannot be told apart.
+    Character,
+    Wall {
+        half_width: f32,
+        half_height: f32,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// A user took part in the match.
+    Player {
+        user: u32,
+        name: String,
+    },
+    Spawn {
+        body: u32,
+        shape: Shape,
+        state: BodyState,
+    },
+    Despawn {
+        body: u32,
+    },
+    State {
+        body: u32,
+        state: BodyState,
+    },
+    /// An input the server received from a user and applied to their avatar.
+    Input {
+        user: u32,
+        body: u32,
+        x_axis: f32,
+        y_axis: f32,
+        attack: bool,
+    },
+    /// A hunter was given a new target, or none.
+    Target {
+        hunter: u32,
+        target: Option<u32>,
+    },
+    /// A user's attack killed a body.
+    Kill {
+        attacker: u32,
+        victim: u32,
+    },
+    /// The player speed was changed while the match ran.
+    PlayerSpeed(f32),
+    /// A character was given a look, on spawning or by changing disguise.
+    Appearance {
+        body: u32,
+        look: Look,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub tick: Tick,
+    pub events: Vec<Event>,
+}
+
+/// A whole replay, read into memory.
+pub struct Replay {
+    pub header: Header,
+    pub frames: Vec<Frame>,
+    /// Whether the file ended partway through a frame.
+    pub truncated: bool,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut reader = ReplayReader::new(BufReader::new(File::open(path)?))?;
+
+        let mut frames = Vec::new();
+        let truncated = loop {
+            match reader.next_frame() {
+                Ok(Some(frame)) => frames.push(frame),
+                Ok(None) => break false,
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break true,
+                Err(err) => return Err(err),
+            }
+        };
+
+        Ok(Self {
+            header: reader.header,
+            frames,
+            truncated,
+        })
+    }
+}
+
+pub struct ReplayWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> ReplayWriter<W> {
+    pub fn new(mut out: W, header: &Header) -> io::Result<Self> {
+        out.write_all(&MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+        write_str(&mut out, &header.room)?;
+        write_str(&mut out, &header.map)?;
+        out.write_all(&header.started.to_le_bytes())?;
+        write_f32(&mut out, header.player_speed)?;
+
+        Ok(Self { out })
+    }
+
+    pub fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        let out = &mut self.out;
+        out.write_all(&frame.tick.to_le_bytes())?;
+        write_varint(out, frame.events.len() as u32)?;
+        for event in frame.events.iter() {
+            write_event(out, event)?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+pub struct ReplayReader<R: Read> {
+    input: R,
+    header: Header,
+}
+
+impl<R: Read> ReplayReader<R> {
+    pub fn new(mut input: R) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        input.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(invalid("not a replay file"));
+        }
+        let version = u16::from_le_bytes(read_array(&mut input)?);
+        if !(1..=VERSION).contains(&version) {
+            return Err(invalid(&format!("unsupported replay version {version}")));
+        }
+
+        let header = Header {
+            room: read_str(&mut input)?,
+            map: read_str(&mut input)?,
+            started: u64::from_le_bytes(read_array(&mut input)?),
+            player_speed: read_f32(&mut input)?,
+        };
+
+        Ok(Self { input, header })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Reads the next frame, or `None` at the end of the file.
+    pub fn next_frame(&mut self) -> io::Result<Option<Frame>> {
+        let mut tick = [0; 2];
+        match self.input.read(&mut tick[..1])? {
+            0 => return Ok(None),
+            _ => self.input.read_exact(&mut tick[1..])?,
+        }
+
+        let count = read_varint(&mut self.input)?;
+        let events = (0..count)
+            .map(|_| read_event(&mut self.input))
+            .collect::<io::Result<_>>()?;
+
+        Ok(Some(Frame {
+            tick: Tick::from_le_bytes(tick),
+            events,
+        }))
+    }
+}
+
+fn write_event(out: &mut impl Write, event: &Event) -> io::Result<()> {
+    match event {
+        Event::Player { user, name } => {
+            out.write_all(&[0])?;
+            write_varint(out, *user)?;
+            write_str(out, name)
+        }
+        Event::Spawn { body, shape, state } => {
+            out.write_all(&[1])?;
+            write_varint(out, *body)?;
+            match shape {
+                Shape::Character => out.write_all(&[0])?,
+                Shape::Wall {
+                    half_width,
+                    half_height,
+                } => {
+                    out.write_all(&[1])?;
+                    write_f32(out, *half_width)?;
+                    write_f32(out, *half_height)?;
+                }
+            }
+            write_state(out, state)
+        }
+        Event::Despawn { body } => {
+            out.write_all(&[2])?;
+            write_varint(out, *body)
+        }
+        Event::State { body, state } => {
+            out.write_all(&[3])?;
+            write_varint(out, *body)?;
+            write_state(out, state)
+        }
+        Event::Input {
+            user,
+            body,
+            x_axis,
+            y_axis,
+            attack,
+        } => {
+            out.write_all(&[4])?;
+            write_varint(out, *user)?;
+            write_varint(out, *body)?;
+            write_f32(out, *x_axis)?;
+            write_f32(out, *y_axis)?;
+            out.write_all(&[*attack as u8])
+        }
+        Event::Target { hunter, target } => {
+            out.write_all(&[5])?;
+            write_varint(out, *hunter)?;
+            // Ids are shifted by one so that 0 can mean no target
+            write_varint(out, target.map_or(0, |target| target + 1))
+        }
+        Event::Kill { attacker, victim } => {
+            out.write_all(&[6])?;
+            write_varint(out, *attacker)?;
+            write_varint(out, *victim)
+        }
+        Event::PlayerSpeed(speed) => {
+            out.write_all(&[7])?;
+            write_f32(out, *speed)
+        }
+        Event::Appearance { body, look } => {
+            out.write_all(&[8])?;
+            write_varint(out, *body)?;
+            out.write_all(&[look.outfit, look.palette])
+        }
+    }
+}
+
+fn read_event(input: &mut impl Read) -> io::Result<Event> {
+    let [tag] = read_array(input)?;
+    let event = match tag {
+        0 => Event::Player {
+            user: read_varint(input)?,
+            name: read_str(input)?,
+        },
+        1 => {
+            let body = read_varint(input)?;
+            let shape = match read_array(input)? {
+                [0] => Shape::Character,
+                [1] => Shape::Wall {
+                    half_width: read_f32(input)?,
+                    half_height: read_f32(input)?,
+                },
+                [other] => return Err(invalid(&format!("unknown shape {other}"))),
+            };
+            Event::Spawn {
+                body,
+                shape,
+                state: read_state(input)?,
+            }
+        }
+        2 => Event::Despawn {
+            body: read_varint(input)?,
+        },
+        3 => Event::State {
+            body: read_varint(input)?,
+            state: read_state(input)?,
+        },
+        4 => Event::Input {
+            user: read_varint(input)?,
+            body: read_varint(input)?,
+            x_axis: read_f32(input)?,
+            y_axis: read_f32(input)?,
+            attack: read_array::<1>(input)? != [0],
+        },
+        5 => Event::Target {
+            hunter: read_varint(input)?,
+            target: read_varint(input)?.checked_sub(1),
+        },
+        6 => Event::Kill {
+            attacker: read_varint(input)?,
+            victim: read_varint(input)?,
+        },
+        7 => Event::PlayerSpeed(read_f32(input)?),
+        8 => {
+            let body = read_varint(input)?;
+            let [outfit, palette] = read_array(input)?;
+            Event::Appearance {
+                body,
+                look: Look { outfit, palette },
+            }
+        }
+        other => return Err(invalid(&format!("unknown event {other}"))),
+    };
+    Ok(event)
+}
+
+fn write_state(out: &mut impl Write, state: &BodyState) -> io::Result<()> {
+    for value in [state.x, state.y, state.linvel_x, state.linvel_y] {
+        write_f32(out, value)?;
+    }
+    Ok(())
+}
+
+fn read_state(input: &mut impl Read) -> io::Result<BodyState> {
+    Ok(BodyState {
+        x: read_f32(input)?,
+        y: read_f32(input)?,
+        linvel_x: read_f32(input)?,
+        linvel_y: read_f32(input)?,
+    })
+}
+
+fn write_f32(out: &mut impl Write, value: f32) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+fn read_f32(input: &mut impl Read) -> io::Result<f32> {
+    Ok(f32::from_le_bytes(read_array(input)?))
+}
+
+fn write_varint(out: &mut impl Write, mut value: u32) -> io::Result<()> {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            return out.write_all(&[byte]);
+        }
+        out.write_all(&[byte | 0x80])?;
+    }
+}
+
+fn read_varint(input: &mut impl Read) -> io::Result<u32> {
+    let mut value = 0u32;
+    for shift in (0..35).step_by(7) {
+        let [byte] = read_array(input)?;
+        value |= ((byte & 0x7f) as u32) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid("varint is too long"))
+}
+
+fn write_str(out: &mut impl Write, s: &str) -> io::Result<()> {
+    write_varint(out, s.len() as u32)?;
+    out.write_all(s.as_bytes())
+}
+
+fn read_str(input: &mut impl Read) -> io::Result<String> {
+    let len = read_varint(input)?;
+    let mut bytes = Vec::new();
+    input.take(len.into()).read_to_end(&mut bytes)?;
+    if bytes.len() != len as usize {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    String::from_utf8(bytes).map_err(|_| invalid("string is not UTF-8"))
+}
+
+fn read_array<const N: usize>(input: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_owned())
+}
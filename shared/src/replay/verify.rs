@@ -0,0 +1,215 @@
+//! Checks that a replay's inputs reproduce its states.
+//!
+//! Every frame, the recorded states are loaded into a [`PhysicsWorld`], the recorded inputs are
+//! applied on top of them and the world is stepped to the next frame, where its prediction is
+//! compared with what the server recorded. The server integrates with a variable step while this
+//! takes one fixed step per tick, as clients do, so collisions drift a little even when nothing is
+//! wrong, which is what the tolerance is for.
+
+use std::fmt;
+
+use bevy::utils::HashMap;
+use naia_bevy_shared::Tick;
+use rapier2d::prelude::*;
+
+use crate::{physics::PhysicsWorld, units::CHARACTER_HALF_SIZE};
+
+use super::{BodyState, Event, Replay, Shape};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MismatchKind {
+    /// A body ended up somewhere other than the inputs and the previous states lead to.
+    Position {
+        predicted: [f32; 2],
+        recorded: [f32; 2],
+    },
+    /// An avatar moved at a different velocity than its user's input asked for.
+    Velocity { input: [f32; 2], recorded: [f32; 2] },
+    /// An event refers to a body which does not exist.
+    UnknownBody,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mismatch {
+    pub tick: Tick,
+    pub body: u32,
+    pub kind: MismatchKind,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Mismatch { tick, body, kind } = self;
+        write!(f, "tick {tick}, body {body}: ")?;
+        match kind {
+            MismatchKind::Position {
+                predicted,
+                recorded,
+            } => write!(f, "predicted position {predicted:?}, recorded {recorded:?}"),
+            MismatchKind::Velocity { input, recorded } => {
+                write!(f, "input velocity {input:?}, recorded {recorded:?}")
+            }
+            MismatchKind::UnknownBody => write!(f, "unknown body"),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub frames: usize,
+    pub inputs: usize,
+    /// The largest distance between a predicted and a recorded position, in meters.
+    pub max_error: f32,
+    pub mismatches: Vec<Mismatch>,
+}
+
+/// Re-simulates the replay, reporting every prediction which is off by more than `tolerance`
+/// meters, or meters per second for velocities.
+pub fn verify(replay: &Replay, tolerance: f32) -> Report {
+    let mut report = Report::default();
+    let mut world = PhysicsWorld::default();
+    let mut bodies = HashMap::<u32, (RigidBodyHandle, Shape)>::new();
+    let mut recorded = HashMap::<u32, BodyState>::new();
+    let mut player_speed = replay.header.player_speed;
+    let mut last_tick = None;
+
+    for frame in replay.frames.iter() {
+        report.frames += 1;
+
+        // Step through the ticks since the last frame, including any the server skipped
+        if let Some(last_tick) = last_tick {
+            for _ in 0..frame.tick.wrapping_sub(last_tick) {
+                world.step();
+            }
+        }
+        last_tick = Some(frame.tick);
+
+        for event in frame.events.iter() {
+            match event {
+                Event::Spawn { body, shape, state } => {
+                    if let Some((handle, _)) = bodies.remove(body) {
+                        world.remove(handle);
+                    }
+                    bodies.insert(*body, (insert(&mut world, *shape, state), *shape));
+                    recorded.insert(*body, *state);
+                }
+                Event::State { body, state } => {
+                    recorded.insert(*body, *state);
+                }
+                Event::PlayerSpeed(speed) => player_speed = *speed,
+                Event::Player { .. }
+                | Event::Despawn { .. }
+                | Event::Input { .. }
+                | Event::Target { .. }
+                | Event::Kill { .. }
+                | Event::Appearance { .. } => {}
+            }
+        }
+
+        // Compare the predictions with the recording, then resync to it
+        for (body, (handle, shape)) in bodies.iter() {
+            let Some(state) = recorded.get(body) else { continue; };
+            let Some(rb) = world.get_rigid_body_mut(*handle) else { continue; };
+
+            if *shape == Shape::Character {
+                let predicted = [rb.translation().x, rb.translation().y];
+                let error = (vector![state.x, state.y] - rb.translation()).norm();
+                report.max_error = report.max_error.max(error);
+                if error > tolerance {
+                    report.mismatches.push(Mismatch {
+                        tick: frame.tick,
+                        body: *body,
+                        kind: MismatchKind::Position {
+                            predicted,
+                            recorded: [state.x, state.y],
+                        },
+                    });
+                }
+            }
+
+            rb.set_translation(vector![state.x, state.y], true);
+            rb.set_linvel(vector![state.linvel_x, state.linvel_y], true);
+        }
+
+        for event in frame.events.iter() {
+            let Event::Input {
+                body,
+                x_axis,
+                y_axis,
+                ..
+            } = event
+            else {
+                continue;
+            };
+            report.inputs += 1;
+
+            let Some(rb) = bodies
+                .get(body)
+                .and_then(|(handle, _)| world.get_rigid_body_mut(*handle))
+            else {
+                report.mismatches.push(Mismatch {
+                    tick: frame.tick,
+                    body: *body,
+                    kind: MismatchKind::UnknownBody,
+                });
+                continue;
+            };
+
+            let velocity = [x_axis * player_speed, y_axis * player_speed];
+            rb.set_linvel(vector![velocity[0], velocity[1]], true);
+
+            let Some(state) = recorded.get(body) else { continue; };
+            let error = (vector![state.linvel_x, state.linvel_y] - rb.linvel()).norm();
+            if error > tolerance {
+                report.mismatches.push(Mismatch {
+                    tick: frame.tick,
+                    body: *body,
+                    kind: MismatchKind::Velocity {
+                        input: velocity,
+                        recorded: [state.linvel_x, state.linvel_y],
+                    },
+                });
+            }
+        }
+
+        // Bodies killed this tick still took their last input. Corpses are left out from then on,
+        // as the server takes them out of its physics until they are despawned
+        for event in frame.events.iter() {
+            let (Event::Despawn { body } | Event::Kill { victim: body, .. }) = event else {
+                continue;
+            };
+            if let Some((handle, _)) = bodies.remove(body) {
+                world.remove(handle);
+            }
+            recorded.remove(body);
+        }
+    }
+
+    report
+}
+
+/// Adds a body built like the server builds it.
+fn insert(world: &mut PhysicsWorld, shape: Shape, state: &BodyState) -> RigidBodyHandle {
+    let translation = vector![state.x, state.y];
+    let (rb, cl) = match shape {
+        Shape::Character => (
+            RigidBodyBuilder::dynamic()
+                .translation(translation)
+                .linvel(vector![state.linvel_x, state.linvel_y])
+                .linear_damping(1.0)
+                .angular_damping(1.0)
+                .build(),
+            ColliderBuilder::cuboid(CHARACTER_HALF_SIZE.x, CHARACTER_HALF_SIZE.y)
+                .restitution(0.2)
+                .build(),
+        ),
+        Shape::Wall {
+            half_width,
+            half_height,
+        } => (
+            RigidBodyBuilder::fixed().translation(translation).build(),
+            ColliderBuilder::cuboid(half_width, half_height).build(),
+        ),
+    };
+
+    world.insert(rb, cl).0
+}
@@ -0,0 +1,23 @@
+//! The units the game is measured in. Physics, navigation and the network work in meters, while
+//! the client draws in pixels. Every conversion between the two goes through here, along with the
+//! size of each kind of body, so that rendering keeps matching the colliders when either changes.
+
+use std::ops::{Div, Mul};
+
+use bevy::prelude::Vec2;
+
+/// How many pixels a meter is drawn as at normal zoom.
+pub const PIXELS_PER_METER: f32 = 100.0;
+
+/// Half of a character's width and height, in meters.
+pub const CHARACTER_HALF_SIZE: Vec2 = Vec2::new(0.5, 0.5);
+
+/// Converts a length, position or velocity in meters to pixels.
+pub fn to_pixels<T: Mul<f32, Output = T>>(meters: T) -> T {
+    meters * PIXELS_PER_METER
+}
+
+/// Converts a length, position or velocity in pixels to meters.
+pub fn to_meters<T: Div<f32, Output = T>>(pixels: T) -> T {
+    pixels / PIXELS_PER_METER
+}
@@ -0,0 +1,193 @@
+use std::{env, fs, process};
+
+use shared::{
+    components::Look,
+    replay::{
+        verify::{verify, MismatchKind},
+        BodyState, Event, Frame, Header, Replay, ReplayWriter, Shape,
+    },
+};
+
+fn header() -> Header {
+    Header {
+        room: "main".to_owned(),
+        map: "open".to_owned(),
+        started: 1_700_000_000,
+        player_speed: 2.0,
+    }
+}
+
+/// Writes the frames to a replay file unique to this test process and reads them back.
+fn round_trip(name: &str, frames: &[Frame], cut: usize) -> Replay {
+    let mut bytes = Vec::new();
+    let mut writer = ReplayWriter::new(&mut bytes, &header()).unwrap();
+    for frame in frames {
+        writer.write_frame(frame).unwrap();
+    }
+    bytes.truncate(bytes.len() - cut);
+
+    let path = env::temp_dir().join(format!("assassin-{}-{name}.replay", process::id()));
+    fs::write(&path, bytes).unwrap();
+    let replay = Replay::load(&path).unwrap();
+    fs::remove_file(path).unwrap();
+    replay
+}
+
+fn still(x: f32, y: f32) -> BodyState {
+    BodyState {
+        x,
+        y,
+        ..Default::default()
+    }
+}
+
+fn match_frames() -> Vec<Frame> {
+    vec![
+        Frame {
+            tick: 65534,
+            events: vec![
+                Event::Player {
+                    user: 0,
+                    name: "alice".to_owned(),
+                },
+                Event::Spawn {
+                    body: 1,
+                    shape: Shape::Wall {
+                        half_width: 1.0,
+                        half_height: 4.0,
+                    },
+                    state: still(10.0, 0.0),
+                },
+                Event::Spawn {
+                    body: 2,
+                    shape: Shape::Character,
+                    state: still(0.0, 0.0),
+                },
+                Event::Appearance {
+                    body: 2,
+                    look: Look {
+                        outfit: 3,
+                        palette: 5,
+                    },
+                },
+                Event::Target {
+                    hunter: 0,
+                    target: None,
+                },
+            ],
+        },
+        // Across the tick wrap-around, with a skipped tick
+        Frame {
+            tick: 1,
+            events: vec![
+                Event::Input {
+                    user: 0,
+                    body: 2,
+                    x_axis: 0.0,
+                    y_axis: 0.0,
+                    attack: true,
+                },
+                Event::Kill {
+                    attacker: 0,
+                    victim: 2,
+                },
+                Event::Despawn { body: 2 },
+                Event::PlayerSpeed(1.5),
+            ],
+        },
+    ]
+}
+
+#[test]
+fn frames_survive_a_round_trip() {
+    let frames = match_frames();
+    let replay = round_trip("round-trip", &frames, 0);
+
+    assert_eq!(replay.header, header());
+    assert_eq!(replay.frames, frames);
+    assert!(!replay.truncated);
+
+    let truncated = round_trip("truncated", &frames, 3);
+    assert_eq!(truncated.frames, frames[..1]);
+    assert!(truncated.truncated);
+}
+
+#[test]
+fn consistent_replays_verify() {
+    let replay = round_trip("consistent", &match_frames(), 0);
+    let report = verify(&replay, 0.05);
+
+    assert_eq!(report.frames, 2);
+    assert_eq!(report.inputs, 1);
+    assert!(report.mismatches.is_empty(), "{:?}", report.mismatches);
+}
+
+#[test]
+fn teleports_and_speed_hacks_are_reported() {
+    let spawn = Event::Spawn {
+        body: 0,
+        shape: Shape::Character,
+        state: still(0.0, 0.0),
+    };
+    let frames = vec![
+        Frame {
+            tick: 10,
+            events: vec![spawn],
+        },
+        Frame {
+            tick: 11,
+            events: vec![Event::State {
+                body: 0,
+                state: still(3.0, 0.0),
+            }],
+        },
+        Frame {
+            tick: 12,
+            events: vec![
+                Event::Input {
+                    user: 5,
+                    body: 0,
+                    x_axis: 1.0,
+                    y_axis: 0.0,
+                    attack: false,
+                },
+                Event::State {
+                    body: 0,
+                    state: BodyState {
+                        x: 3.0,
+                        y: 0.0,
+                        linvel_x: 20.0,
+                        linvel_y: 0.0,
+                    },
+                },
+            ],
+        },
+    ];
+    let report = verify(&round_trip("cheating", &frames, 0), 0.05);
+
+    let kinds = report
+        .mismatches
+        .iter()
+        .map(|mismatch| (mismatch.tick, mismatch.kind))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        [
+            (
+                11,
+                MismatchKind::Position {
+                    predicted: [0.0, 0.0],
+                    recorded: [3.0, 0.0]
+                }
+            ),
+            (
+                12,
+                MismatchKind::Velocity {
+                    input: [2.0, 0.0],
+                    recorded: [20.0, 0.0]
+                }
+            ),
+        ]
+    );
+    assert_eq!(report.max_error, 3.0);
+}